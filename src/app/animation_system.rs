@@ -17,7 +17,7 @@ let mut build_frame = |x, y| {
             pivot: Vec2 { x: 16., y: 16. },
             size: Vec2 { x: 32., y: 32. },
         },
-        1_000_000,
+        Duration::from_secs(1),
     )
 };
 
@@ -39,6 +39,7 @@ use super::{
     renderer::Sprite,
     imgui::ImDraw,
     task_system::Task,
+    time_system::{Clock, Duration},
 };
 
 #[derive(Copy, Clone, Debug, ImDraw)]
@@ -50,6 +51,8 @@ pub struct Animator {
     current_frame: usize,
     current_repetition: Repetitions,
 
+    // Clock used to schedule the frame changes
+    clock: Clock,
     task: Option<Task>,
 }
 
@@ -60,10 +63,27 @@ impl Animator {
             current_animation: 0usize,
             current_frame: 0usize,
             current_repetition: Repetitions::Finite(0),
+            clock: Clock::GAME,
             task: None,
         }
     }
 
+    pub fn with_clock(animation_set: AnimationSet, clock: Clock) -> Self {
+        Self {
+            clock,
+            ..Self::new(animation_set)
+        }
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    // Only affects the next frames scheduled
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
     // @Maybe animators shouldn't be allowed to change the animation set, only the animations
     pub fn change_animation_set<S: GameState>(&mut self, animation_set: AnimationSet, app: &mut App<S>) {
         self.animation_set = animation_set;
@@ -109,17 +129,28 @@ impl Animator {
         self.task.is_some()
     }
 
+    // True if there's no frame change scheduled (never played, stopped or the last frame task
+    // already ran without playing the next frame)
+    pub fn is_finished<S>(&self, app: &App<S>) -> bool {
+        match &self.task {
+            Some(task) => !app.is_task_scheduled(task),
+            None => true,
+        }
+    }
+
     pub fn stop<S>(&mut self, app: &mut App<S>) {
         let mut task = self.task.take().unwrap();
         app.cancel_task(&mut task);
     }
 
+    #[track_caller]
     pub fn play<'a, S: GameState, F>(&mut self, app: &mut App<'a, S>, callback: F)
-    where F: FnMut(u64, &mut S, &mut App<S>) + 'a,
+    where F: FnMut(Task, &mut S, &mut App<S>) + 'a,
     {
         let (_, frame_data) = app.animation_system.get_animation_and_frame(self);
         let duration = frame_data.duration;
-        let task = app.schedule_task(duration, callback);
+        let task = app.schedule_task_on(self.clock, duration, callback);
+        app.set_task_label(&task, "animator");
 
         match self.task.replace(task) {
             None => panic!("[animation] trying to play while already playing"),
@@ -176,7 +207,7 @@ pub struct Frame(u64);
 pub struct FrameData {
     pub(super) id: Frame,
     pub sprite: Sprite,
-    pub duration: u64, // @Refactor create type-safe time/duration struct
+    pub duration: Duration,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -192,7 +223,7 @@ impl ImDraw for Repetitions {
 }
 
 impl<S> App<'_, S> {
-    pub fn build_frame(&mut self, sprite: Sprite, duration: u64) -> Frame {
+    pub fn build_frame(&mut self, sprite: Sprite, duration: Duration) -> Frame {
         let frames = &mut self.animation_system.frames;
 
         let id = frames.len() as u64;
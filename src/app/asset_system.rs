@@ -3,6 +3,7 @@ use std::collections::BTreeMap;
 
 use super::{
     App,
+    audio::Sound,
     renderer::{
         texture::{Texture, load_texture},
     },
@@ -11,12 +12,14 @@ use super::{
 
 pub(super) struct AssetSystem {
     textures: BTreeMap<StringRef, Texture>,
+    sounds: BTreeMap<StringRef, Sound>,
 }
 
 impl AssetSystem {
     pub(super) fn new() -> Self {
         Self {
             textures: BTreeMap::new(),
+            sounds: BTreeMap::new(),
         }
     }
 
@@ -25,6 +28,13 @@ impl AssetSystem {
             .entry(StringRef::new(path.as_ref().display().to_string()))
             .or_insert_with(|| load_texture(path))
     }
+
+    fn get_sound<P: AsRef<Path>>(&mut self, path: P) -> Sound {
+        self.sounds
+            .entry(StringRef::new(path.as_ref().display().to_string()))
+            .or_insert_with(|| Sound::load(path).unwrap_or_else(|e| panic!("[asset_system get_sound] {}", e)))
+            .clone()
+    }
 }
 
 impl<S> App<'_, S> {
@@ -33,4 +43,8 @@ impl<S> App<'_, S> {
     pub fn get_texture<P: AsRef<Path>>(&mut self, path: P) -> Texture {
         self.asset_system.get_texture(path)
     }
+
+    pub fn get_sound<P: AsRef<Path>>(&mut self, path: P) -> Sound {
+        self.asset_system.get_sound(path)
+    }
 }
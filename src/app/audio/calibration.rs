@@ -0,0 +1,404 @@
+// Latency calibration
+//
+// Audio and visual offsets compensate the latencies the engine can't measure: audio drivers and
+// hardware, displays and input devices. Both are in milliseconds, positive when the player is late.
+// The audio offset delays the song clocks (and the judgements, since they use the song time), the
+// visual offset moves the visual song time the chart is drawn with.
+//
+// The calibration plays a metronome on a song clock and collects the taps of a button. In audio
+// mode the player taps to the clicks. In visual mode the clicks are muted and the player taps to a
+// flash the game draws on the beats of the visual song time. The offset is corrected by the median
+// tap error, so a few mistaps don't matter.
+
+/* Format
+
+# comment
+audio_offset = 25      # milliseconds
+visual_offset = -8
+*/
+
+/* Usage
+
+// startup
+match AudioOffsets::load("config/audio.cfg") {
+    Ok(offsets) => app.set_audio_offsets(offsets),
+    Err(e) => println!("{}", e),
+}
+
+// calibration screen
+self.calibration = LatencyCalibration::new(CalibrationMode::Audio, "HIT".to_string());
+app.start_latency_calibration(&mut self.calibration);
+
+// update
+app.update_input_mapping(&mut self.input_mapping);
+app.update_latency_calibration(&mut self.calibration, &self.input_mapping);
+
+// render (visual mode)
+let beat = self.calibration.beat_duration();
+let phase = app.visual_song_time(self.calibration.clock().unwrap()).as_micros() % beat.as_micros();
+if phase < beat.as_micros() / 8 {
+    // draw the flash
+}
+
+if self.calibration.is_done() {
+    if let Some(offsets) = app.finish_latency_calibration(&mut self.calibration) {
+        offsets.save("config/audio.cfg").unwrap();
+    }
+}
+*/
+
+use std::fs;
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+    input::InputMapping,
+    time_system::{Clock, Duration},
+};
+
+use super::{
+    mixer::{AudioBus, VoiceParams},
+    sound::Sound,
+    system::Voice,
+};
+
+const CALIBRATION_CLOCK_NAME: &str = "latency_calibration";
+
+// 120 BPM
+const CALIBRATION_BEAT: Duration = Duration::from_millis(500);
+
+// Taps of the first beats are ignored, while the player gets the rhythm
+const CALIBRATION_WARMUP_BEATS: u64 = 4;
+
+const DEFAULT_CALIBRATION_TAPS: usize = 16;
+
+// Farther from a beat is a mistap. Less than half a beat
+const MAX_TAP_ERROR: Duration = Duration::from_millis(200);
+
+const CLICK_DURATION: Duration = Duration::from_millis(20);
+const CLICK_FREQUENCY: f32 = 1_000.0;
+const CLICK_SAMPLE_RATE: u32 = 48_000;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, ImDraw)]
+pub struct AudioOffsets {
+    pub audio_ms: i32,
+    pub visual_ms: i32,
+}
+
+impl AudioOffsets {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("[audio_offsets load] could not read {}: {}", path, e))?;
+
+        Self::from_config_str(&text)
+            .map_err(|e| format!("[audio_offsets load] {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_config_string())
+            .map_err(|e| format!("[audio_offsets save] could not write {}: {}", path, e))
+    }
+
+    pub fn to_config_string(self) -> String {
+        format!("audio_offset = {}\nvisual_offset = {}\n", self.audio_ms, self.visual_ms)
+    }
+
+    // Missing entries are 0
+    pub fn from_config_str(text: &str) -> Result<Self, String> {
+        let mut offsets = Self::default();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            }.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = match line.find('=') {
+                Some(split) => (line[..split].trim(), line[split + 1..].trim()),
+                None => return Err(format!("line {}: expected 'key = value', found '{}'", line_number, line)),
+            };
+
+            let value = value.parse()
+                .map_err(|_| format!("line {}: invalid number '{}'", line_number, value))?;
+
+            match key {
+                "audio_offset" => offsets.audio_ms = value,
+                "visual_offset" => offsets.visual_ms = value,
+                _ => return Err(format!("line {}: unknown entry '{}'", line_number, key)),
+            }
+        }
+
+        Ok(offsets)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CalibrationMode {
+    // Taps to the metronome clicks
+    Audio,
+    // Taps to flashes drawn by the game, without sound
+    Visual,
+}
+
+impl_imdraw_todo!(CalibrationMode);
+
+#[derive(Clone, Debug, ImDraw)]
+pub struct LatencyCalibration {
+    mode: CalibrationMode,
+    // Button mapping name
+    button: String,
+    taps_needed: usize,
+
+    clock: Option<Clock>,
+    voice: Voice,
+    // Song time minus the nearest beat, in microseconds
+    tap_errors: Vec<i64>,
+}
+
+impl LatencyCalibration {
+    pub fn new(mode: CalibrationMode, button: String) -> Self {
+        Self {
+            mode,
+            button,
+            taps_needed: DEFAULT_CALIBRATION_TAPS,
+            clock: None,
+            voice: Voice::default(),
+            tap_errors: Vec::new(),
+        }
+    }
+
+    pub fn taps_needed(mut self, taps_needed: usize) -> Self {
+        assert!(taps_needed > 0, "[latency_calibration taps_needed] no taps needed");
+        self.taps_needed = taps_needed;
+        self
+    }
+
+    pub fn mode(&self) -> CalibrationMode {
+        self.mode
+    }
+
+    pub fn beat_duration(&self) -> Duration {
+        CALIBRATION_BEAT
+    }
+
+    // Song clock of the metronome, None when not started
+    pub fn clock(&self) -> Option<Clock> {
+        self.clock
+    }
+
+    pub fn tap_count(&self) -> usize {
+        self.tap_errors.len()
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.tap_errors.len() >= self.taps_needed
+    }
+
+    // Returns if the tap was counted
+    fn add_tap(&mut self, song_time: Duration) -> bool {
+        let beat = CALIBRATION_BEAT.as_micros();
+        let beat_index = (song_time.as_micros() + beat / 2) / beat;
+        let error = song_time.as_micros() as i64 - (beat_index * beat) as i64;
+
+        if self.is_done() || beat_index < CALIBRATION_WARMUP_BEATS || error.abs() > MAX_TAP_ERROR.as_micros() as i64 {
+            return false;
+        }
+
+        self.tap_errors.push(error);
+        true
+    }
+
+    // Median tap error, rounded to milliseconds
+    pub fn median_error_ms(&self) -> Option<i32> {
+        if self.tap_errors.is_empty() {
+            return None;
+        }
+
+        let mut errors = self.tap_errors.clone();
+        errors.sort_unstable();
+
+        let middle = errors.len() / 2;
+        let median = if errors.len().is_multiple_of(2) {
+            (errors[middle - 1] + errors[middle]) as f64 / 2.0
+        } else {
+            errors[middle] as f64
+        };
+
+        Some((median / 1_000.0).round() as i32)
+    }
+
+    // The errors were measured with the current offsets applied
+    fn corrected_offsets(&self, offsets: AudioOffsets) -> Option<AudioOffsets> {
+        if !self.is_done() {
+            return None;
+        }
+
+        let error = self.median_error_ms()?;
+        let mut offsets = offsets;
+        match self.mode {
+            CalibrationMode::Audio => offsets.audio_ms += error,
+            CalibrationMode::Visual => offsets.visual_ms += error,
+        }
+
+        Some(offsets)
+    }
+}
+
+// Short decaying sine at the start of a beat of silence, to loop
+fn metronome_sound(beat: Duration) -> Sound {
+    let frame_count = (beat.as_micros() * CLICK_SAMPLE_RATE as u64 / 1_000_000) as usize;
+    let click_frames = (CLICK_DURATION.as_micros() * CLICK_SAMPLE_RATE as u64 / 1_000_000) as usize;
+
+    let frames = (0..frame_count)
+        .map(|frame| {
+            if frame >= click_frames {
+                return [0.0, 0.0];
+            }
+
+            let t = frame as f32 / CLICK_SAMPLE_RATE as f32;
+            let envelope = 1.0 - frame as f32 / click_frames as f32;
+            let sample = (t * CLICK_FREQUENCY * std::f32::consts::TAU).sin() * envelope;
+            [sample, sample]
+        })
+        .collect();
+
+    Sound::from_frames(frames, CLICK_SAMPLE_RATE)
+}
+
+impl<S> App<'_, S> {
+    pub fn audio_offsets(&self) -> AudioOffsets {
+        self.audio_system.offsets
+    }
+
+    pub fn set_audio_offsets(&mut self, offsets: AudioOffsets) {
+        self.audio_system.offsets = offsets;
+    }
+
+    // Starts the metronome, discarding the taps of a previous run
+    pub fn start_latency_calibration(&mut self, calibration: &mut LatencyCalibration) {
+        self.stop_voice(calibration.voice);
+
+        let volume = match calibration.mode {
+            CalibrationMode::Audio => 1.0,
+            CalibrationMode::Visual => 0.0,
+        };
+        let params = VoiceParams::new(AudioBus::Ui).volume(volume).looping(true);
+        let voice = self.play_sound(&metronome_sound(CALIBRATION_BEAT), params);
+
+        // The clock can't be removed, it's reused by the next calibrations
+        let clock = match self.find_clock(CALIBRATION_CLOCK_NAME) {
+            Some(clock) => {
+                self.set_song_clock_voice(clock, voice);
+                clock
+            },
+            None => self.create_song_clock(CALIBRATION_CLOCK_NAME, voice),
+        };
+
+        calibration.clock = Some(clock);
+        calibration.voice = voice;
+        calibration.tap_errors.clear();
+    }
+
+    // After updating the input mapping
+    pub fn update_latency_calibration(&mut self, calibration: &mut LatencyCalibration, mapping: &InputMapping) {
+        let clock = match calibration.clock {
+            Some(clock) => clock,
+            None => return,
+        };
+
+        let button = mapping.button(calibration.button.clone());
+        if button.pressed() {
+            let song_time = self.song_time_at(clock, button.timestamp());
+            calibration.add_tap(song_time);
+        }
+    }
+
+    // Stops the metronome. If enough taps were collected, the corrected offsets are applied and
+    // returned (to save them)
+    pub fn finish_latency_calibration(&mut self, calibration: &mut LatencyCalibration) -> Option<AudioOffsets> {
+        self.stop_voice(calibration.voice);
+        calibration.voice = Voice::default();
+        calibration.clock = None;
+
+        let offsets = calibration.corrected_offsets(self.audio_offsets())?;
+        self.set_audio_offsets(offsets);
+        Some(offsets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_latency_calibration_taps() {
+        let mut calibration = LatencyCalibration::new(CalibrationMode::Audio, "HIT".to_string()).taps_needed(5);
+
+        // warmup
+        assert!(!calibration.add_tap(ms(1_530)));
+        // mistap, between beats
+        assert!(!calibration.add_tap(ms(2_250)));
+
+        assert!(calibration.add_tap(ms(2_030)));
+        assert!(calibration.add_tap(ms(2_520)));
+        assert!(calibration.add_tap(ms(2_980)));
+        assert!(calibration.add_tap(ms(3_540)));
+        assert_eq!(calibration.corrected_offsets(AudioOffsets::default()), None);
+
+        assert!(calibration.add_tap(ms(4_190)));
+        assert!(calibration.is_done());
+        assert!(!calibration.add_tap(ms(4_510)));
+
+        // -20, 20, 30, 40, 190
+        assert_eq!(calibration.median_error_ms(), Some(30));
+        assert_eq!(
+            calibration.corrected_offsets(AudioOffsets { audio_ms: 10, visual_ms: 5 }),
+            Some(AudioOffsets { audio_ms: 40, visual_ms: 5 })
+        );
+
+        let mut calibration = LatencyCalibration::new(CalibrationMode::Visual, "HIT".to_string()).taps_needed(2);
+        calibration.add_tap(ms(2_000) - Duration::from_micros(12_400));
+        calibration.add_tap(ms(2_490));
+        assert_eq!(
+            calibration.corrected_offsets(AudioOffsets { audio_ms: 10, visual_ms: 5 }),
+            Some(AudioOffsets { audio_ms: 10, visual_ms: -6 })
+        );
+    }
+
+    #[test]
+    fn test_audio_offsets_config() {
+        let offsets = AudioOffsets { audio_ms: 25, visual_ms: -8 };
+        let text = offsets.to_config_string();
+        assert_eq!(AudioOffsets::from_config_str(&text), Ok(offsets));
+
+        assert_eq!(
+            AudioOffsets::from_config_str("# offsets\nvisual_offset = 3 # ms\n"),
+            Ok(AudioOffsets { audio_ms: 0, visual_ms: 3 })
+        );
+        assert_eq!(
+            AudioOffsets::from_config_str("audio_offset = 1.5\n"),
+            Err("line 1: invalid number '1.5'".to_string())
+        );
+        assert_eq!(
+            AudioOffsets::from_config_str("\ninput_offset = 1\n"),
+            Err("line 2: unknown entry 'input_offset'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_metronome_sound() {
+        let sound = metronome_sound(CALIBRATION_BEAT);
+        assert_eq!(sound.duration(), CALIBRATION_BEAT);
+        assert!(sound.frames()[..960].iter().any(|frame| frame[0].abs() > 0.5));
+        assert!(sound.frames()[960..].iter().all(|frame| *frame == [0.0, 0.0]));
+    }
+}
@@ -0,0 +1,441 @@
+// Software mixer
+//
+// Mixes the playing voices into the interleaved stereo output. Every voice plays a sound (decoded
+// in memory) or a stream (a buffer topped up by the audio system), resampled to the output rate
+// with linear interpolation. The mixer runs in the audio callback, so the audio system only
+// touches it with the device locked.
+
+use std::collections::VecDeque;
+
+use crate::app::{
+    imdraw::ImDraw,
+    time_system::{Duration, RealTime},
+};
+
+use super::sound::Sound;
+
+// Stereo
+pub(super) type Frame = [f32; 2];
+
+pub(super) const MAX_VOICES: usize = 32;
+
+const AUDIO_BUS_COUNT: usize = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AudioBus {
+    Music,
+    Sfx,
+    Ui,
+}
+
+impl_imdraw_todo!(AudioBus);
+
+#[derive(Copy, Clone, Debug, PartialEq, ImDraw)]
+pub struct VoiceParams {
+    pub bus: AudioBus,
+    pub volume: f32,
+    // -1 (left) to 1 (right)
+    pub pan: f32,
+    // Playback rate, also changes the tempo
+    pub pitch: f32,
+    pub looping: bool,
+}
+
+impl Default for VoiceParams {
+    fn default() -> Self {
+        Self {
+            bus: AudioBus::Sfx,
+            volume: 1.0,
+            pan: 0.0,
+            pitch: 1.0,
+            looping: false,
+        }
+    }
+}
+
+impl VoiceParams {
+    pub fn new(bus: AudioBus) -> Self {
+        Self { bus, ..Self::default() }
+    }
+
+    pub fn volume(mut self, volume: f32) -> Self {
+        self.volume = volume;
+        self
+    }
+
+    pub fn pan(mut self, pan: f32) -> Self {
+        self.pan = pan;
+        self
+    }
+
+    pub fn pitch(mut self, pitch: f32) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    fn channel_gains(&self) -> Frame {
+        let pan = self.pan.clamp(-1.0, 1.0);
+        [self.volume * (1.0 - pan).min(1.0), self.volume * (1.0 + pan).min(1.0)]
+    }
+}
+
+pub(super) struct StreamBuffer {
+    frames: VecDeque<Frame>,
+    sample_rate: u32,
+    // No more frames will be pushed
+    end_of_stream: bool,
+}
+
+impl StreamBuffer {
+    pub(super) fn new(sample_rate: u32) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            sample_rate,
+            end_of_stream: false,
+        }
+    }
+}
+
+// Source position of a voice when the last buffer was mixed, to estimate the playing position
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(super) struct VoiceTiming {
+    // At the start of the buffer
+    pub(super) position: Duration,
+    pub(super) mix_time: RealTime,
+    // Source time per real time, 0 while paused
+    pub(super) rate: f64,
+}
+
+pub(super) enum VoiceSource {
+    Sound(Sound),
+    // Streams loop by rewinding the decoder, not in the mixer
+    Stream(StreamBuffer),
+}
+
+struct MixerVoice {
+    id: u64,
+    source: VoiceSource,
+    params: VoiceParams,
+    paused: bool,
+    finished: bool,
+
+    // Fractional source frame. Streams drop the mixed frames, so it's relative to the buffer front
+    position: f64,
+    // Stream frames dropped
+    consumed: u64,
+
+    // Source frame at the start of the last buffer mixed, and when. None before the first buffer
+    last_mix: Option<(f64, RealTime)>,
+}
+
+impl MixerVoice {
+    fn sample_rate(&self) -> u32 {
+        match &self.source {
+            VoiceSource::Sound(sound) => sound.sample_rate(),
+            VoiceSource::Stream(stream) => stream.sample_rate,
+        }
+    }
+
+    fn frame(&self, index: usize) -> Option<Frame> {
+        match &self.source {
+            VoiceSource::Sound(sound) => {
+                let frames = sound.frames();
+                if self.params.looping && !frames.is_empty() {
+                    Some(frames[index % frames.len()])
+                } else {
+                    frames.get(index).copied()
+                }
+            },
+            VoiceSource::Stream(stream) => stream.frames.get(index).copied(),
+        }
+    }
+
+    // Running out of frames finishes the voice (instead of waiting for the stream)
+    fn is_ended(&self) -> bool {
+        match &self.source {
+            VoiceSource::Sound(sound) => !self.params.looping || sound.frames().is_empty(),
+            VoiceSource::Stream(stream) => stream.end_of_stream,
+        }
+    }
+
+    fn source_frame(&self) -> f64 {
+        self.consumed as f64 + self.position
+    }
+
+    fn frames_to_duration(&self, frames: f64) -> Duration {
+        Duration::from_micros((frames * 1_000_000.0 / self.sample_rate() as f64).round() as u64)
+    }
+
+    fn mix(&mut self, out: &mut [f32], output_rate: u32, gain: f32) {
+        let gains = self.params.channel_gains();
+        let step = self.sample_rate() as f64 / output_rate as f64 * self.params.pitch.max(0.0) as f64;
+
+        for out_frame in out.chunks_exact_mut(2) {
+            let index = self.position as usize;
+            let current = match self.frame(index) {
+                Some(frame) => frame,
+                None => {
+                    // Otherwise the stream is late (underrun) and the voice waits for it
+                    self.finished = self.is_ended();
+                    break;
+                },
+            };
+            let next = self.frame(index + 1).unwrap_or(current);
+            let t = (self.position - index as f64) as f32;
+
+            for channel in 0..2 {
+                let sample = current[channel] + (next[channel] - current[channel]) * t;
+                out_frame[channel] += sample * gains[channel] * gain;
+            }
+
+            self.position += step;
+        }
+
+        match &mut self.source {
+            VoiceSource::Sound(sound) => {
+                let frames = sound.frames().len() as f64;
+                if self.params.looping && frames > 0.0 {
+                    self.position %= frames;
+                }
+            },
+            VoiceSource::Stream(stream) => {
+                let mixed = (self.position as usize).min(stream.frames.len());
+                stream.frames.drain(..mixed);
+                self.position -= mixed as f64;
+                self.consumed += mixed as u64;
+            },
+        }
+    }
+}
+
+pub(super) struct Mixer {
+    sample_rate: u32,
+    voices: Vec<MixerVoice>,
+    next_voice_id: u64,
+
+    bus_volumes: [f32; AUDIO_BUS_COUNT],
+    master_volume: f32,
+}
+
+impl Mixer {
+    pub(super) fn new(sample_rate: u32) -> Self {
+        assert!(sample_rate > 0, "[mixer new] invalid sample rate");
+
+        Self {
+            sample_rate,
+            voices: Vec::new(),
+            next_voice_id: 0,
+            bus_volumes: [1.0; AUDIO_BUS_COUNT],
+            master_volume: 1.0,
+        }
+    }
+
+    pub(super) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // None if all the voices are playing
+    pub(super) fn add_voice(&mut self, source: VoiceSource, params: VoiceParams) -> Option<u64> {
+        if self.voices.len() >= MAX_VOICES {
+            return None;
+        }
+
+        let id = self.next_voice_id;
+        self.next_voice_id += 1;
+
+        self.voices.push(MixerVoice {
+            id,
+            source,
+            params,
+            paused: false,
+            finished: false,
+            position: 0.0,
+            consumed: 0,
+            last_mix: None,
+        });
+
+        Some(id)
+    }
+
+    fn voice(&self, id: u64) -> Option<&MixerVoice> {
+        self.voices.iter().find(|voice| voice.id == id && !voice.finished)
+    }
+
+    fn voice_mut(&mut self, id: u64) -> Option<&mut MixerVoice> {
+        self.voices.iter_mut().find(|voice| voice.id == id && !voice.finished)
+    }
+
+    pub(super) fn remove_voice(&mut self, id: u64) {
+        self.voices.retain(|voice| voice.id != id);
+    }
+
+    pub(super) fn is_playing(&self, id: u64) -> bool {
+        self.voice(id).is_some()
+    }
+
+    pub(super) fn voice_params_mut(&mut self, id: u64) -> Option<&mut VoiceParams> {
+        self.voice_mut(id).map(|voice| &mut voice.params)
+    }
+
+    pub(super) fn set_paused(&mut self, id: u64, paused: bool) {
+        if let Some(voice) = self.voice_mut(id) {
+            voice.paused = paused;
+        }
+    }
+
+    pub(super) fn is_paused(&self, id: u64) -> bool {
+        self.voice(id).is_some_and(|voice| voice.paused)
+    }
+
+    // Source time played. Looping sounds wrap, looping streams don't
+    pub(super) fn voice_position(&self, id: u64) -> Option<Duration> {
+        self.voice(id).map(|voice| voice.frames_to_duration(voice.source_frame()))
+    }
+
+    pub(super) fn voice_timing(&self, id: u64) -> Option<VoiceTiming> {
+        let voice = self.voice(id)?;
+        let (frame, mix_time) = voice.last_mix?;
+
+        Some(VoiceTiming {
+            position: voice.frames_to_duration(frame),
+            mix_time,
+            rate: if voice.paused { 0.0 } else { voice.params.pitch.max(0.0) as f64 },
+        })
+    }
+
+    // Frames buffered for a stream voice
+    pub(super) fn stream_buffered(&self, id: u64) -> Option<usize> {
+        match self.voice(id).map(|voice| &voice.source) {
+            Some(VoiceSource::Stream(stream)) => Some(stream.frames.len()),
+            _ => None,
+        }
+    }
+
+    pub(super) fn push_stream(&mut self, id: u64, frames: &[Frame], end_of_stream: bool) {
+        if let Some(VoiceSource::Stream(stream)) = self.voice_mut(id).map(|voice| &mut voice.source) {
+            stream.frames.extend(frames.iter().copied());
+            stream.end_of_stream |= end_of_stream;
+        }
+    }
+
+    pub(super) fn bus_volume(&self, bus: AudioBus) -> f32 {
+        self.bus_volumes[bus as usize]
+    }
+
+    pub(super) fn set_bus_volume(&mut self, bus: AudioBus, volume: f32) {
+        self.bus_volumes[bus as usize] = volume.max(0.0);
+    }
+
+    pub(super) fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    pub(super) fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.max(0.0);
+    }
+
+    // Interleaved stereo. now is when the buffer starts (its real time)
+    pub(super) fn mix(&mut self, out: &mut [f32], now: RealTime) {
+        for sample in out.iter_mut() {
+            *sample = 0.0;
+        }
+
+        for voice in self.voices.iter_mut() {
+            voice.last_mix = Some((voice.source_frame(), now));
+            if voice.paused {
+                continue;
+            }
+
+            let gain = self.bus_volumes[voice.params.bus as usize] * self.master_volume;
+            voice.mix(out, self.sample_rate, gain);
+        }
+
+        self.voices.retain(|voice| !voice.finished);
+
+        for sample in out.iter_mut() {
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant_sound(value: f32, frames: usize, sample_rate: u32) -> Sound {
+        Sound::from_frames(vec![[value, value]; frames], sample_rate)
+    }
+
+    #[test]
+    fn test_mixer_volume_and_pan() {
+        let mut mixer = Mixer::new(100);
+        mixer.add_voice(VoiceSource::Sound(constant_sound(0.5, 4, 100)), VoiceParams::default().pan(-1.0));
+        let music = mixer.add_voice(
+            VoiceSource::Sound(constant_sound(0.5, 2, 100)),
+            VoiceParams::new(AudioBus::Music).volume(0.5),
+        ).unwrap();
+        mixer.set_bus_volume(AudioBus::Music, 0.5);
+
+        let mut out = [1.0; 6];
+        mixer.mix(&mut out, RealTime::default());
+        assert_eq!(out, [0.625, 0.125, 0.625, 0.125, 0.5, 0.0]);
+
+        // the music finished, the sfx didn't
+        assert!(!mixer.is_playing(music));
+        assert_eq!(mixer.voices.len(), 1);
+
+        mixer.set_master_volume(0.0);
+        mixer.mix(&mut out, RealTime::default());
+        assert_eq!(out, [0.0; 6]);
+        assert!(mixer.voices.is_empty());
+    }
+
+    #[test]
+    fn test_mixer_pitch_and_looping() {
+        let sound = Sound::from_frames(vec![[0.0, 0.0], [1.0, 1.0]], 100);
+
+        // half the output rate: every source frame is interpolated once
+        let mut mixer = Mixer::new(200);
+        let voice = mixer.add_voice(VoiceSource::Sound(sound.clone()), VoiceParams::default()).unwrap();
+        let mut out = [0.0; 10];
+        mixer.mix(&mut out, RealTime::default());
+        assert_eq!(out, [0.0, 0.0, 0.5, 0.5, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0]);
+        assert!(!mixer.is_playing(voice));
+
+        let voice = mixer.add_voice(VoiceSource::Sound(sound), VoiceParams::default().pitch(4.0).looping(true));
+        let voice = voice.unwrap();
+        mixer.mix(&mut out, RealTime::default());
+        assert_eq!(out, [0.0; 10]);
+        assert!(mixer.is_playing(voice));
+        assert_eq!(mixer.voice_position(voice), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_mixer_stream() {
+        let mut mixer = Mixer::new(100);
+        let voice = mixer.add_voice(VoiceSource::Stream(StreamBuffer::new(100)), VoiceParams::default()).unwrap();
+        mixer.push_stream(voice, &[[0.25, 0.25]; 3], false);
+
+        // underrun: waits for more frames
+        let mut out = [0.0; 8];
+        mixer.mix(&mut out, RealTime::default());
+        assert_eq!(out, [0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.0, 0.0]);
+        assert!(mixer.is_playing(voice));
+        assert_eq!(mixer.stream_buffered(voice), Some(0));
+        assert_eq!(mixer.voice_position(voice), Some(Duration::from_millis(30)));
+        assert_eq!(
+            mixer.voice_timing(voice),
+            Some(VoiceTiming { position: Duration::ZERO, mix_time: RealTime::default(), rate: 1.0 })
+        );
+
+        mixer.push_stream(voice, &[[0.5, 0.5]], true);
+        mixer.mix(&mut out, RealTime::default());
+        assert_eq!(out[..2], [0.5, 0.5]);
+        assert!(!mixer.is_playing(voice));
+    }
+}
@@ -0,0 +1,47 @@
+// Audio
+//
+// Software mixer playing sounds (decoded in memory, for sound effects) and streams (decoded while
+// playing, for music) through the SDL audio device. Every voice has its own volume, pan and pitch
+// and plays on a bus (music, sfx, ui) with its own volume.
+//
+// Song clocks follow the playing position of a voice (see song_clock.rs), with the latency offsets
+// calibrated by the player (see calibration.rs).
+//
+// WAV and Ogg Vorbis files are supported.
+
+/* Usage
+
+// construction
+
+let hit_sound = app.get_sound("assets/sounds/hit.wav");
+app.set_bus_volume(AudioBus::Music, 0.8);
+
+// song start
+
+self.music = app.play_music("assets/songs/song.wav", VoiceParams::new(AudioBus::Music))?;
+
+// update
+
+if hit {
+    app.play_sound(&hit_sound, VoiceParams::new(AudioBus::Sfx).pan(lane_pan));
+}
+
+if paused {
+    app.pause_voice(self.music);
+}
+
+if !app.is_voice_playing(self.music) {
+    // song finished
+}
+*/
+
+pub mod calibration;
+pub mod mixer;
+pub mod song_clock;
+pub mod sound;
+pub mod system;
+mod vorbis;
+mod wav;
+
+pub use sound::Sound;
+pub(in crate::app) use system::AudioSystem;
@@ -0,0 +1,286 @@
+// Song clocks
+//
+// Clocks driven by the playing position of a voice instead of the real time, so everything on
+// them (tasks, animators, the chart) stays in sync with the music. The position is estimated from
+// the last buffer the mixer mixed (extrapolated to the frame start and compensated by the output
+// latency and the calibrated audio offset) and smoothed: small differences are corrected a bit
+// every frame, big ones (the audio skipped) immediately. The time never goes back.
+//
+// The clock time starts where the clock was when the voice was bound, so binding the next song
+// doesn't rewind the clock. The song time is the position in the song.
+//
+// Scaling or pausing a song clock doesn't do anything: pause or pitch the voice instead.
+
+/* Usage
+
+// song start
+let music = app.play_music("assets/songs/song.wav", VoiceParams::new(AudioBus::Music))?;
+self.song_clock = app.create_song_clock("song", music);
+
+// next song
+app.set_song_clock_voice(self.song_clock, next_music);
+
+// update
+let song_time = app.song_time(self.song_clock);
+for note in self.chart.notes_between(last_song_time, song_time) {
+    // ...
+}
+
+app.schedule_task_on(self.song_clock, beat_duration, |_, _, _| {});
+*/
+
+use crate::app::{
+    App,
+    time_system::{Clock, Duration, GameTime, RealTime},
+};
+
+use super::{
+    mixer::VoiceTiming,
+    system::Voice,
+};
+
+// Above this difference the clock jumps to the audio
+const SONG_CLOCK_RESYNC_THRESHOLD: Duration = Duration::from_millis(50);
+
+// Fraction of the difference corrected every frame
+const SONG_CLOCK_CORRECTION: f64 = 0.1;
+
+// The mixer timing is not extrapolated further (the device stopped calling back)
+const SONG_CLOCK_MAX_EXTRAPOLATION: Duration = Duration::from_millis(100);
+
+pub(in crate::app) struct SongClockState {
+    pub(super) clock: Clock,
+    voice: Voice,
+    // Clock time at song time 0
+    origin: GameTime,
+
+    // Smoothed song time, and the real time of the last update (None until the voice is mixed)
+    time: Duration,
+    last_update: Option<RealTime>,
+    // Song time per real time
+    rate: f64,
+}
+
+// Song time being heard at now, in microseconds (negative before the song is heard). The latency
+// is in microseconds, negative with offsets calibrated for audio heard early
+fn estimate_song_time(timing: &VoiceTiming, latency: i64, now: RealTime) -> i64 {
+    let since_mix = now.as_micros() as i64 - timing.mix_time.as_micros() as i64;
+    let since_mix = since_mix.min(SONG_CLOCK_MAX_EXTRAPOLATION.as_micros() as i64);
+
+    timing.position.as_micros() as i64 + (since_mix as f64 * timing.rate) as i64 - latency
+}
+
+impl SongClockState {
+    fn new(clock: Clock, voice: Voice, origin: GameTime) -> Self {
+        Self {
+            clock,
+            voice,
+            origin,
+            time: Duration::ZERO,
+            last_update: None,
+            rate: 1.0,
+        }
+    }
+
+    pub(super) fn time(&self) -> Duration {
+        self.time
+    }
+
+    // Song time real_age before the last update
+    fn time_before(&self, real_age: Duration) -> Duration {
+        self.time.saturating_sub(real_age.mul_f64(self.rate))
+    }
+
+    pub(super) fn update(&mut self, timing: Option<VoiceTiming>, latency: i64, now: RealTime) {
+        // Not mixed yet or finished: the time holds
+        let timing = match timing {
+            Some(timing) => timing,
+            None => {
+                self.last_update = None;
+                return;
+            },
+        };
+
+        let time = self.time.as_micros() as i64;
+        let target = estimate_song_time(&timing, latency, now);
+
+        let new_time = match self.last_update {
+            Some(last_update) => {
+                let predicted = time + (now.duration_since(last_update).as_micros() as f64 * timing.rate) as i64;
+                let error = target - predicted;
+
+                if error.abs() > SONG_CLOCK_RESYNC_THRESHOLD.as_micros() as i64 {
+                    target
+                } else {
+                    predicted + (error as f64 * SONG_CLOCK_CORRECTION).round() as i64
+                }
+            },
+            None => target,
+        };
+
+        self.time = Duration::from_micros(new_time.max(time) as u64);
+        self.last_update = Some(now);
+        self.rate = timing.rate;
+    }
+}
+
+impl<S> App<'_, S> {
+    pub fn create_song_clock(&mut self, name: &str, voice: Voice) -> Clock {
+        let clock = self.create_clock(name);
+        let origin = self.clock_time(clock);
+        self.audio_system.song_clocks.push(SongClockState::new(clock, voice, origin));
+
+        clock
+    }
+
+    // The song time starts again from 0, the clock time continues
+    pub fn set_song_clock_voice(&mut self, clock: Clock, voice: Voice) {
+        let origin = self.clock_time(clock);
+        let song_clock = self.audio_system.song_clocks.iter_mut()
+            .find(|song_clock| song_clock.clock == clock)
+            .expect("[audio_system set_song_clock_voice] not a song clock");
+
+        *song_clock = SongClockState::new(clock, voice, origin);
+    }
+
+    pub fn is_song_clock(&self, clock: Clock) -> bool {
+        self.audio_system.song_clocks.iter().any(|song_clock| song_clock.clock == clock)
+    }
+
+    // Position in the song being heard
+    pub fn song_time(&self, clock: Clock) -> Duration {
+        self.audio_system.song_clocks.iter()
+            .find(|song_clock| song_clock.clock == clock)
+            .map(SongClockState::time)
+            .expect("[audio_system song_time] not a song clock")
+    }
+
+    // Song time of an input timestamp (on the game clock), for judgements. The song rate is per real
+    // time, so the timestamp age is converted from the (maybe scaled or paused) game clock
+    pub fn song_time_at(&self, clock: Clock, timestamp: GameTime) -> Duration {
+        let song_clock = self.audio_system.song_clocks.iter()
+            .find(|song_clock| song_clock.clock == clock)
+            .expect("[audio_system song_time_at] not a song clock");
+
+        song_clock.time_before(self.time_system.real_age(timestamp))
+    }
+
+    // Song time to draw, ahead of the song time by the visual offset
+    pub fn visual_song_time(&self, clock: Clock) -> Duration {
+        let song_time = self.song_time(clock).as_micros() as i64;
+        let visual_offset = self.audio_system.offsets.visual_ms as i64 * 1_000;
+
+        Duration::from_micros((song_time + visual_offset).max(0) as u64)
+    }
+
+    // Called at the frame start, after the clocks advance
+    pub(in crate::app) fn update_song_clocks(&mut self) {
+        let now = self.time_system.frame_start_time();
+        let latency = self.audio_system.output_latency().as_micros() as i64
+            + self.audio_system.offsets.audio_ms as i64 * 1_000;
+
+        for index in 0..self.audio_system.song_clocks.len() {
+            let voice = self.audio_system.song_clocks[index].voice;
+            let timing = self.audio_system.voice_timing(voice);
+
+            let song_clock = &mut self.audio_system.song_clocks[index];
+            let previous_time = song_clock.origin + song_clock.time;
+            song_clock.update(timing, latency, now);
+            let time = song_clock.origin + song_clock.time;
+
+            let clock = self.time_system.clock_mut(song_clock.clock);
+            clock.time = time;
+            clock.frame_duration = time - previous_time;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::audio::{
+        system::AudioSystem,
+        sound::Sound,
+        mixer::VoiceParams,
+    };
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn at(ms: u64) -> RealTime {
+        RealTime::from_micros(ms * 1_000)
+    }
+
+    fn timing(position: u64, mix_time: u64) -> Option<VoiceTiming> {
+        Some(VoiceTiming { position: ms(position), mix_time: at(mix_time), rate: 1.0 })
+    }
+
+    #[test]
+    fn test_song_clock_smoothing() {
+        let mut song_clock = SongClockState::new(Clock::GAME, Voice::default(), GameTime::default());
+
+        // holds until the voice is mixed
+        song_clock.update(None, 10_000, at(1_000));
+        assert_eq!(song_clock.time(), Duration::ZERO);
+
+        // extrapolated from the mix time, minus the latency
+        song_clock.update(timing(100, 1_000), 10_000, at(1_004));
+        assert_eq!(song_clock.time(), ms(94));
+
+        // the mixer buffers are coarser than the frames: small jitter is smoothed
+        song_clock.update(timing(110, 1_010), 10_000, at(1_020));
+        assert_eq!(song_clock.time(), ms(110));
+        song_clock.update(timing(125, 1_030), 10_000, at(1_034));
+        assert_eq!(song_clock.time(), Duration::from_micros(123_500));
+
+        // never goes back
+        song_clock.update(timing(0, 1_050), 10_000, at(1_050));
+        assert_eq!(song_clock.time(), Duration::from_micros(123_500));
+
+        // big differences resync
+        song_clock.update(timing(500, 1_060), 10_000, at(1_060));
+        assert_eq!(song_clock.time(), ms(490));
+
+        // paused
+        let paused = Some(VoiceTiming { position: ms(500), mix_time: at(1_060), rate: 0.0 });
+        song_clock.update(paused, 10_000, at(1_200));
+        assert_eq!(song_clock.time(), ms(490));
+    }
+
+    #[test]
+    fn test_song_clock_time_before() {
+        let mut song_clock = SongClockState::new(Clock::GAME, Voice::default(), GameTime::default());
+        let pitched = Some(VoiceTiming { position: ms(1_000), mix_time: at(1_000), rate: 1.5 });
+        song_clock.update(pitched, 0, at(1_000));
+
+        assert_eq!(song_clock.time_before(ms(10)), ms(985));
+        assert_eq!(song_clock.time_before(ms(2_000)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_song_clock_follows_audio() {
+        let mut audio_system = AudioSystem::null(48_000);
+        let sound = Sound::from_frames(vec![[0.0, 0.0]; 48_000], 48_000);
+        let voice = audio_system.play_sound(&sound, VoiceParams::default());
+
+        let mut song_clock = SongClockState::new(Clock::GAME, voice, GameTime::default());
+        let mut now = at(1_000);
+
+        for frame in 0..60 {
+            // 16ms and 17ms frames
+            let frame_duration = Duration::from_micros(16_000 + (frame % 2) * 1_000);
+            now += frame_duration;
+
+            let timing = audio_system.voice_timing(voice);
+            song_clock.update(timing, 0, now);
+            if frame > 0 {
+                // the virtual output mixed the previous frame
+                let heard = audio_system.voice_position(voice).unwrap() + frame_duration;
+                assert_eq!(song_clock.time(), heard);
+            }
+
+            audio_system.update(now, frame_duration);
+        }
+    }
+}
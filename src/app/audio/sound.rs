@@ -0,0 +1,109 @@
+// Sounds and decoders
+//
+// Sounds are decoded in memory and shared by the voices playing them (sound effects). Music is
+// streamed from a decoder instead.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::app::{
+    imdraw::ImDraw,
+    time_system::Duration,
+};
+
+use super::{
+    mixer::Frame,
+    vorbis::VorbisDecoder,
+    wav::WavDecoder,
+};
+
+pub(super) trait AudioDecoder {
+    fn sample_rate(&self) -> u32;
+    // Appends up to max_frames frames. Returns the number of frames read, 0 at the end
+    fn read_frames(&mut self, out: &mut Vec<Frame>, max_frames: usize) -> Result<usize, String>;
+    fn rewind(&mut self) -> Result<(), String>;
+}
+
+impl<R: std::io::Read + std::io::Seek> AudioDecoder for WavDecoder<R> {
+    fn sample_rate(&self) -> u32 {
+        WavDecoder::sample_rate(self)
+    }
+
+    fn read_frames(&mut self, out: &mut Vec<Frame>, max_frames: usize) -> Result<usize, String> {
+        WavDecoder::read_frames(self, out, max_frames)
+    }
+
+    fn rewind(&mut self) -> Result<(), String> {
+        WavDecoder::rewind(self)
+    }
+}
+
+impl<R: std::io::Read + std::io::Seek> AudioDecoder for VorbisDecoder<R> {
+    fn sample_rate(&self) -> u32 {
+        VorbisDecoder::sample_rate(self)
+    }
+
+    fn read_frames(&mut self, out: &mut Vec<Frame>, max_frames: usize) -> Result<usize, String> {
+        VorbisDecoder::read_frames(self, out, max_frames)
+    }
+
+    fn rewind(&mut self) -> Result<(), String> {
+        VorbisDecoder::rewind(self)
+    }
+}
+
+// By extension
+pub(super) fn open_decoder<P: AsRef<Path>>(path: P) -> Result<Box<dyn AudioDecoder>, String> {
+    let path = path.as_ref();
+    let extension = path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("wav") => Ok(Box::new(WavDecoder::open(path)?)),
+        Some("ogg") => Ok(Box::new(VorbisDecoder::open(path)?)),
+        _ => Err(format!("'{}': unknown audio format", path.display())),
+    }
+}
+
+#[derive(Clone)]
+pub struct Sound {
+    frames: Arc<[Frame]>,
+    sample_rate: u32,
+}
+
+impl ImDraw for Sound {
+    fn imdraw(&mut self, label: &str, ui: &imgui::Ui) {
+        ui.text(format!("{}: sound ({:.2}s, {}Hz)", label, self.duration().as_secs_f32(), self.sample_rate));
+    }
+}
+
+impl Sound {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let mut decoder = open_decoder(path)?;
+
+        let mut frames = Vec::new();
+        while decoder.read_frames(&mut frames, 4096)? > 0 {}
+
+        Ok(Self::from_frames(frames, decoder.sample_rate()))
+    }
+
+    pub(super) fn from_frames(frames: Vec<Frame>, sample_rate: u32) -> Self {
+        Self {
+            frames: frames.into(),
+            sample_rate,
+        }
+    }
+
+    pub(super) fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_micros(self.frames.len() as u64 * 1_000_000 / self.sample_rate as u64)
+    }
+}
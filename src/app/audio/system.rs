@@ -0,0 +1,480 @@
+// Audio system
+//
+// Owns the mixer and the output: the SDL audio device, or a virtual output advanced by the real
+// frame duration (when there's no audio device, and for tests) that discards the mixed audio or
+// writes it to a WAV file. Streams are decoded here, outside of the audio callback, and pushed to
+// the mixer once per frame.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+    time_system::{Duration, RealTime, thread_system_time},
+};
+
+use super::{
+    calibration::AudioOffsets,
+    mixer::{AudioBus, Frame, Mixer, StreamBuffer, VoiceParams, VoiceSource, VoiceTiming},
+    song_clock::SongClockState,
+    sound::{AudioDecoder, Sound, open_decoder},
+    wav::WavWriter,
+};
+
+const AUDIO_SAMPLE_RATE: u32 = 48_000;
+
+// Frames per callback (~10ms)
+const AUDIO_BUFFER_FRAMES: u16 = 512;
+
+// Decoded ahead of the mixer. Longer than any expected frame stall
+const STREAM_BUFFER_DURATION: Duration = Duration::from_millis(500);
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Voice(Option<u64>);
+
+impl ImDraw for Voice {
+    fn imdraw(&mut self, label: &str, ui: &imgui::Ui) {
+        match self.0 {
+            Some(id) => ui.text(format!("{}: voice {}", label, id)),
+            None => ui.text(format!("{}: (no voice)", label)),
+        }
+    }
+}
+
+struct MixerCallback(Mixer);
+
+impl AudioCallback for MixerCallback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.mix(out, thread_system_time());
+    }
+}
+
+enum AudioOutput {
+    Device(AudioDevice<MixerCallback>),
+    Virtual {
+        mixer: Mixer,
+        file: Option<WavWriter<BufWriter<File>>>,
+        elapsed: Duration,
+        mixed_frames: u64,
+    },
+}
+
+struct AudioStream {
+    voice_id: u64,
+    decoder: Box<dyn AudioDecoder>,
+    looping: bool,
+}
+
+pub(in crate::app) struct AudioSystem {
+    output: AudioOutput,
+    streams: Vec<AudioStream>,
+    pub(super) song_clocks: Vec<SongClockState>,
+    pub(super) offsets: AudioOffsets,
+}
+
+impl AudioSystem {
+    // Falls back to a virtual output without an audio device
+    pub(in crate::app) fn new(audio_subsystem: Option<sdl2::AudioSubsystem>) -> Self {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(AUDIO_SAMPLE_RATE as i32),
+            channels: Some(2),
+            samples: Some(AUDIO_BUFFER_FRAMES),
+        };
+
+        let device = audio_subsystem.and_then(|audio_subsystem| {
+            audio_subsystem
+                .open_playback(None, &desired_spec, |spec| MixerCallback(Mixer::new(spec.freq as u32)))
+                .map_err(|e| println!("[audio_system new] can't open the audio device: {}", e))
+                .ok()
+        });
+
+        match device {
+            Some(device) => {
+                println!("Audio device: {}Hz, {} frames", device.spec().freq, device.spec().samples);
+                device.resume();
+
+                Self {
+                    output: AudioOutput::Device(device),
+                    streams: Vec::new(),
+                    song_clocks: Vec::new(),
+                    offsets: AudioOffsets::default(),
+                }
+            },
+            None => Self::null(AUDIO_SAMPLE_RATE),
+        }
+    }
+
+    pub(in crate::app) fn null(sample_rate: u32) -> Self {
+        Self {
+            output: AudioOutput::Virtual {
+                mixer: Mixer::new(sample_rate),
+                file: None,
+                elapsed: Duration::ZERO,
+                mixed_frames: 0,
+            },
+            streams: Vec::new(),
+            song_clocks: Vec::new(),
+            offsets: AudioOffsets::default(),
+        }
+    }
+
+    pub(in crate::app) fn with_file_output<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self, String> {
+        let mut audio_system = Self::null(sample_rate);
+        if let AudioOutput::Virtual { file, .. } = &mut audio_system.output {
+            *file = Some(WavWriter::create(path, sample_rate)?);
+        }
+
+        Ok(audio_system)
+    }
+
+    // Locks the audio device
+    fn with_mixer<R>(&mut self, f: impl FnOnce(&mut Mixer) -> R) -> R {
+        match &mut self.output {
+            AudioOutput::Device(device) => f(&mut device.lock().0),
+            AudioOutput::Virtual { mixer, .. } => f(mixer),
+        }
+    }
+
+    // Called once per frame with the frame start time. The virtual output mixes the last frame
+    pub(in crate::app) fn update(&mut self, now: RealTime, real_frame_duration: Duration) {
+        self.update_streams();
+
+        if let AudioOutput::Virtual { mixer, file, elapsed, mixed_frames } = &mut self.output {
+            *elapsed += real_frame_duration;
+            let frames = elapsed.as_micros() * mixer.sample_rate() as u64 / 1_000_000 - *mixed_frames;
+            *mixed_frames += frames;
+
+            let mut out = vec![0.0; frames as usize * 2];
+            let mix_time = RealTime::from_micros(now.as_micros().saturating_sub(real_frame_duration.as_micros()));
+            mixer.mix(&mut out, mix_time);
+
+            if let Some(writer) = file.as_mut() {
+                if let Err(e) = writer.write(&out) {
+                    println!("[audio_system update] can't write the audio output: {}", e);
+                    *file = None;
+                }
+            }
+        }
+    }
+
+    // Tops up the stream buffers. Decoding happens before locking the device
+    fn update_streams(&mut self) {
+        let mut streams = std::mem::take(&mut self.streams);
+
+        streams.retain_mut(|stream| {
+            let buffered = match self.with_mixer(|mixer| mixer.stream_buffered(stream.voice_id)) {
+                Some(buffered) => buffered,
+                // Finished or stopped
+                None => return false,
+            };
+
+            let target = STREAM_BUFFER_DURATION.as_micros() * stream.decoder.sample_rate() as u64 / 1_000_000;
+            let missing = (target as usize).saturating_sub(buffered);
+            if missing == 0 {
+                return true;
+            }
+
+            let (frames, end_of_stream) = decode(stream, missing);
+            self.with_mixer(|mixer| mixer.push_stream(stream.voice_id, &frames, end_of_stream));
+
+            !end_of_stream
+        });
+
+        self.streams = streams;
+    }
+
+    // Between mixing a buffer and hearing it. Only the SDL buffer, the driver and hardware add more
+    pub(super) fn output_latency(&self) -> Duration {
+        match &self.output {
+            AudioOutput::Device(device) => {
+                let spec = device.spec();
+                Duration::from_micros(spec.samples as u64 * 1_000_000 / spec.freq as u64)
+            },
+            AudioOutput::Virtual { .. } => Duration::ZERO,
+        }
+    }
+
+    pub(super) fn voice_timing(&mut self, voice: Voice) -> Option<VoiceTiming> {
+        voice.0.and_then(|id| self.with_mixer(|mixer| mixer.voice_timing(id)))
+    }
+
+    pub(super) fn play_sound(&mut self, sound: &Sound, params: VoiceParams) -> Voice {
+        Voice(self.with_mixer(|mixer| mixer.add_voice(VoiceSource::Sound(sound.clone()), params)))
+    }
+
+    // Looping is done by the decoder
+    pub(super) fn play_stream<P: AsRef<Path>>(&mut self, path: P, params: VoiceParams) -> Result<Voice, String> {
+        let decoder = open_decoder(path)?;
+        let sample_rate = decoder.sample_rate();
+        let source_params = VoiceParams { looping: false, ..params };
+
+        let source = VoiceSource::Stream(StreamBuffer::new(sample_rate));
+        let voice_id = match self.with_mixer(|mixer| mixer.add_voice(source, source_params)) {
+            Some(voice_id) => voice_id,
+            None => return Ok(Voice(None)),
+        };
+
+        self.streams.push(AudioStream {
+            voice_id,
+            decoder,
+            looping: params.looping,
+        });
+
+        // Buffered before the next callback, so it starts right away
+        self.update_streams();
+
+        Ok(Voice(Some(voice_id)))
+    }
+
+    pub(super) fn stop_voice(&mut self, voice: Voice) {
+        if let Some(id) = voice.0 {
+            self.with_mixer(|mixer| mixer.remove_voice(id));
+            self.streams.retain(|stream| stream.voice_id != id);
+        }
+    }
+
+    pub(super) fn is_voice_playing(&mut self, voice: Voice) -> bool {
+        voice.0.is_some_and(|id| self.with_mixer(|mixer| mixer.is_playing(id)))
+    }
+
+    pub(super) fn update_voice_params(&mut self, voice: Voice, f: impl FnOnce(&mut VoiceParams)) {
+        if let Some(id) = voice.0 {
+            self.with_mixer(|mixer| {
+                if let Some(params) = mixer.voice_params_mut(id) {
+                    f(params);
+                }
+            });
+        }
+    }
+
+    pub(super) fn set_voice_paused(&mut self, voice: Voice, paused: bool) {
+        if let Some(id) = voice.0 {
+            self.with_mixer(|mixer| mixer.set_paused(id, paused));
+        }
+    }
+
+    pub(super) fn is_voice_paused(&mut self, voice: Voice) -> bool {
+        voice.0.is_some_and(|id| self.with_mixer(|mixer| mixer.is_paused(id)))
+    }
+
+    pub(super) fn voice_position(&mut self, voice: Voice) -> Option<Duration> {
+        voice.0.and_then(|id| self.with_mixer(|mixer| mixer.voice_position(id)))
+    }
+
+    pub(super) fn bus_volume(&mut self, bus: AudioBus) -> f32 {
+        self.with_mixer(|mixer| mixer.bus_volume(bus))
+    }
+
+    pub(super) fn set_bus_volume(&mut self, bus: AudioBus, volume: f32) {
+        self.with_mixer(|mixer| mixer.set_bus_volume(bus, volume));
+    }
+
+    pub(super) fn master_volume(&mut self) -> f32 {
+        self.with_mixer(|mixer| mixer.master_volume())
+    }
+
+    pub(super) fn set_master_volume(&mut self, volume: f32) {
+        self.with_mixer(|mixer| mixer.set_master_volume(volume));
+    }
+}
+
+// Returns the frames and if the stream ended. Errors end the stream
+fn decode(stream: &mut AudioStream, max_frames: usize) -> (Vec<Frame>, bool) {
+    let mut frames = Vec::with_capacity(max_frames);
+    // Empty files would loop forever
+    let mut rewound = false;
+
+    while frames.len() < max_frames {
+        let remaining = max_frames - frames.len();
+        let read = match stream.decoder.read_frames(&mut frames, remaining) {
+            Ok(read) => read,
+            Err(e) => {
+                println!("[audio_system decode] {}", e);
+                return (frames, true);
+            },
+        };
+
+        if read > 0 {
+            rewound = false;
+            continue;
+        }
+
+        if !stream.looping || rewound {
+            return (frames, true);
+        }
+
+        if let Err(e) = stream.decoder.rewind() {
+            println!("[audio_system decode] {}", e);
+            return (frames, true);
+        }
+        rewound = true;
+    }
+
+    (frames, false)
+}
+
+impl<S> App<'_, S> {
+    // Fire and forget, the voice can be ignored
+    pub fn play_sound(&mut self, sound: &Sound, params: VoiceParams) -> Voice {
+        self.audio_system.play_sound(sound, params)
+    }
+
+    // Streamed from the file, on the bus of the params (VoiceParams::new(AudioBus::Music) for songs)
+    pub fn play_music<P: AsRef<Path>>(&mut self, path: P, params: VoiceParams) -> Result<Voice, String> {
+        self.audio_system.play_stream(path, params)
+    }
+
+    pub fn stop_voice(&mut self, voice: Voice) {
+        self.audio_system.stop_voice(voice);
+    }
+
+    // Voices stop playing when they finish or are stopped
+    pub fn is_voice_playing(&mut self, voice: Voice) -> bool {
+        self.audio_system.is_voice_playing(voice)
+    }
+
+    pub fn set_voice_volume(&mut self, voice: Voice, volume: f32) {
+        self.audio_system.update_voice_params(voice, |params| params.volume = volume);
+    }
+
+    pub fn set_voice_pan(&mut self, voice: Voice, pan: f32) {
+        self.audio_system.update_voice_params(voice, |params| params.pan = pan);
+    }
+
+    pub fn set_voice_pitch(&mut self, voice: Voice, pitch: f32) {
+        self.audio_system.update_voice_params(voice, |params| params.pitch = pitch);
+    }
+
+    pub fn pause_voice(&mut self, voice: Voice) {
+        self.audio_system.set_voice_paused(voice, true);
+    }
+
+    pub fn resume_voice(&mut self, voice: Voice) {
+        self.audio_system.set_voice_paused(voice, false);
+    }
+
+    pub fn is_voice_paused(&mut self, voice: Voice) -> bool {
+        self.audio_system.is_voice_paused(voice)
+    }
+
+    // Source time played, None if the voice isn't playing
+    pub fn voice_position(&mut self, voice: Voice) -> Option<Duration> {
+        self.audio_system.voice_position(voice)
+    }
+
+    pub fn bus_volume(&mut self, bus: AudioBus) -> f32 {
+        self.audio_system.bus_volume(bus)
+    }
+
+    pub fn set_bus_volume(&mut self, bus: AudioBus, volume: f32) {
+        self.audio_system.set_bus_volume(bus, volume);
+    }
+
+    pub fn master_volume(&mut self) -> f32 {
+        self.audio_system.master_volume()
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.audio_system.set_master_volume(volume);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::wav::WavDecoder;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("audio_system_{}_{}.wav", name, std::process::id()))
+    }
+
+    fn write_test_wav(path: &Path, frames: &[f32], sample_rate: u32) {
+        let mut writer = WavWriter::create(path, sample_rate).unwrap();
+        writer.write(frames).unwrap();
+        writer.finish().unwrap();
+    }
+
+    #[test]
+    fn test_audio_system_stream() {
+        let music_path = temp_path("music");
+        let output_path = temp_path("output");
+
+        // 100ms at 0.5, then 100ms at -0.5
+        let mut samples = vec![0.5; 4800 * 2];
+        samples.extend(vec![-0.5; 4800 * 2]);
+        write_test_wav(&music_path, &samples, 48_000);
+
+        let mut audio_system = AudioSystem::with_file_output(&output_path, 48_000).unwrap();
+        audio_system.set_bus_volume(AudioBus::Music, 0.5);
+
+        let voice = audio_system.play_stream(&music_path, VoiceParams::new(AudioBus::Music)).unwrap();
+        assert!(audio_system.is_voice_playing(voice));
+
+        audio_system.update(RealTime::default(), Duration::from_millis(150));
+        assert_eq!(audio_system.voice_position(voice), Some(Duration::from_millis(150)));
+
+        audio_system.update(RealTime::default(), Duration::from_millis(100));
+        assert!(!audio_system.is_voice_playing(voice));
+
+        if let AudioOutput::Virtual { file, .. } = &mut audio_system.output {
+            file.take().unwrap().finish().unwrap();
+        }
+
+        let mut decoder = WavDecoder::open(&output_path).unwrap();
+        assert_eq!(decoder.frames(), 12_000);
+
+        let mut frames = Vec::new();
+        decoder.read_frames(&mut frames, 12_000).unwrap();
+        assert!((frames[100][0] - 0.25).abs() < 1e-3);
+        assert!((frames[6_000][1] + 0.25).abs() < 1e-3);
+        assert_eq!(frames[11_000], [0.0, 0.0]);
+
+        // looping stream and stop
+        let voice = audio_system.play_stream(&music_path, VoiceParams::new(AudioBus::Music).looping(true)).unwrap();
+        for _ in 0..10 {
+            audio_system.update(RealTime::default(), Duration::from_millis(100));
+        }
+        assert!(audio_system.is_voice_playing(voice));
+        assert_eq!(audio_system.voice_position(voice), Some(Duration::from_millis(1_000)));
+
+        audio_system.stop_voice(voice);
+        assert!(!audio_system.is_voice_playing(voice));
+        assert!(audio_system.streams.is_empty());
+
+        assert!(audio_system.play_stream(temp_path("missing"), VoiceParams::default()).is_err());
+        assert!(audio_system.play_stream("song.ogg", VoiceParams::default()).is_err());
+
+        let _ = std::fs::remove_file(music_path);
+        let _ = std::fs::remove_file(output_path);
+    }
+
+    #[test]
+    fn test_audio_system_sound() {
+        let sound_path = temp_path("sound");
+        write_test_wav(&sound_path, &[1.0, 1.0, 1.0, 1.0], 24_000);
+
+        let sound = Sound::load(&sound_path).unwrap();
+        assert_eq!(sound.sample_rate(), 24_000);
+        assert_eq!(sound.frames().len(), 2);
+
+        let mut audio_system = AudioSystem::null(48_000);
+        let voice = audio_system.play_sound(&sound, VoiceParams::default().looping(true));
+        audio_system.update(RealTime::default(), Duration::from_millis(10));
+        assert!(audio_system.is_voice_playing(voice));
+
+        audio_system.set_voice_paused(voice, true);
+        assert!(audio_system.is_voice_paused(voice));
+
+        audio_system.stop_voice(voice);
+        assert!(!audio_system.is_voice_playing(voice));
+        assert!(!audio_system.is_voice_playing(Voice::default()));
+
+        let _ = std::fs::remove_file(sound_path);
+    }
+}
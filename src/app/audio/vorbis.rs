@@ -0,0 +1,206 @@
+// Ogg Vorbis files
+//
+// Streaming decoder (lewton) for Vorbis streams with one or two channels. Decoded frames are always
+// stereo: mono streams are duplicated to both channels. Chained streams are decoded one after the
+// other, keeping the sample rate of the first one.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use lewton::{
+    inside_ogg::OggStreamReader,
+    samples::InterleavedSamples,
+};
+
+use super::mixer::Frame;
+
+pub(super) struct VorbisDecoder<R: Read + Seek> {
+    // None only if a rewind failed
+    stream: Option<OggStreamReader<R>>,
+    sample_rate: u32,
+
+    // Frames of the last packet not read yet. Packets don't match the frames requested
+    pending: VecDeque<Frame>,
+}
+
+impl VorbisDecoder<BufReader<File>> {
+    pub(super) fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| format!("can't open '{}': {}", path.as_ref().display(), e))?;
+
+        Self::new(BufReader::new(file))
+            .map_err(|e| format!("'{}': {}", path.as_ref().display(), e))
+    }
+}
+
+impl<R: Read + Seek> VorbisDecoder<R> {
+    pub(super) fn new(reader: R) -> Result<Self, String> {
+        let stream = OggStreamReader::new(reader).map_err(|e| e.to_string())?;
+
+        let channels = stream.ident_hdr.audio_channels as usize;
+        if channels != 1 && channels != 2 {
+            return Err(format!("unsupported channel count {}", channels));
+        }
+
+        Ok(Self {
+            sample_rate: stream.ident_hdr.audio_sample_rate,
+            stream: Some(stream),
+            pending: VecDeque::new(),
+        })
+    }
+
+    pub(super) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    // Appends up to max_frames frames. Returns the number of frames read, 0 at the end
+    pub(super) fn read_frames(&mut self, out: &mut Vec<Frame>, max_frames: usize) -> Result<usize, String> {
+        let stream = self.stream.as_mut().ok_or_else(|| "decoder failed to rewind".to_string())?;
+
+        while self.pending.len() < max_frames {
+            let packet: InterleavedSamples<f32> = match stream.read_dec_packet_generic().map_err(|e| e.to_string())? {
+                Some(packet) => packet,
+                None => break,
+            };
+
+            self.pending.extend(packet.samples.chunks_exact(packet.channel_count).map(|samples| {
+                let left = samples[0];
+                let right = samples.get(1).copied().unwrap_or(left);
+                [left, right]
+            }));
+        }
+
+        let frames = self.pending.len().min(max_frames);
+        out.extend(self.pending.drain(..frames));
+        Ok(frames)
+    }
+
+    // The headers are read again, since seeking works with page granularity
+    pub(super) fn rewind(&mut self) -> Result<(), String> {
+        self.pending.clear();
+
+        let stream = self.stream.take().ok_or_else(|| "decoder failed to rewind".to_string())?;
+        let mut reader = stream.into_inner().into_inner();
+        reader.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+
+        self.stream = Some(OggStreamReader::new(reader).map_err(|e| e.to_string())?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+
+    // Bits are packed from the least significant bit of every byte
+    #[derive(Default)]
+    struct BitWriter {
+        bytes: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, bits: usize) {
+            for i in 0..bits {
+                if self.bits.is_multiple_of(8) {
+                    self.bytes.push(0);
+                }
+                *self.bytes.last_mut().unwrap() |= (((value >> i) & 1) as u8) << (self.bits % 8);
+                self.bits += 1;
+            }
+        }
+    }
+
+    fn header(packet_type: u8) -> Vec<u8> {
+        let mut bytes = vec![packet_type];
+        bytes.extend_from_slice(b"vorbis");
+        bytes
+    }
+
+    // Silent stereo stream with 256 frames blocks (the first packet only primes the overlap). The setup
+    // has a single codebook, floor, residue, mapping and mode, and every audio packet marks both
+    // channels as unused
+    fn silent_vorbis(sample_rate: u32, audio_packets: usize) -> Vec<u8> {
+        let mut identification = header(1);
+        identification.extend_from_slice(&0u32.to_le_bytes()); // version
+        identification.push(2); // channels
+        identification.extend_from_slice(&sample_rate.to_le_bytes());
+        identification.extend_from_slice(&[0; 12]); // bitrates
+        identification.push(0x88); // block sizes 2^8
+        identification.push(1); // framing
+
+        let mut comment = header(3);
+        comment.extend_from_slice(&4u32.to_le_bytes());
+        comment.extend_from_slice(b"test");
+        comment.extend_from_slice(&0u32.to_le_bytes()); // comments
+        comment.push(1); // framing
+
+        let mut setup = BitWriter::default();
+        setup.bytes = header(5);
+        setup.bits = setup.bytes.len() * 8;
+        let fields = [
+            (0, 8), // codebooks - 1
+            (0x564342, 24), (1, 16), (2, 24), (0, 1), (0, 1), (0, 5), (0, 5), (0, 4), // 2 entries of 1 bit
+            (0, 6), (0, 16), // time domain transforms
+            (0, 6), (1, 16), (0, 5), (0, 2), (8, 4), // floor 1 without partitions
+            (0, 6), (0, 16), (0, 24), (0, 24), (0, 24), (0, 6), (0, 8), (0, 3), (0, 1), // empty residue
+            (0, 6), (0, 16), (0, 1), (0, 1), (0, 2), (0, 8), (0, 8), (0, 8), // mapping
+            (0, 6), (0, 1), (0, 16), (0, 16), (0, 8), // mode
+            (1, 1), // framing
+        ];
+        for &(value, bits) in fields.iter() {
+            setup.write(value, bits);
+        }
+
+        let mut writer = PacketWriter::new(Vec::new());
+        let mut write = |packet: Vec<u8>, end: PacketWriteEndInfo, granule: u64| {
+            writer.write_packet(packet.into_boxed_slice(), 1, end, granule).unwrap();
+        };
+        write(identification, PacketWriteEndInfo::EndPage, 0);
+        write(comment, PacketWriteEndInfo::NormalPacket, 0);
+        write(setup.bytes, PacketWriteEndInfo::EndPage, 0);
+
+        let frames = (audio_packets as u64 - 1) * 128;
+        for i in 0..audio_packets {
+            let end = if i + 1 == audio_packets {
+                PacketWriteEndInfo::EndStream
+            } else {
+                PacketWriteEndInfo::NormalPacket
+            };
+            // audio packet, both floors unused
+            write(vec![0], end, frames);
+        }
+
+        writer.into_inner()
+    }
+
+    #[test]
+    fn test_vorbis_decoder() {
+        let mut decoder = VorbisDecoder::new(Cursor::new(silent_vorbis(22050, 5))).unwrap();
+        assert_eq!(decoder.sample_rate(), 22050);
+
+        let mut frames = Vec::new();
+        assert_eq!(decoder.read_frames(&mut frames, 300).unwrap(), 300);
+        assert_eq!(decoder.read_frames(&mut frames, 300).unwrap(), 212);
+        assert_eq!(decoder.read_frames(&mut frames, 300).unwrap(), 0);
+        assert!(frames.iter().all(|&frame| frame == [0.0, 0.0]));
+
+        decoder.rewind().unwrap();
+        frames.clear();
+        assert_eq!(decoder.read_frames(&mut frames, 1000).unwrap(), 512);
+    }
+
+    #[test]
+    fn test_vorbis_decoder_errors() {
+        assert!(VorbisDecoder::new(Cursor::new(b"RIFF".to_vec())).is_err());
+
+        // truncated headers
+        let mut bytes = silent_vorbis(44100, 2);
+        bytes.truncate(60);
+        assert!(VorbisDecoder::new(Cursor::new(bytes)).is_err());
+    }
+}
@@ -0,0 +1,359 @@
+// WAV files
+//
+// Streaming decoder for PCM (8, 16, 24 and 32 bits) and float (32 bits) WAV files with one or two
+// channels, and a 16 bit stereo writer (used by the file output). Decoded frames are always stereo:
+// mono files are duplicated to both channels.
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::mixer::Frame;
+
+const WAVE_FORMAT_PCM        : u16 = 0x0001;
+const WAVE_FORMAT_IEEE_FLOAT : u16 = 0x0003;
+const WAVE_FORMAT_EXTENSIBLE : u16 = 0xFFFE;
+
+// Bytes after the header size field (RIFF size) and the data size field, patched when finished
+const WAV_HEADER_SIZE: u32 = 44;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SampleFormat {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+}
+
+impl SampleFormat {
+    fn bytes(self) -> usize {
+        match self {
+            SampleFormat::U8 => 1,
+            SampleFormat::I16 => 2,
+            SampleFormat::I24 => 3,
+            SampleFormat::I32 | SampleFormat::F32 => 4,
+        }
+    }
+
+    fn sample(self, bytes: &[u8]) -> f32 {
+        match self {
+            SampleFormat::U8 => (bytes[0] as f32 - 128.0) / 128.0,
+            SampleFormat::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+            // Sign extended from the top byte
+            SampleFormat::I24 => i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2147483648.0,
+            SampleFormat::I32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2147483648.0,
+            SampleFormat::F32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+}
+
+pub(super) struct WavDecoder<R> {
+    reader: R,
+    format: SampleFormat,
+    channels: usize,
+    sample_rate: u32,
+
+    // Data chunk position and size in bytes
+    data_start: u64,
+    data_size: u64,
+    data_read: u64,
+}
+
+impl WavDecoder<BufReader<File>> {
+    pub(super) fn open<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let file = File::open(path.as_ref())
+            .map_err(|e| format!("can't open '{}': {}", path.as_ref().display(), e))?;
+
+        Self::new(BufReader::new(file))
+            .map_err(|e| format!("'{}': {}", path.as_ref().display(), e))
+    }
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, String> {
+    read_bytes(reader).map(u16::from_le_bytes)
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, String> {
+    read_bytes(reader).map(u32::from_le_bytes)
+}
+
+impl<R: Read + Seek> WavDecoder<R> {
+    pub(super) fn new(mut reader: R) -> Result<Self, String> {
+        if &read_bytes::<R, 4>(&mut reader)? != b"RIFF" {
+            return Err("not a RIFF file".to_string());
+        }
+        read_u32(&mut reader)?;
+        if &read_bytes::<R, 4>(&mut reader)? != b"WAVE" {
+            return Err("not a WAVE file".to_string());
+        }
+
+        let mut format = None;
+
+        // Chunks, the fmt chunk comes before the data chunk
+        loop {
+            let id = read_bytes::<R, 4>(&mut reader).map_err(|_| "missing data chunk".to_string())?;
+            let size = read_u32(&mut reader)? as u64;
+            // Chunks are padded to an even size
+            let padded_size = size + (size & 1);
+
+            match &id {
+                b"fmt " => {
+                    if size < 16 {
+                        return Err("invalid fmt chunk size".to_string());
+                    }
+
+                    let mut tag = read_u16(&mut reader)?;
+                    let channels = read_u16(&mut reader)? as usize;
+                    let sample_rate = read_u32(&mut reader)?;
+                    read_u32(&mut reader)?; // byte rate
+                    read_u16(&mut reader)?; // block align
+                    let bits = read_u16(&mut reader)?;
+                    let mut read = 16;
+
+                    if tag == WAVE_FORMAT_EXTENSIBLE && size >= 26 {
+                        read_u16(&mut reader)?; // extension size
+                        read_u16(&mut reader)?; // valid bits
+                        read_u32(&mut reader)?; // channel mask
+                        tag = read_u16(&mut reader)?; // sub format GUID first bytes
+                        read += 10;
+                    }
+
+                    reader.seek(SeekFrom::Current(padded_size as i64 - read)).map_err(|e| e.to_string())?;
+
+                    let sample_format = match (tag, bits) {
+                        (WAVE_FORMAT_PCM, 8) => SampleFormat::U8,
+                        (WAVE_FORMAT_PCM, 16) => SampleFormat::I16,
+                        (WAVE_FORMAT_PCM, 24) => SampleFormat::I24,
+                        (WAVE_FORMAT_PCM, 32) => SampleFormat::I32,
+                        (WAVE_FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32,
+                        _ => return Err(format!("unsupported format {} with {} bits", tag, bits)),
+                    };
+
+                    if channels != 1 && channels != 2 {
+                        return Err(format!("unsupported channel count {}", channels));
+                    }
+
+                    if sample_rate == 0 {
+                        return Err("invalid sample rate 0".to_string());
+                    }
+
+                    format = Some((sample_format, channels, sample_rate));
+                },
+                b"data" => {
+                    let (format, channels, sample_rate) = format.ok_or_else(|| "data before fmt chunk".to_string())?;
+                    let data_start = reader.stream_position().map_err(|e| e.to_string())?;
+
+                    return Ok(Self {
+                        reader,
+                        format,
+                        channels,
+                        sample_rate,
+                        data_start,
+                        data_size: size,
+                        data_read: 0,
+                    });
+                },
+                _ => {
+                    reader.seek(SeekFrom::Current(padded_size as i64)).map_err(|e| e.to_string())?;
+                },
+            }
+        }
+    }
+
+    pub(super) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn frame_size(&self) -> usize {
+        self.format.bytes() * self.channels
+    }
+
+    pub(super) fn frames(&self) -> u64 {
+        self.data_size / self.frame_size() as u64
+    }
+
+    // Appends up to max_frames frames. Returns the number of frames read, 0 at the end
+    pub(super) fn read_frames(&mut self, out: &mut Vec<Frame>, max_frames: usize) -> Result<usize, String> {
+        let frame_size = self.frame_size();
+        let remaining = ((self.data_size - self.data_read) / frame_size as u64) as usize;
+        let frames = remaining.min(max_frames);
+
+        let mut bytes = vec![0; frames * frame_size];
+        self.reader.read_exact(&mut bytes).map_err(|e| e.to_string())?;
+        self.data_read += bytes.len() as u64;
+
+        let sample_size = self.format.bytes();
+        out.extend(bytes.chunks_exact(frame_size).map(|frame| {
+            let left = self.format.sample(&frame[..sample_size]);
+            let right = if self.channels == 2 { self.format.sample(&frame[sample_size..]) } else { left };
+            [left, right]
+        }));
+
+        Ok(frames)
+    }
+
+    pub(super) fn rewind(&mut self) -> Result<(), String> {
+        self.reader.seek(SeekFrom::Start(self.data_start)).map_err(|e| e.to_string())?;
+        self.data_read = 0;
+        Ok(())
+    }
+}
+
+// 16 bit stereo. The sizes in the header are patched when finished (or dropped)
+pub(super) struct WavWriter<W: Write + Seek> {
+    writer: W,
+    frames: u32,
+    finished: bool,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub(super) fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self, String> {
+        let file = File::create(path.as_ref())
+            .map_err(|e| format!("can't create '{}': {}", path.as_ref().display(), e))?;
+
+        Self::new(BufWriter::new(file), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub(super) fn new(mut writer: W, sample_rate: u32) -> Result<Self, String> {
+        let mut header = Vec::with_capacity(WAV_HEADER_SIZE as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_SIZE - 8).to_le_bytes());
+        header.extend_from_slice(b"WAVEfmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&sample_rate.to_le_bytes());
+        header.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&16u16.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&0u32.to_le_bytes());
+
+        writer.write_all(&header).map_err(|e| e.to_string())?;
+
+        Ok(Self { writer, frames: 0, finished: false })
+    }
+
+    // Interleaved stereo samples
+    pub(super) fn write(&mut self, samples: &[f32]) -> Result<(), String> {
+        let bytes: Vec<u8> = samples.iter()
+            .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+            .collect();
+
+        self.writer.write_all(&bytes).map_err(|e| e.to_string())?;
+        self.frames += (samples.len() / 2) as u32;
+        Ok(())
+    }
+
+    fn write_sizes(&mut self) -> std::io::Result<()> {
+        let data_size = self.frames * 4;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(WAV_HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()
+    }
+
+    pub(super) fn finish(mut self) -> Result<(), String> {
+        self.finished = true;
+        self.write_sizes().map_err(|e| e.to_string())
+    }
+}
+
+impl<W: Write + Seek> Drop for WavWriter<W> {
+    fn drop(&mut self) {
+        if !self.finished {
+            if let Err(e) = self.write_sizes() {
+                println!("[audio_system wav_writer drop] can't finish the file: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Mono 16 bit file with a LIST chunk before the data
+    fn mono_wav(samples: &[i16]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&22050u32.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"LIST");
+        bytes.extend_from_slice(&3u32.to_le_bytes());
+        bytes.extend_from_slice(&[1, 2, 3, 0]);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(samples.len() as u32 * 2).to_le_bytes());
+        for sample in samples {
+            bytes.extend_from_slice(&sample.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn test_wav_decoder() {
+        let mut decoder = WavDecoder::new(Cursor::new(mono_wav(&[0, 16384, -32768]))).unwrap();
+        assert_eq!(decoder.sample_rate(), 22050);
+        assert_eq!(decoder.frames(), 3);
+
+        let mut frames = Vec::new();
+        assert_eq!(decoder.read_frames(&mut frames, 2).unwrap(), 2);
+        assert_eq!(decoder.read_frames(&mut frames, 2).unwrap(), 1);
+        assert_eq!(decoder.read_frames(&mut frames, 2).unwrap(), 0);
+        assert_eq!(frames, vec![[0.0, 0.0], [0.5, 0.5], [-1.0, -1.0]]);
+
+        decoder.rewind().unwrap();
+        frames.clear();
+        decoder.read_frames(&mut frames, 1).unwrap();
+        assert_eq!(frames, vec![[0.0, 0.0]]);
+
+        assert!(WavDecoder::new(Cursor::new(b"RIFF\0\0\0\0AVI ".to_vec())).is_err());
+
+        let mut bytes = mono_wav(&[0]);
+        bytes[16..20].copy_from_slice(&12u32.to_le_bytes());
+        assert_eq!(WavDecoder::new(Cursor::new(bytes)).err(), Some("invalid fmt chunk size".to_string()));
+    }
+
+    #[test]
+    fn test_wav_writer_roundtrip() {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = WavWriter::new(&mut cursor, 48000).unwrap();
+        writer.write(&[0.0, 0.5, -0.5, 1.0]).unwrap();
+        writer.write(&[0.25, -0.25]).unwrap();
+        writer.finish().unwrap();
+
+        let mut decoder = WavDecoder::new(Cursor::new(cursor.into_inner())).unwrap();
+        assert_eq!(decoder.sample_rate(), 48000);
+        assert_eq!(decoder.frames(), 3);
+
+        let mut frames = Vec::new();
+        decoder.read_frames(&mut frames, 3).unwrap();
+        assert!((frames[1][0] + 0.5).abs() < 1e-3);
+        assert!((frames[2][1] + 0.25).abs() < 1e-3);
+
+        // not finished
+        let mut cursor = Cursor::new(Vec::new());
+        WavWriter::new(&mut cursor, 48000).unwrap().write(&[0.5, 0.5]).unwrap();
+        assert_eq!(WavDecoder::new(Cursor::new(cursor.into_inner())).unwrap().frames(), 1);
+    }
+}
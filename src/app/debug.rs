@@ -4,8 +4,16 @@ use imgui::*;
 use crate::app::{
     App,
     imgui::imgui_sdl2,
+    task_system::Task,
 };
 
+// Actions requested by the debug windows. Applied after the frame is rendered, since we can't use
+// App inside the imgui callbacks
+enum DebugAction {
+    CancelTask(Task),
+    RunTaskNow(Task),
+}
+
 // TODO move all this to Render?
 pub(super) struct Debug {
     imgui: imgui::Context,
@@ -52,19 +60,71 @@ impl<S> App<'_, S> {
             &self.sdl_context.event_pump.mouse_state()
         );
 
+        let frame_time_stats = self.frame_time_stats();
+        let task_infos = self.task_debug_infos();
+        let mut actions = Vec::new();
+
         let ui = self.debug.imgui.frame();
 
         imgui::Window::new(imgui::im_str!("Debug"))
             .build(&ui, || {
                 ui.text(format!("Application average {:.3} ms/frame ({:.1} FPS)",
                     1000.0 / ui.io().framerate, ui.io().framerate));
+                ui.text(format!("Frame time min {:.3} avg {:.3} max {:.3} p99 {:.3} ms",
+                    frame_time_stats.min.as_micros() as f32 / 1000.0,
+                    frame_time_stats.avg.as_micros() as f32 / 1000.0,
+                    frame_time_stats.max.as_micros() as f32 / 1000.0,
+                    frame_time_stats.p99.as_micros() as f32 / 1000.0));
 
                 ui.separator();
 
                 render_info(&ui, state);
             });
 
+        imgui::Window::new(imgui::im_str!("Tasks"))
+            .build(&ui, || {
+                ui.text(format!("{} scheduled tasks", task_infos.len()));
+                ui.separator();
+
+                for (index, info) in task_infos.iter().enumerate() {
+                    let id = ui.push_id(index as i32);
+
+                    if ui.small_button(imgui::im_str!("cancel")) {
+                        actions.push(DebugAction::CancelTask(info.task));
+                    }
+                    ui.same_line(0.0);
+                    if ui.small_button(imgui::im_str!("run now")) {
+                        actions.push(DebugAction::RunTaskNow(info.task));
+                    }
+                    ui.same_line(0.0);
+
+                    let label = info.label.as_deref().unwrap_or("(no label)");
+                    ui.text(format!(
+                        "{} [{}] {}{}{}",
+                        label,
+                        info.clock,
+                        info.remaining,
+                        if info.repeating { " repeating" } else { "" },
+                        if info.paused { " paused" } else { "" },
+                    ));
+                    ui.text_disabled(format!("    {}", info.origin));
+
+                    id.pop(&ui);
+                }
+            });
+
         self.debug.imgui_sdl2.prepare_render(&ui, &self.video_system.window);
         self.debug.imgui_renderer.render(ui);
+
+        for action in actions {
+            match action {
+                DebugAction::CancelTask(mut task) => {
+                    if self.is_task_scheduled(&task) {
+                        self.cancel_task(&mut task);
+                    }
+                },
+                DebugAction::RunTaskNow(task) => self.run_task_now(&task, state),
+            }
+        }
     }
 }
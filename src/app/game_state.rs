@@ -5,6 +5,8 @@ use crate::app::App;
 pub trait GameState {
     fn new(app: &mut App<'_, Self>) -> Self where Self: Sized;
     fn update(&mut self, app: &mut App<'_, Self>) where Self: Sized;
+    // Only called when the fixed timestep mode is enabled (App::set_fixed_timestep)
+    fn fixed_update(&mut self, _app: &mut App<'_, Self>) where Self: Sized {}
     fn render(&mut self, app: &mut App<'_, Self>) where Self: Sized;
     fn handle_input(&mut self, app: &mut App<'_, Self>, event: &Event) -> bool where Self: Sized; // true if handled/can be ignored
 }
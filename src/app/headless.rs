@@ -0,0 +1,67 @@
+// Headless app
+//
+// Time and task systems without SDL, driven frame by frame. Task callbacks and sequence steps get
+// the headless app instead of the app. Used to test the scheduling logic.
+
+/* Usage
+
+let mut app = HeadlessApp::new();
+let mut log = Vec::new();
+
+let task = app.task_system.schedule_on(&app.time_system, Clock::GAME, Duration::from_millis(20), false,
+    |_, log: &mut Vec<GameTime>, app| log.push(app.time_system.game_time()));
+app.frame(&mut log);
+
+assert!(app.task_system.is_scheduled(&task));
+*/
+
+use super::{
+    sequence::SequenceHost,
+    task_system::{self, TaskHost, TaskSystem},
+    time_system::{Duration, TimeSystem},
+};
+
+pub(in crate::app) const HEADLESS_FRAME_DURATION: Duration = Duration::from_millis(16);
+
+pub(in crate::app) type HeadlessSequenceFn<S> = Box<dyn FnMut(&mut S, &mut HeadlessApp<S>)>;
+pub(in crate::app) type HeadlessSequencePredicate<S> = Box<dyn FnMut(&mut S, &mut HeadlessApp<S>) -> bool>;
+
+pub(in crate::app) struct HeadlessApp<S> {
+    pub(in crate::app) task_system: TaskSystem<'static, S, HeadlessApp<S>>,
+    pub(in crate::app) time_system: TimeSystem,
+}
+
+impl<S> HeadlessApp<S> {
+    pub(in crate::app) fn new() -> Self {
+        Self {
+            task_system: TaskSystem::new(),
+            time_system: TimeSystem::headless(),
+        }
+    }
+
+    // Advances the clocks by HEADLESS_FRAME_DURATION and runs the due tasks
+    pub(in crate::app) fn frame(&mut self, state: &mut S) {
+        let current_time = self.time_system.frame_start_time() + HEADLESS_FRAME_DURATION;
+        self.time_system.new_frame(current_time, 0);
+        task_system::run_tasks(self, state);
+    }
+}
+
+impl<S> TaskHost<'static, S> for HeadlessApp<S> {
+    fn task_systems(&mut self) -> (&mut TaskSystem<'static, S, Self>, &TimeSystem) {
+        (&mut self.task_system, &self.time_system)
+    }
+}
+
+impl<S: 'static> SequenceHost<'static, S> for HeadlessApp<S> {
+    type Run = HeadlessSequenceFn<S>;
+    type Predicate = HeadlessSequencePredicate<S>;
+
+    fn run_step(&mut self, callback: &mut HeadlessSequenceFn<S>, state: &mut S) {
+        callback(state, self);
+    }
+
+    fn check_step(&mut self, predicate: &mut HeadlessSequencePredicate<S>, state: &mut S) -> bool {
+        predicate(state, self)
+    }
+}
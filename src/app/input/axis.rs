@@ -0,0 +1,645 @@
+use crate::app::imdraw::ImDraw;
+use crate::app::sdl2::{
+    keyboard::Scancode,
+    mouse::MouseButton,
+};
+use crate::linalg::Vec2;
+
+use super::{
+    context::InputBlock,
+    mapping::ControllerBind,
+    system::{InputSystem, JoystickHatDirection},
+    touch::TouchAxis,
+};
+
+/*
+    let mut axis = Axis::new();
+    axis.add_keys(sdl2::keyboard::Scancode::A, sdl2::keyboard::Scancode::D);
+    axis.add_controller_buttons(
+        sdl2::controller::Button::DPadLeft,
+        sdl2::controller::Button::DPadRight
+    );
+    axis.add_controller_axis(sdl2::controller::Axis::LeftX);
+    axis.settings.dead_zone = 0.2;
+    axis.settings.curve = AxisCurve::Power(2.0);
+
+    input_mapping.add_axis_mapping("HORIZONTAL".to_string(), axis);
+
+    // chart editor scroll (wheel notches during the frame, clamped to [-1, 1])
+    let mut scroll_axis = Axis::new();
+    scroll_axis.add_mouse_wheel(MouseWheelAxis::Vertical);
+
+    let mut move_axis = Axis2D::new(horizontal_axis, vertical_axis);
+    move_axis.settings.dead_zone = 0.15; // radial
+
+    input_mapping.add_axis_2d_mapping("MOVE".to_string(), move_axis);
+
+    // update
+
+    let speed = input_mapping.axis("HORIZONTAL".to_string()).value();
+    let direction = input_mapping.axis_2d("MOVE".to_string()).value();
+*/
+
+// Response curve applied after the dead zone, keeping the sign
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AxisCurve {
+    Linear,
+    // |value|^exponent. Exponents > 1 give more precision near the center
+    Power(f32),
+}
+
+impl_imdraw_todo!(AxisCurve);
+
+#[derive(Copy, Clone, Debug, ImDraw)]
+pub struct AxisSettings {
+    // Values with magnitude under the dead zone are zeroed. The rest is remapped to [0, 1]
+    pub dead_zone: f32,
+    pub curve: AxisCurve,
+    pub sensitivity: f32,
+    pub inverted: bool,
+}
+
+impl Default for AxisSettings {
+    fn default() -> Self {
+        Self {
+            dead_zone: 0.0,
+            curve: AxisCurve::Linear,
+            sensitivity: 1.0,
+            inverted: false,
+        }
+    }
+}
+
+impl AxisSettings {
+    // Maps a magnitude in [0, 1]
+    fn apply_magnitude(&self, magnitude: f32) -> f32 {
+        if magnitude <= self.dead_zone {
+            return 0.0;
+        }
+
+        let magnitude = ((magnitude - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0);
+        let magnitude = match self.curve {
+            AxisCurve::Linear => magnitude,
+            AxisCurve::Power(exponent) => magnitude.powf(exponent),
+        };
+
+        (magnitude * self.sensitivity).min(1.0)
+    }
+
+    fn apply(&self, value: f32) -> f32 {
+        let value = value.signum() * self.apply_magnitude(value.abs());
+        if self.inverted { -value } else { value }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MouseWheelAxis {
+    // Positive to the right
+    Horizontal,
+    // Positive away from the user
+    Vertical,
+}
+
+impl_imdraw_todo!(MouseWheelAxis);
+
+#[derive(ImDraw)]
+pub struct Axis {
+    keys: Vec<KeyAxisInput>,
+    mouse_buttons: Vec<MouseButtonAxisInput>,
+    mouse_wheels: Vec<MouseWheelAxisInput>,
+    controller_buttons: Vec<ControllerButtonAxisInput>,
+    controller_axes: Vec<ControllerAxisAxisInput>,
+    joystick_buttons: Vec<JoystickButtonAxisInput>,
+    joystick_axes: Vec<JoystickAxisAxisInput>,
+    joystick_hats: Vec<JoystickHatAxisInput>,
+    touch_axes: Vec<TouchAxisInput>,
+
+    pub settings: AxisSettings,
+
+    raw_value: f32,
+    value: f32,
+}
+
+impl Axis {
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+            mouse_buttons: Vec::new(),
+            mouse_wheels: Vec::new(),
+            controller_buttons: Vec::new(),
+            controller_axes: Vec::new(),
+            joystick_buttons: Vec::new(),
+            joystick_axes: Vec::new(),
+            joystick_hats: Vec::new(),
+            touch_axes: Vec::new(),
+
+            settings: AxisSettings::default(),
+
+            raw_value: 0.0,
+            value: 0.0,
+        }
+    }
+
+    // Keyboard keys
+    pub fn add_keys(&mut self, negative: Scancode, positive: Scancode) {
+        if self.keys.iter().any(|elem| elem.negative == negative && elem.positive == positive) {
+            panic!("[axis add_keys] trying to add repeated keys to Axis");
+        }
+
+        self.keys.push(KeyAxisInput { negative, positive });
+    }
+
+    pub fn rem_keys(&mut self, negative: Scancode, positive: Scancode) {
+        self.keys.retain(|elem| elem.negative != negative || elem.positive != positive);
+    }
+
+    // Mouse buttons
+    pub fn add_mouse_buttons(&mut self, negative: MouseButton, positive: MouseButton) {
+        if self.mouse_buttons.iter().any(|elem| elem.negative == negative && elem.positive == positive) {
+            panic!("[axis add_mouse_buttons] trying to add repeated mouse buttons to Axis");
+        }
+
+        self.mouse_buttons.push(MouseButtonAxisInput { negative, positive });
+    }
+
+    pub fn rem_mouse_buttons(&mut self, negative: MouseButton, positive: MouseButton) {
+        self.mouse_buttons.retain(|elem| elem.negative != negative || elem.positive != positive);
+    }
+
+    // Controller buttons
+    // The controller is given by the ControllerBind of the InputMapping
+    pub fn add_controller_buttons(
+        &mut self,
+        negative: sdl2::controller::Button,
+        positive: sdl2::controller::Button,
+    ) {
+        if self.controller_buttons.iter().any(|elem| elem.negative == negative && elem.positive == positive) {
+            panic!("[axis add_controller_buttons] trying to add repeated controller buttons to Axis");
+        }
+
+        self.controller_buttons.push(ControllerButtonAxisInput { negative, positive });
+    }
+
+    pub fn rem_controller_buttons(
+        &mut self,
+        negative: sdl2::controller::Button,
+        positive: sdl2::controller::Button,
+    ) {
+        self.controller_buttons.retain(|elem| elem.negative != negative || elem.positive != positive);
+    }
+
+    // Mouse wheel
+    pub fn add_mouse_wheel(&mut self, axis: MouseWheelAxis) {
+        if self.mouse_wheels.iter().any(|elem| elem.0 == axis) {
+            panic!("[axis add_mouse_wheel] trying to add repeated mouse wheel axis to Axis");
+        }
+
+        self.mouse_wheels.push(MouseWheelAxisInput(axis));
+    }
+
+    pub fn rem_mouse_wheel(&mut self, axis: MouseWheelAxis) {
+        self.mouse_wheels.retain(|elem| elem.0 != axis);
+    }
+
+    // Controller axis
+    pub fn add_controller_axis(&mut self, axis: sdl2::controller::Axis) {
+        if self.controller_axes.iter().any(|elem| elem.0 == axis) {
+            panic!("[axis add_controller_axis] trying to add repeated controller axis to Axis");
+        }
+
+        self.controller_axes.push(ControllerAxisAxisInput(axis));
+    }
+
+    pub fn rem_controller_axis(&mut self, axis: sdl2::controller::Axis) {
+        self.controller_axes.retain(|elem| elem.0 != axis);
+    }
+
+    // Joystick buttons
+    // The joysticks are given by the joystick bind of the InputMapping
+    pub fn add_joystick_buttons(&mut self, negative: u8, positive: u8) {
+        if self.joystick_buttons.iter().any(|elem| elem.negative == negative && elem.positive == positive) {
+            panic!("[axis add_joystick_buttons] trying to add repeated joystick buttons to Axis");
+        }
+
+        self.joystick_buttons.push(JoystickButtonAxisInput { negative, positive });
+    }
+
+    pub fn rem_joystick_buttons(&mut self, negative: u8, positive: u8) {
+        self.joystick_buttons.retain(|elem| elem.negative != negative || elem.positive != positive);
+    }
+
+    // Joystick axis
+    pub fn add_joystick_axis(&mut self, axis: u8) {
+        if self.joystick_axes.iter().any(|elem| elem.0 == axis) {
+            panic!("[axis add_joystick_axis] trying to add repeated joystick axis to Axis");
+        }
+
+        self.joystick_axes.push(JoystickAxisAxisInput(axis));
+    }
+
+    pub fn rem_joystick_axis(&mut self, axis: u8) {
+        self.joystick_axes.retain(|elem| elem.0 != axis);
+    }
+
+    // Joystick hats
+    pub fn add_joystick_hat(&mut self, hat: u8, negative: JoystickHatDirection, positive: JoystickHatDirection) {
+        if self.joystick_hats.iter().any(|elem| {
+            elem.hat == hat && elem.negative == negative && elem.positive == positive
+        }) {
+            panic!("[axis add_joystick_hat] trying to add repeated joystick hat to Axis");
+        }
+
+        self.joystick_hats.push(JoystickHatAxisInput { hat, negative, positive });
+    }
+
+    pub fn rem_joystick_hat(&mut self, hat: u8, negative: JoystickHatDirection, positive: JoystickHatDirection) {
+        self.joystick_hats.retain(|elem| {
+            elem.hat != hat || elem.negative != negative || elem.positive != positive
+        });
+    }
+
+    // Touch drag and pinch
+    pub fn add_touch_axis(&mut self, axis: TouchAxis) {
+        if self.touch_axes.iter().any(|elem| elem.0 == axis) {
+            panic!("[axis add_touch_axis] trying to add repeated touch axis to Axis");
+        }
+
+        self.touch_axes.push(TouchAxisInput(axis));
+    }
+
+    pub fn rem_touch_axis(&mut self, axis: TouchAxis) {
+        self.touch_axes.retain(|elem| elem.0 != axis);
+    }
+
+    // Generic sources
+    pub fn add_source(&mut self, source: AxisSource) {
+        match source {
+            AxisSource::Keys(negative, positive) => self.add_keys(negative, positive),
+            AxisSource::MouseButtons(negative, positive) => self.add_mouse_buttons(negative, positive),
+            AxisSource::MouseWheel(axis) => self.add_mouse_wheel(axis),
+            AxisSource::ControllerButtons(negative, positive) => self.add_controller_buttons(negative, positive),
+            AxisSource::ControllerAxis(axis) => self.add_controller_axis(axis),
+            AxisSource::JoystickButtons(negative, positive) => self.add_joystick_buttons(negative, positive),
+            AxisSource::JoystickAxis(axis) => self.add_joystick_axis(axis),
+            AxisSource::JoystickHat(hat, negative, positive) => self.add_joystick_hat(hat, negative, positive),
+            AxisSource::Touch(axis) => self.add_touch_axis(axis),
+        }
+    }
+
+    pub fn rem_source(&mut self, source: AxisSource) {
+        match source {
+            AxisSource::Keys(negative, positive) => self.rem_keys(negative, positive),
+            AxisSource::MouseButtons(negative, positive) => self.rem_mouse_buttons(negative, positive),
+            AxisSource::MouseWheel(axis) => self.rem_mouse_wheel(axis),
+            AxisSource::ControllerButtons(negative, positive) => self.rem_controller_buttons(negative, positive),
+            AxisSource::ControllerAxis(axis) => self.rem_controller_axis(axis),
+            AxisSource::JoystickButtons(negative, positive) => self.rem_joystick_buttons(negative, positive),
+            AxisSource::JoystickAxis(axis) => self.rem_joystick_axis(axis),
+            AxisSource::JoystickHat(hat, negative, positive) => self.rem_joystick_hat(hat, negative, positive),
+            AxisSource::Touch(axis) => self.rem_touch_axis(axis),
+        }
+    }
+
+    pub fn has_source(&self, source: AxisSource) -> bool {
+        self.sources().contains(&source)
+    }
+
+    pub fn sources(&self) -> Vec<AxisSource> {
+        let mut sources = Vec::new();
+
+        sources.extend(self.keys.iter().map(|elem| AxisSource::Keys(elem.negative, elem.positive)));
+        sources.extend(self.mouse_buttons.iter().map(|elem| {
+            AxisSource::MouseButtons(elem.negative, elem.positive)
+        }));
+        sources.extend(self.mouse_wheels.iter().map(|elem| AxisSource::MouseWheel(elem.0)));
+        sources.extend(self.controller_buttons.iter().map(|elem| {
+            AxisSource::ControllerButtons(elem.negative, elem.positive)
+        }));
+        sources.extend(self.controller_axes.iter().map(|elem| AxisSource::ControllerAxis(elem.0)));
+        sources.extend(self.joystick_buttons.iter().map(|elem| {
+            AxisSource::JoystickButtons(elem.negative, elem.positive)
+        }));
+        sources.extend(self.joystick_axes.iter().map(|elem| AxisSource::JoystickAxis(elem.0)));
+        sources.extend(self.joystick_hats.iter().map(|elem| {
+            AxisSource::JoystickHat(elem.hat, elem.negative, elem.positive)
+        }));
+        sources.extend(self.touch_axes.iter().map(|elem| AxisSource::Touch(elem.0)));
+
+        sources
+    }
+
+    pub(super) fn update(
+        &mut self,
+        input_system: &InputSystem,
+        controller_bind: ControllerBind,
+        joystick_bind: ControllerBind,
+        block: &InputBlock
+    ) {
+        // Multiple sources per axis logic
+        // The source with the greatest magnitude wins, so a stick resting near the center doesn't
+        // cancel a key being held
+        let mut raw_value = 0.0f32;
+        let mut add_value = |value: f32| {
+            if value.abs() > raw_value.abs() {
+                raw_value = value;
+            }
+        };
+
+        let digital = |negative: bool, positive: bool| (positive as i32 - negative as i32) as f32;
+
+        // Keyboard keys
+        for key in self.keys.iter() {
+            if block.blocks_axis(AxisSource::Keys(key.negative, key.positive)) {
+                continue;
+            }
+
+            let negative = input_system.devices.keyboard.button_state(key.negative).down;
+            let positive = input_system.devices.keyboard.button_state(key.positive).down;
+            add_value(digital(negative, positive));
+        }
+
+        // Mouse buttons
+        for button in self.mouse_buttons.iter() {
+            if block.blocks_axis(AxisSource::MouseButtons(button.negative, button.positive)) {
+                continue;
+            }
+
+            let negative = input_system.devices.mouse.button_state(button.negative).down;
+            let positive = input_system.devices.mouse.button_state(button.positive).down;
+            add_value(digital(negative, positive));
+        }
+
+        // Mouse wheel
+        for wheel in self.mouse_wheels.iter() {
+            if block.blocks_axis(AxisSource::MouseWheel(wheel.0)) {
+                continue;
+            }
+
+            let (x, y) = input_system.devices.mouse.get_wheel();
+            let value = match wheel.0 {
+                MouseWheelAxis::Horizontal => x,
+                MouseWheelAxis::Vertical => y,
+            };
+            add_value(value as f32);
+        }
+
+        // Controller buttons
+        for button in self.controller_buttons.iter() {
+            if block.blocks_axis(AxisSource::ControllerButtons(button.negative, button.positive)) {
+                continue;
+            }
+
+            for controller_state in input_system.bound_controllers(controller_bind) {
+                let negative = controller_state.button_state(button.negative).down;
+                let positive = controller_state.button_state(button.positive).down;
+                add_value(digital(negative, positive));
+            }
+        }
+
+        // Controller axis
+        for axis in self.controller_axes.iter() {
+            if block.blocks_axis(AxisSource::ControllerAxis(axis.0)) {
+                continue;
+            }
+
+            for controller_state in input_system.bound_controllers(controller_bind) {
+                add_value(controller_state.axis_state(axis.0).value);
+            }
+        }
+
+        // Joystick buttons
+        for button in self.joystick_buttons.iter() {
+            if block.blocks_axis(AxisSource::JoystickButtons(button.negative, button.positive)) {
+                continue;
+            }
+
+            for joystick_state in input_system.bound_joysticks(joystick_bind) {
+                let negative = joystick_state.button_state(button.negative).down;
+                let positive = joystick_state.button_state(button.positive).down;
+                add_value(digital(negative, positive));
+            }
+        }
+
+        // Joystick axis
+        for axis in self.joystick_axes.iter() {
+            if block.blocks_axis(AxisSource::JoystickAxis(axis.0)) {
+                continue;
+            }
+
+            for joystick_state in input_system.bound_joysticks(joystick_bind) {
+                add_value(joystick_state.axis_state(axis.0).value);
+            }
+        }
+
+        // Joystick hats
+        for hat in self.joystick_hats.iter() {
+            if block.blocks_axis(AxisSource::JoystickHat(hat.hat, hat.negative, hat.positive)) {
+                continue;
+            }
+
+            for joystick_state in input_system.bound_joysticks(joystick_bind) {
+                let negative = joystick_state.hat_state(hat.hat, hat.negative).down;
+                let positive = joystick_state.hat_state(hat.hat, hat.positive).down;
+                add_value(digital(negative, positive));
+            }
+        }
+
+        // Touch
+        for axis in self.touch_axes.iter() {
+            if block.blocks_axis(AxisSource::Touch(axis.0)) {
+                continue;
+            }
+
+            add_value(input_system.devices.touch.axis_value(axis.0));
+        }
+
+        self.set_raw_value(raw_value);
+    }
+
+    fn set_raw_value(&mut self, raw_value: f32) {
+        self.raw_value = raw_value.clamp(-1.0, 1.0);
+        self.value = self.settings.apply(self.raw_value);
+    }
+
+    // Value in [-1, 1] after the dead zone, curve, sensitivity and inversion
+    pub fn value(&self) -> f32 { self.value }
+
+    // Value in [-1, 1] before any processing
+    pub fn raw_value(&self) -> f32 { self.raw_value }
+}
+
+// 2D axis built from two axes. The axes settings are ignored except for inversion: the dead zone
+// is radial (applied to the vector magnitude) to avoid snapping diagonals to the main directions
+#[derive(ImDraw)]
+pub struct Axis2D {
+    pub x: Axis,
+    pub y: Axis,
+
+    pub settings: AxisSettings,
+
+    value: Vec2,
+}
+
+impl Axis2D {
+    pub fn new(x: Axis, y: Axis) -> Self {
+        Self {
+            x,
+            y,
+            settings: AxisSettings::default(),
+            value: Vec2::new(),
+        }
+    }
+
+    pub(super) fn update(
+        &mut self,
+        input_system: &InputSystem,
+        controller_bind: ControllerBind,
+        joystick_bind: ControllerBind,
+        block: &InputBlock
+    ) {
+        self.x.update(input_system, controller_bind, joystick_bind, block);
+        self.y.update(input_system, controller_bind, joystick_bind, block);
+
+        let invert = |axis: &Axis| if axis.settings.inverted { -axis.raw_value } else { axis.raw_value };
+        self.set_raw_value(Vec2 { x: invert(&self.x), y: invert(&self.y) });
+    }
+
+    fn set_raw_value(&mut self, raw_value: Vec2) {
+        let magnitude = raw_value.mag();
+        if magnitude == 0.0 {
+            self.value = Vec2::new();
+            return;
+        }
+
+        let new_magnitude = self.settings.apply_magnitude(magnitude.min(1.0));
+        let new_magnitude = if self.settings.inverted { -new_magnitude } else { new_magnitude };
+
+        self.value = raw_value * (new_magnitude / magnitude);
+    }
+
+    // Vector with magnitude in [0, 1]
+    pub fn value(&self) -> Vec2 { self.value }
+}
+
+// Physical input that can be mapped to an Axis. Pairs are (negative, positive)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AxisSource {
+    Keys(Scancode, Scancode),
+    MouseButtons(MouseButton, MouseButton),
+    MouseWheel(MouseWheelAxis),
+    ControllerButtons(sdl2::controller::Button, sdl2::controller::Button),
+    ControllerAxis(sdl2::controller::Axis),
+    JoystickButtons(u8, u8),
+    JoystickAxis(u8),
+    JoystickHat(u8, JoystickHatDirection, JoystickHatDirection),
+    Touch(TouchAxis),
+}
+
+impl_imdraw_todo!(AxisSource);
+
+#[derive(ImDraw)]
+struct KeyAxisInput {
+    negative: Scancode,
+    positive: Scancode,
+}
+
+#[derive(ImDraw)]
+struct MouseButtonAxisInput {
+    negative: MouseButton,
+    positive: MouseButton,
+}
+
+#[derive(ImDraw)]
+struct MouseWheelAxisInput(MouseWheelAxis);
+
+#[derive(ImDraw)]
+struct ControllerButtonAxisInput {
+    negative: sdl2::controller::Button,
+    positive: sdl2::controller::Button,
+}
+
+#[derive(ImDraw)]
+struct ControllerAxisAxisInput(sdl2::controller::Axis);
+
+#[derive(ImDraw)]
+struct JoystickButtonAxisInput {
+    negative: u8,
+    positive: u8,
+}
+
+#[derive(ImDraw)]
+struct JoystickAxisAxisInput(u8);
+
+#[derive(ImDraw)]
+struct JoystickHatAxisInput {
+    hat: u8,
+    negative: JoystickHatDirection,
+    positive: JoystickHatDirection,
+}
+
+#[derive(ImDraw)]
+struct TouchAxisInput(TouchAxis);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_axis_keys() {
+        let mut axis = Axis::new();
+
+        axis.add_keys(Scancode::A, Scancode::D);
+        axis.add_keys(Scancode::Left, Scancode::Right);
+
+        assert_eq!(axis.keys.len(), 2);
+
+        axis.rem_keys(Scancode::A, Scancode::D);
+
+        assert_eq!(axis.keys.len(), 1);
+    }
+
+    #[test]
+    fn test_axis_settings() {
+        let mut axis = Axis::new();
+        axis.settings.dead_zone = 0.2;
+
+        axis.set_raw_value(0.1);
+        assert_eq!(axis.value(), 0.0);
+
+        axis.set_raw_value(-0.6);
+        assert!((axis.value() - -0.5).abs() < 1e-6);
+
+        axis.settings.curve = AxisCurve::Power(2.0);
+        axis.set_raw_value(0.6);
+        assert!((axis.value() - 0.25).abs() < 1e-6);
+
+        axis.settings.inverted = true;
+        axis.set_raw_value(1.0);
+        assert_eq!(axis.value(), -1.0);
+        assert_eq!(axis.raw_value(), 1.0);
+    }
+
+    #[test]
+    fn test_axis_2d_radial_dead_zone() {
+        let mut axis = Axis2D::new(Axis::new(), Axis::new());
+        axis.settings.dead_zone = 0.5;
+
+        // outside the dead zone (magnitude 0.57) even if both components alone are inside it
+        assert_eq!(axis.settings.apply(0.4), 0.0);
+        axis.set_raw_value(Vec2 { x: 0.4, y: 0.4 });
+        assert!(axis.value().x > 0.0);
+        assert_eq!(axis.value().x, axis.value().y);
+
+        axis.set_raw_value(Vec2 { x: 0.3, y: 0.3 });
+        assert_eq!(axis.value(), Vec2::new());
+
+        // direction is kept
+        axis.set_raw_value(Vec2 { x: 0.0, y: -0.75 });
+        assert_eq!(axis.value().x, 0.0);
+        assert!((axis.value().y - -0.5).abs() < 1e-6);
+
+        // magnitude is clamped to 1
+        axis.set_raw_value(Vec2 { x: 1.0, y: 1.0 });
+        assert!((axis.value().mag() - 1.0).abs() < 1e-6);
+    }
+}
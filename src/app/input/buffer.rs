@@ -0,0 +1,272 @@
+// Input buffer
+//
+// Each mapping remembers the last button presses (with their event timestamps). Presses stay
+// buffered for a configurable window, so an action pressed slightly early still triggers when it
+// becomes possible (jump before landing). The buffer is also used to detect chords (buttons pressed
+// together) and ordered sequences (slide notes, cheat codes).
+//
+// Single button gestures (double tap, hold and release) are detected by the Button itself.
+
+/* Usage
+
+input_mapping.set_input_buffer_window(Duration::from_millis(150));
+
+// update
+
+app.update_input_mapping(&mut self.input_mapping);
+
+if self.grounded && self.input_mapping.consume_buffered_press("JUMP") {
+    // jump
+}
+
+// "double" note: both lanes hit within 40ms
+if self.input_mapping.chord_pressed(&["LANE_1", "LANE_2"], Duration::from_millis(40)) {}
+
+// "slide" note: lanes hit in order, less than 100ms apart
+if self.input_mapping.sequence_pressed(&["LANE_1", "LANE_2", "LANE_3"], Duration::from_millis(100)) {}
+
+// cheat code
+if self.input_mapping.sequence_pressed(&["UP", "UP", "DOWN", "DOWN"], Duration::from_millis(500)) {}
+*/
+
+use std::collections::BTreeMap;
+
+use crate::app::{
+    imdraw::ImDraw,
+    time_system::{Duration, GameTime},
+};
+
+use super::{
+    button::Button,
+    mapping::InputMapping,
+};
+
+const DEFAULT_INPUT_BUFFER_WINDOW: Duration = Duration::from_millis(150);
+
+// Presses kept for the sequences, independently of the window
+const MAX_BUFFERED_PRESSES: usize = 32;
+
+#[derive(ImDraw)]
+struct BufferedPress {
+    button: String,
+    timestamp: GameTime,
+    // Frame time of the update that buffered it
+    update_timestamp: GameTime,
+    consumed: bool,
+}
+
+#[derive(ImDraw)]
+pub(super) struct InputBuffer {
+    window: Duration,
+    // Ordered by timestamp
+    presses: Vec<BufferedPress>,
+    update_timestamp: GameTime,
+}
+
+impl InputBuffer {
+    pub(super) fn new() -> Self {
+        Self {
+            window: DEFAULT_INPUT_BUFFER_WINDOW,
+            presses: Vec::new(),
+            update_timestamp: GameTime::default(),
+        }
+    }
+
+    pub(super) fn update(&mut self, buttons: &BTreeMap<String, Button>, timestamp: GameTime) {
+        self.update_timestamp = timestamp;
+
+        for (name, button) in buttons.iter() {
+            if !button.pressed() {
+                continue;
+            }
+
+            // Pressed stays set if the mapping is updated more than once per frame
+            let buffered = self.presses.iter()
+                .any(|press| press.button == *name && press.timestamp == button.timestamp());
+
+            if !buffered {
+                self.presses.push(BufferedPress {
+                    button: name.clone(),
+                    timestamp: button.timestamp(),
+                    update_timestamp: timestamp,
+                    consumed: false,
+                });
+            }
+        }
+
+        self.presses.sort_by_key(|press| press.timestamp);
+
+        if self.presses.len() > MAX_BUFFERED_PRESSES {
+            let excess = self.presses.len() - MAX_BUFFERED_PRESSES;
+            self.presses.drain(..excess);
+        }
+    }
+
+    fn is_new(&self, press: &BufferedPress) -> bool {
+        press.update_timestamp == self.update_timestamp
+    }
+
+    fn in_window(&self, press: &BufferedPress) -> bool {
+        self.update_timestamp - press.timestamp <= self.window
+    }
+
+    fn last_buffered_press(&self, button: &str) -> Option<usize> {
+        self.presses.iter()
+            .rposition(|press| press.button == button && !press.consumed && self.in_window(press))
+    }
+
+    fn chord_pressed(&self, buttons: &[&str], max_spread: Duration) -> bool {
+        let presses: Option<Vec<&BufferedPress>> = buttons.iter()
+            .map(|button| self.last_buffered_press(button).map(|index| &self.presses[index]))
+            .collect();
+
+        let presses = match presses {
+            Some(presses) if !presses.is_empty() => presses,
+            _ => return false,
+        };
+
+        let first = presses.iter().map(|press| press.timestamp).min().unwrap();
+        let last = presses.iter().max_by_key(|press| press.timestamp).unwrap();
+
+        self.is_new(last) && last.timestamp - first <= max_spread
+    }
+
+    fn sequence_pressed(&self, buttons: &[&str], max_gap: Duration) -> bool {
+        if buttons.is_empty() || buttons.len() > self.presses.len() {
+            return false;
+        }
+
+        let presses = &self.presses[self.presses.len() - buttons.len()..];
+        if !self.is_new(presses.last().unwrap()) {
+            return false;
+        }
+
+        let in_order = presses.iter().zip(buttons.iter()).all(|(press, button)| press.button == *button);
+        let in_time = presses.windows(2).all(|pair| pair[1].timestamp - pair[0].timestamp <= max_gap);
+
+        in_order && in_time
+    }
+}
+
+impl InputMapping {
+    // How long presses can be consumed after they happen
+    pub fn set_input_buffer_window(&mut self, window: Duration) {
+        self.buffer.window = window;
+    }
+
+    pub fn input_buffer_window(&self) -> Duration {
+        self.buffer.window
+    }
+
+    // Pressed inside the buffer window and not consumed
+    pub fn buffered_press(&self, button: &str) -> bool {
+        self.buffer.last_buffered_press(button).is_some()
+    }
+
+    // Returns if there was a buffered press to consume
+    pub fn consume_buffered_press(&mut self, button: &str) -> bool {
+        match self.buffer.last_buffered_press(button) {
+            Some(index) => {
+                self.buffer.presses[index].consumed = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // All the buttons pressed at most max_spread apart, the last one this frame. The presses have
+    // to be in the buffer window and not consumed
+    pub fn chord_pressed(&self, buttons: &[&str], max_spread: Duration) -> bool {
+        self.buffer.chord_pressed(buttons, max_spread)
+    }
+
+    // The last presses of the mapping are the buttons in order, each one at most max_gap after the
+    // previous one and the last one this frame. Any other press in between breaks the sequence
+    pub fn sequence_pressed(&self, buttons: &[&str], max_gap: Duration) -> bool {
+        self.buffer.sequence_pressed(buttons, max_gap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> GameTime {
+        GameTime::from_micros(ms * 1_000)
+    }
+
+    fn mapping() -> InputMapping {
+        let mut mapping = InputMapping::new();
+        for name in ["A", "B", "C"].iter() {
+            mapping.add_button_mapping(name.to_string(), Button::new());
+        }
+
+        mapping
+    }
+
+    // Presses the buttons with their event times and updates the buffer at the frame time
+    fn update(mapping: &mut InputMapping, presses: &[(&str, u64)], frame: u64) {
+        for (name, button) in mapping.button_mapping.iter_mut() {
+            match presses.iter().find(|(pressed, _)| pressed == name) {
+                Some(&(_, time)) => button.update_state(true, ms(time), ms(0), ms(frame)),
+                None => button.update_state(false, ms(0), ms(frame), ms(frame)),
+            }
+        }
+
+        mapping.buffer.update(&mapping.button_mapping, ms(frame));
+    }
+
+    #[test]
+    fn test_input_buffer_consume() {
+        let mut mapping = mapping();
+        mapping.set_input_buffer_window(Duration::from_millis(100));
+
+        update(&mut mapping, &[("A", 10)], 16);
+        update(&mut mapping, &[], 32);
+        assert!(mapping.buffered_press("A"));
+        assert!(!mapping.buffered_press("B"));
+
+        assert!(mapping.consume_buffered_press("A"));
+        assert!(!mapping.consume_buffered_press("A"));
+
+        update(&mut mapping, &[("A", 40)], 48);
+        update(&mut mapping, &[], 160);
+        assert!(!mapping.buffered_press("A"));
+    }
+
+    #[test]
+    fn test_input_buffer_chord() {
+        let mut mapping = mapping();
+        let spread = Duration::from_millis(40);
+
+        update(&mut mapping, &[("A", 10)], 16);
+        assert!(!mapping.chord_pressed(&["A", "B"], spread));
+
+        update(&mut mapping, &[("B", 30)], 32);
+        assert!(mapping.chord_pressed(&["A", "B"], spread));
+        assert!(!mapping.chord_pressed(&["A", "B"], Duration::from_millis(10)));
+
+        // only on the frame the chord completes
+        update(&mut mapping, &[], 48);
+        assert!(!mapping.chord_pressed(&["A", "B"], spread));
+    }
+
+    #[test]
+    fn test_input_buffer_sequence() {
+        let mut mapping = mapping();
+        let gap = Duration::from_millis(100);
+
+        update(&mut mapping, &[("A", 10)], 16);
+        update(&mut mapping, &[("B", 50)], 64);
+        update(&mut mapping, &[("C", 120)], 128);
+        assert!(mapping.sequence_pressed(&["A", "B", "C"], gap));
+        assert!(mapping.sequence_pressed(&["B", "C"], gap));
+        assert!(!mapping.sequence_pressed(&["B", "A", "C"], gap));
+        assert!(!mapping.sequence_pressed(&["A", "B", "C"], Duration::from_millis(50)));
+
+        // broken by another press
+        update(&mut mapping, &[("A", 200)], 208);
+        update(&mut mapping, &[("C", 230)], 240);
+        assert!(!mapping.sequence_pressed(&["A", "B", "C"], gap));
+    }
+}
@@ -1,5 +1,8 @@
 use std::cmp::max;
-use crate::app::imdraw::ImDraw;
+use crate::app::{
+    imdraw::ImDraw,
+    time_system::{Duration, GameTime},
+};
 use crate::app::sdl2::{
     keyboard::Scancode,
     mouse::MouseButton,
@@ -7,25 +10,40 @@ use crate::app::sdl2::{
 
 use super::{
     ControllerAxisThreshold,
-    system::InputSystem,
+    context::InputBlock,
+    mapping::ControllerBind,
+    system::{InputSystem, JoystickHatDirection},
+    touch::TouchGesture,
 };
 
 /*
     let mut button = Button::new();
     button.add_key(sdl2::keyboard::Scancode::W);
-    button.add_controller_button(0, sdl2::controller::Button::DPadUp);
+    button.add_controller_button(sdl2::controller::Button::DPadUp);
     button.add_controller_axis(
-        0,
         sdl2::controller::Axis::LeftY,
         ControllerAxisThreshold::lesser_than(-0.5)
     );
 
+    // raw joysticks (devices without a game controller mapping), given by the joystick bind
+    button.add_joystick_button(3);
+    button.add_joystick_hat(0, JoystickHatDirection::Up);
+    button.add_joystick_axis(1, ControllerAxisThreshold::lesser_than(-0.5));
+
+    // touch gestures (taps and swipes are down for a single frame)
+    button.add_touch_gesture(TouchGesture::Tap);
+
     input_mapping.add_button_mapping("UP".to_string(), button);
 
     let down = button.down();
     let pressed = button.pressed();
     let released = button.released();
-    let long_press = button.pressed_for(1_000_000, app.time_system.game_time);
+    let long_press = button.pressed_for(Duration::from_secs(1), app.game_time());
+    let double_tap = button.double_tapped(Duration::from_millis(250));
+    let charged = button.hold_released(Duration::from_millis(500));
+
+    // time of the input event (inside the last frame), e.g. for rhythm judgement
+    let hit_time = button.timestamp();
 
     if down { println!("down!"); }
     if pressed { println!("pressed!"); }
@@ -39,13 +57,27 @@ pub struct Button {
     mouse_buttons: Vec<MouseButtonInput>,
     controller_buttons: Vec<ControllerButtonInput>,
     controller_axes: Vec<ControllerAxisInput>,
+    joystick_buttons: Vec<JoystickButtonInput>,
+    joystick_axes: Vec<JoystickAxisInput>,
+    joystick_hats: Vec<JoystickHatInput>,
+    touch_gestures: Vec<TouchGestureInput>,
 
     // @TODO bitflags
     down: bool,
     pressed: bool,
     released: bool,
 
-    timestamp: u64,
+    // Time of the last transition, from the input event timestamps
+    timestamp: GameTime,
+
+    // Last two presses, for double taps and hold durations
+    press_timestamp: Option<GameTime>,
+    previous_press_timestamp: Option<GameTime>,
+
+    // Frame time of the current and previous updates. Transitions after the previous update are
+    // reported as pressed/released
+    update_timestamp: GameTime,
+    previous_update_timestamp: GameTime,
 }
 
 impl Button {
@@ -56,11 +88,19 @@ impl Button {
             mouse_buttons: Vec::new(),
             controller_buttons: Vec::new(),
             controller_axes: Vec::new(),
+            joystick_buttons: Vec::new(),
+            joystick_axes: Vec::new(),
+            joystick_hats: Vec::new(),
+            touch_gestures: Vec::new(),
 
             down: false,
             pressed: false,
             released: false,
-            timestamp: 0u64,
+            timestamp: GameTime::default(),
+            press_timestamp: None,
+            previous_press_timestamp: None,
+            update_timestamp: GameTime::default(),
+            previous_update_timestamp: GameTime::default(),
         }
     }
 
@@ -93,91 +133,241 @@ impl Button {
     }
 
     // Controller buttons
-    // @TODO add the controller id to the input mapping instead of the specific button
-    pub fn add_controller_button(&mut self, controller_index: usize, button: sdl2::controller::Button) {
-        if self.controller_buttons.iter().any(|elem| {
-            elem.controller_index == controller_index && elem.button == button
-        }) {
+    // The controller is given by the ControllerBind of the InputMapping
+    pub fn add_controller_button(&mut self, button: sdl2::controller::Button) {
+        if self.controller_buttons.iter().any(|elem| elem.0 == button) {
             panic!("[button add_controller_button] trying to add repeated controller button to Button");
         }
 
-        self.controller_buttons.push(ControllerButtonInput{ controller_index, button });
+        self.controller_buttons.push(ControllerButtonInput(button));
     }
 
-    pub fn rem_controller_button(&mut self, controller_index: usize, button: sdl2::controller::Button) {
-        self.controller_buttons.retain(|elem| {
-            elem.controller_index != controller_index || elem.button != button
-        });
+    pub fn rem_controller_button(&mut self, button: sdl2::controller::Button) {
+        self.controller_buttons.retain(|elem| elem.0 != button);
     }
 
     // Controller axis
     pub fn add_controller_axis(
         &mut self,
-        controller_index: usize,
         axis: sdl2::controller::Axis,
         threshold: ControllerAxisThreshold,
     ) {
         if self.controller_axes.iter().any(|elem| {
-            elem.controller_index == controller_index &&
             elem.axis == axis &&
             elem.threshold.direction == threshold.direction
         }) {
-            panic!("[button add_controller_button] trying to add repeated controller button to Button");
+            panic!("[button add_controller_axis] trying to add repeated controller axis to Button");
         }
 
         self.controller_axes.push(
             ControllerAxisInput {
-                controller_index,
                 axis,
                 threshold,
 
                 last_update_value: false,
-                last_change_timestamp: 0,
+                last_change_timestamp: GameTime::default(),
             }
         );
     }
 
-    pub fn rem_controller_axis(&mut self, controller_index: usize, axis: sdl2::controller::Axis) {
-        self.controller_axes.retain(|elem| {
-            elem.controller_index != controller_index || elem.axis != axis
-        });
+    pub fn rem_controller_axis(&mut self, axis: sdl2::controller::Axis) {
+        self.controller_axes.retain(|elem| elem.axis != axis);
     }
 
-    pub(super) fn update(&mut self, input_system: &InputSystem, timestamp: u64) {
-        let mut last_pressed  = 0u64;
-        let mut last_released = 0u64;
+    // Joystick buttons
+    // The joysticks are given by the joystick bind of the InputMapping
+    pub fn add_joystick_button(&mut self, button: u8) {
+        if self.joystick_buttons.iter().any(|elem| elem.0 == button) {
+            panic!("[button add_joystick_button] trying to add repeated joystick button to Button");
+        }
+
+        self.joystick_buttons.push(JoystickButtonInput(button));
+    }
+
+    pub fn rem_joystick_button(&mut self, button: u8) {
+        self.joystick_buttons.retain(|elem| elem.0 != button);
+    }
+
+    // Joystick axis
+    pub fn add_joystick_axis(&mut self, axis: u8, threshold: ControllerAxisThreshold) {
+        if self.joystick_axes.iter().any(|elem| {
+            elem.axis == axis && elem.threshold.direction == threshold.direction
+        }) {
+            panic!("[button add_joystick_axis] trying to add repeated joystick axis to Button");
+        }
+
+        self.joystick_axes.push(
+            JoystickAxisInput {
+                axis,
+                threshold,
+
+                last_update_value: false,
+                last_change_timestamp: GameTime::default(),
+            }
+        );
+    }
+
+    pub fn rem_joystick_axis(&mut self, axis: u8) {
+        self.joystick_axes.retain(|elem| elem.axis != axis);
+    }
+
+    // Joystick hats
+    // Diagonals press both directions
+    pub fn add_joystick_hat(&mut self, hat: u8, direction: JoystickHatDirection) {
+        if self.joystick_hats.iter().any(|elem| elem.hat == hat && elem.direction == direction) {
+            panic!("[button add_joystick_hat] trying to add repeated joystick hat to Button");
+        }
+
+        self.joystick_hats.push(JoystickHatInput { hat, direction });
+    }
+
+    pub fn rem_joystick_hat(&mut self, hat: u8, direction: JoystickHatDirection) {
+        self.joystick_hats.retain(|elem| elem.hat != hat || elem.direction != direction);
+    }
+
+    // Touch gestures
+    pub fn add_touch_gesture(&mut self, gesture: TouchGesture) {
+        if self.touch_gestures.iter().any(|elem| elem.0 == gesture) {
+            panic!("[button add_touch_gesture] trying to add repeated touch gesture to Button");
+        }
+
+        self.touch_gestures.push(TouchGestureInput(gesture));
+    }
+
+    pub fn rem_touch_gesture(&mut self, gesture: TouchGesture) {
+        self.touch_gestures.retain(|elem| elem.0 != gesture);
+    }
+
+    // Generic sources (used when rebinding)
+    pub fn add_source(&mut self, source: ButtonSource) {
+        match source {
+            ButtonSource::Key(code) => self.add_key(code),
+            ButtonSource::MouseButton(button) => self.add_mouse_button(button),
+            ButtonSource::ControllerButton(button) => self.add_controller_button(button),
+            ButtonSource::ControllerAxis(axis, threshold) => self.add_controller_axis(axis, threshold),
+            ButtonSource::JoystickButton(button) => self.add_joystick_button(button),
+            ButtonSource::JoystickAxis(axis, threshold) => self.add_joystick_axis(axis, threshold),
+            ButtonSource::JoystickHat(hat, direction) => self.add_joystick_hat(hat, direction),
+            ButtonSource::TouchGesture(gesture) => self.add_touch_gesture(gesture),
+        }
+    }
+
+    pub fn rem_source(&mut self, source: ButtonSource) {
+        match source {
+            ButtonSource::Key(code) => self.rem_key(code),
+            ButtonSource::MouseButton(button) => self.rem_mouse_button(button),
+            ButtonSource::ControllerButton(button) => self.rem_controller_button(button),
+            ButtonSource::ControllerAxis(axis, threshold) => {
+                self.controller_axes.retain(|elem| {
+                    elem.axis != axis || elem.threshold.direction != threshold.direction
+                });
+            },
+            ButtonSource::JoystickButton(button) => self.rem_joystick_button(button),
+            ButtonSource::JoystickAxis(axis, threshold) => {
+                self.joystick_axes.retain(|elem| {
+                    elem.axis != axis || elem.threshold.direction != threshold.direction
+                });
+            },
+            ButtonSource::JoystickHat(hat, direction) => self.rem_joystick_hat(hat, direction),
+            ButtonSource::TouchGesture(gesture) => self.rem_touch_gesture(gesture),
+        }
+    }
+
+    pub fn has_source(&self, source: ButtonSource) -> bool {
+        self.sources().iter().any(|elem| elem.same_input(&source))
+    }
+
+    pub fn sources(&self) -> Vec<ButtonSource> {
+        let mut sources = Vec::new();
+
+        sources.extend(self.keys.iter().map(|elem| ButtonSource::Key(elem.0)));
+        sources.extend(self.mouse_buttons.iter().map(|elem| ButtonSource::MouseButton(elem.0)));
+        sources.extend(self.controller_buttons.iter().map(|elem| ButtonSource::ControllerButton(elem.0)));
+        sources.extend(self.controller_axes.iter().map(|elem| {
+            ButtonSource::ControllerAxis(elem.axis, elem.threshold)
+        }));
+        sources.extend(self.joystick_buttons.iter().map(|elem| ButtonSource::JoystickButton(elem.0)));
+        sources.extend(self.joystick_axes.iter().map(|elem| {
+            ButtonSource::JoystickAxis(elem.axis, elem.threshold)
+        }));
+        sources.extend(self.joystick_hats.iter().map(|elem| ButtonSource::JoystickHat(elem.hat, elem.direction)));
+        sources.extend(self.touch_gestures.iter().map(|elem| ButtonSource::TouchGesture(elem.0)));
+
+        sources
+    }
+
+    // Removes all the sources
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.mouse_buttons.clear();
+        self.controller_buttons.clear();
+        self.controller_axes.clear();
+        self.joystick_buttons.clear();
+        self.joystick_axes.clear();
+        self.joystick_hats.clear();
+        self.touch_gestures.clear();
+    }
+
+    pub(super) fn update(
+        &mut self,
+        input_system: &InputSystem,
+        controller_bind: ControllerBind,
+        joystick_bind: ControllerBind,
+        block: &InputBlock,
+        timestamp: GameTime
+    ) {
+        let mut last_pressed  = GameTime::default();
+        let mut last_released = GameTime::default();
 
         let mut total_down = 0;
 
         // Keyboard keys
         for key in self.keys.iter() {
-            let key_state = input_system.keyboard.button_state(key.0);
+            if block.blocks_button(ButtonSource::Key(key.0)) {
+                continue;
+            }
+
+            let key_state = input_system.devices.keyboard.button_state(key.0);
 
             total_down += key_state.down as i32;
-            last_pressed = max(last_pressed, (key_state.down as u64) * key_state.timestamp);
-            last_released = max(last_released, (!key_state.down as u64) * key_state.timestamp);
+            if key_state.down {
+                last_pressed = max(last_pressed, key_state.timestamp);
+            } else {
+                last_released = max(last_released, key_state.timestamp);
+            }
         }
 
         // Mouse buttons
         for button in self.mouse_buttons.iter() {
-            let button_state = input_system.mouse.button_state(button.0);
+            if block.blocks_button(ButtonSource::MouseButton(button.0)) {
+                continue;
+            }
+
+            let button_state = input_system.devices.mouse.button_state(button.0);
 
             total_down += button_state.down as i32;
-            last_pressed = max(last_pressed, (button_state.down as u64) * button_state.timestamp);
-            last_released = max(last_released, (!button_state.down as u64) * button_state.timestamp);
+            if button_state.down {
+                last_pressed = max(last_pressed, button_state.timestamp);
+            } else {
+                last_released = max(last_released, button_state.timestamp);
+            }
         }
 
         // Controller buttons
         for button in self.controller_buttons.iter() {
-            match input_system.controllers.controller_state(button.controller_index) {
-                Some(controller_state) => {
-                    let button_state = controller_state.button_state(button.button);
-
-                    total_down += button_state.down as i32;
-                    last_pressed = max(last_pressed, (button_state.down as u64) * button_state.timestamp);
-                    last_released = max(last_released, (!button_state.down as u64) * button_state.timestamp);
-                },
-                None => {}
+            if block.blocks_button(ButtonSource::ControllerButton(button.0)) {
+                continue;
+            }
+
+            for controller_state in input_system.bound_controllers(controller_bind) {
+                let button_state = controller_state.button_state(button.0);
+
+                total_down += button_state.down as i32;
+                if button_state.down {
+                    last_pressed = max(last_pressed, button_state.timestamp);
+                } else {
+                    last_released = max(last_released, button_state.timestamp);
+                }
             }
         }
 
@@ -186,26 +376,129 @@ impl Button {
         // threshold. We can't put this logic in the axis itself since it can be used in multiple
         // buttons with different thresholds
         for axis in self.controller_axes.iter_mut() {
-            match input_system.controllers.controller_state(axis.controller_index) {
-                Some(controller_state) => {
-                    let axis_state = controller_state.axis_state(axis.axis);
-
-                    let axis_down = axis_state.pressed(axis.threshold);
-
-                    let changed_state = axis_down ^ axis.last_update_value;
-                    if changed_state {
-                        axis.last_change_timestamp = timestamp;
-                    }
-                    axis.last_update_value = axis_down;
-
-                    total_down += axis_down as i32;
-                    last_pressed = max(last_pressed, (axis_down as u64) * axis.last_change_timestamp);
-                    last_released = max(last_released, (!axis_down as u64) * axis.last_change_timestamp);
-                },
-                None => {}
+            let blocked = block.blocks_button(ButtonSource::ControllerAxis(axis.axis, axis.threshold));
+            let axis_down = !blocked && input_system.bound_controllers(controller_bind)
+                .any(|controller_state| controller_state.axis_state(axis.axis).pressed(axis.threshold));
+
+            let changed_state = axis_down ^ axis.last_update_value;
+            if changed_state {
+                // @XXX last motion of the frame, not necessarily the one crossing the threshold
+                axis.last_change_timestamp = if blocked {
+                    timestamp
+                } else {
+                    input_system.bound_controllers(controller_bind)
+                        .map(|controller_state| controller_state.axis_state(axis.axis).timestamp)
+                        .max()
+                        .unwrap_or(timestamp)
+                };
+            }
+            axis.last_update_value = axis_down;
+
+            total_down += axis_down as i32;
+            if axis_down {
+                last_pressed = max(last_pressed, axis.last_change_timestamp);
+            } else {
+                last_released = max(last_released, axis.last_change_timestamp);
+            }
+        }
+
+        // Joystick buttons
+        for button in self.joystick_buttons.iter() {
+            if block.blocks_button(ButtonSource::JoystickButton(button.0)) {
+                continue;
+            }
+
+            for joystick_state in input_system.bound_joysticks(joystick_bind) {
+                let button_state = joystick_state.button_state(button.0);
+
+                total_down += button_state.down as i32;
+                if button_state.down {
+                    last_pressed = max(last_pressed, button_state.timestamp);
+                } else {
+                    last_released = max(last_released, button_state.timestamp);
+                }
+            }
+        }
+
+        // Joystick hats
+        for hat in self.joystick_hats.iter() {
+            if block.blocks_button(ButtonSource::JoystickHat(hat.hat, hat.direction)) {
+                continue;
+            }
+
+            for joystick_state in input_system.bound_joysticks(joystick_bind) {
+                let hat_state = joystick_state.hat_state(hat.hat, hat.direction);
+
+                total_down += hat_state.down as i32;
+                if hat_state.down {
+                    last_pressed = max(last_pressed, hat_state.timestamp);
+                } else {
+                    last_released = max(last_released, hat_state.timestamp);
+                }
+            }
+        }
+
+        // Joystick axis
+        // Same threshold logic as the controller axis
+        for axis in self.joystick_axes.iter_mut() {
+            let blocked = block.blocks_button(ButtonSource::JoystickAxis(axis.axis, axis.threshold));
+            let axis_down = !blocked && input_system.bound_joysticks(joystick_bind)
+                .any(|joystick_state| joystick_state.axis_state(axis.axis).pressed(axis.threshold));
+
+            if axis_down ^ axis.last_update_value {
+                axis.last_change_timestamp = if blocked {
+                    timestamp
+                } else {
+                    input_system.bound_joysticks(joystick_bind)
+                        .map(|joystick_state| joystick_state.axis_state(axis.axis).timestamp)
+                        .max()
+                        .unwrap_or(timestamp)
+                };
+            }
+            axis.last_update_value = axis_down;
+
+            total_down += axis_down as i32;
+            if axis_down {
+                last_pressed = max(last_pressed, axis.last_change_timestamp);
+            } else {
+                last_released = max(last_released, axis.last_change_timestamp);
             }
         }
 
+        // Touch gestures
+        for gesture in self.touch_gestures.iter() {
+            if block.blocks_button(ButtonSource::TouchGesture(gesture.0)) {
+                continue;
+            }
+
+            let gesture_state = input_system.devices.touch.gesture_state(gesture.0);
+
+            total_down += gesture_state.down as i32;
+            if gesture_state.down {
+                last_pressed = max(last_pressed, gesture_state.timestamp);
+            } else {
+                last_released = max(last_released, gesture_state.timestamp);
+            }
+        }
+
+        self.update_state(total_down > 0, last_pressed, last_released, timestamp);
+    }
+
+    pub(super) fn update_state(
+        &mut self,
+        any_down: bool,
+        last_pressed: GameTime,
+        mut last_released: GameTime,
+        timestamp: GameTime
+    ) {
+        // Updating more than once per frame keeps the transitions of the frame
+        if timestamp != self.update_timestamp {
+            self.previous_update_timestamp = self.update_timestamp;
+            self.update_timestamp = timestamp;
+            self.pressed = false;
+            self.released = false;
+        }
+
         // Multiple keys per button logic
         // down = any key is down
         // up   = no key is down
@@ -215,7 +508,7 @@ impl Button {
         // we should see the following states
         // pressed+down, no change (down), no change (down), released
 
-        if total_down > 0 {
+        if any_down {
             // pressed
 
             // If the button was down already, we don't update the timestamp to avoid false
@@ -223,15 +516,24 @@ impl Button {
             if !self.down {
                 self.timestamp = last_pressed;
                 self.down = true;
-            }
 
-            self.pressed = self.timestamp == timestamp;
-        } else {
+                self.previous_press_timestamp = self.press_timestamp.replace(last_pressed);
+
+                // Held before the previous update (its sources were consumed by an input context)
+                self.pressed = last_pressed >= self.previous_update_timestamp;
+            }
+        } else if self.down {
             //released
 
+            // Released without a release event (the sources were consumed by an input context):
+            // the release happens now
+            if last_released < self.timestamp {
+                last_released = timestamp;
+            }
+
             self.timestamp = last_released;
             self.down = false;
-            self.released = self.timestamp == timestamp;
+            self.released = true;
         }
     }
 
@@ -239,13 +541,63 @@ impl Button {
     pub fn pressed(&self)  -> bool { self.pressed }
     pub fn released(&self) -> bool { self.released } // @TODO return release duration?
 
-    pub fn pressed_for(&self, duration: u64, timestamp: u64)  -> bool {
+    // Time of the last press or release, between the previous frame and the current one
+    pub fn timestamp(&self) -> GameTime { self.timestamp }
+
+    pub fn pressed_for(&self, duration: Duration, timestamp: GameTime)  -> bool {
         self.down && timestamp - self.timestamp >= duration
     }
 
-    pub fn released_for(&self, duration: u64, timestamp: u64)  -> bool {
+    pub fn released_for(&self, duration: Duration, timestamp: GameTime)  -> bool {
         !self.down && timestamp - self.timestamp >= duration
     }
+
+    // Pressed this frame, less than max_interval after the previous press. A third tap in time is
+    // also a double tap
+    pub fn double_tapped(&self, max_interval: Duration) -> bool {
+        match (self.previous_press_timestamp, self.press_timestamp) {
+            (Some(previous), Some(last)) => self.pressed && last.duration_since(previous) <= max_interval,
+            _ => false,
+        }
+    }
+
+    // Released this frame after being held at least min_duration
+    pub fn hold_released(&self, min_duration: Duration) -> bool {
+        match self.press_timestamp {
+            Some(press) => self.released && self.timestamp.duration_since(press) >= min_duration,
+            None => false,
+        }
+    }
+}
+
+// Physical input that can be mapped to a Button
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ButtonSource {
+    Key(Scancode),
+    MouseButton(MouseButton),
+    ControllerButton(sdl2::controller::Button),
+    ControllerAxis(sdl2::controller::Axis, ControllerAxisThreshold),
+    JoystickButton(u8),
+    JoystickAxis(u8, ControllerAxisThreshold),
+    JoystickHat(u8, JoystickHatDirection),
+    TouchGesture(TouchGesture),
+}
+
+impl_imdraw_todo!(ButtonSource);
+
+impl ButtonSource {
+    // Axes are the same input if they use the same direction, whatever the threshold value is
+    pub fn same_input(&self, other: &ButtonSource) -> bool {
+        match (self, other) {
+            (ButtonSource::ControllerAxis(a, a_threshold), ButtonSource::ControllerAxis(b, b_threshold)) => {
+                a == b && a_threshold.direction == b_threshold.direction
+            },
+            (ButtonSource::JoystickAxis(a, a_threshold), ButtonSource::JoystickAxis(b, b_threshold)) => {
+                a == b && a_threshold.direction == b_threshold.direction
+            },
+            _ => self == other,
+        }
+    }
 }
 
 #[derive(ImDraw)]
@@ -255,22 +607,39 @@ struct KeyInput(Scancode);
 struct MouseButtonInput(MouseButton);
 
 #[derive(ImDraw)]
-struct ControllerButtonInput {
-    controller_index: usize,
-    button: sdl2::controller::Button,
-}
+struct ControllerButtonInput(sdl2::controller::Button);
 
 #[derive(ImDraw)]
 struct ControllerAxisInput {
-    controller_index: usize,
     axis: sdl2::controller::Axis,
     threshold: ControllerAxisThreshold,
 
     // Required to handle the last time it changed the threshold boundary
     last_update_value: bool,
-    last_change_timestamp: u64,
+    last_change_timestamp: GameTime,
+}
+
+#[derive(ImDraw)]
+struct JoystickButtonInput(u8);
+
+#[derive(ImDraw)]
+struct JoystickAxisInput {
+    axis: u8,
+    threshold: ControllerAxisThreshold,
+
+    last_update_value: bool,
+    last_change_timestamp: GameTime,
 }
 
+#[derive(ImDraw)]
+struct JoystickHatInput {
+    hat: u8,
+    direction: JoystickHatDirection,
+}
+
+#[derive(ImDraw)]
+struct TouchGestureInput(TouchGesture);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -316,4 +685,93 @@ mod tests {
 
         assert_eq!(button.mouse_buttons.len(), 1);
     }
+
+    #[test]
+    fn test_button_sub_frame_timestamps() {
+        let mut button = Button::new();
+        let frame_0 = GameTime::from_micros(16_000);
+        let frame_1 = GameTime::from_micros(32_000);
+
+        button.update_state(false, GameTime::default(), GameTime::default(), frame_0);
+        assert!(!button.down() && !button.released());
+
+        // pressed between both frames
+        let press_time = GameTime::from_micros(20_500);
+        button.update_state(true, press_time, GameTime::default(), frame_1);
+        assert!(button.down() && button.pressed());
+        assert_eq!(button.timestamp(), press_time);
+        assert!(button.pressed_for(Duration::from_micros(11_500), frame_1));
+
+        // same frame, updated twice
+        button.update_state(true, press_time, GameTime::default(), frame_1);
+        assert!(button.pressed());
+
+        let frame_2 = GameTime::from_micros(48_000);
+        let release_time = GameTime::from_micros(40_000);
+        button.update_state(false, press_time, release_time, frame_2);
+        assert!(!button.pressed() && button.released());
+        assert_eq!(button.timestamp(), release_time);
+
+        // held since before the previous update (consumed by an input context until now)
+        let frame_3 = GameTime::from_micros(64_000);
+        let frame_4 = GameTime::from_micros(80_000);
+        button.update_state(false, press_time, release_time, frame_3);
+        button.update_state(true, GameTime::from_micros(50_000), release_time, frame_4);
+        assert!(button.down() && !button.pressed());
+    }
+
+    #[test]
+    fn test_button_gestures() {
+        let mut button = Button::new();
+        let ms = |ms: u64| GameTime::from_micros(ms * 1_000);
+
+        button.update_state(true, ms(10), ms(0), ms(16));
+        assert!(button.pressed() && !button.double_tapped(Duration::from_millis(200)));
+
+        button.update_state(false, ms(10), ms(40), ms(48));
+        assert!(!button.hold_released(Duration::from_millis(50)));
+
+        button.update_state(true, ms(150), ms(40), ms(160));
+        assert!(button.double_tapped(Duration::from_millis(200)));
+        assert!(!button.double_tapped(Duration::from_millis(100)));
+
+        button.update_state(true, ms(150), ms(40), ms(176));
+        assert!(!button.double_tapped(Duration::from_millis(200)));
+
+        button.update_state(false, ms(150), ms(700), ms(704));
+        assert!(button.hold_released(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_button_sources() {
+        let mut button = Button::new();
+
+        button.add_source(ButtonSource::Key(Scancode::A));
+        button.add_source(ButtonSource::ControllerButton(sdl2::controller::Button::A));
+        button.add_source(ButtonSource::ControllerAxis(
+            sdl2::controller::Axis::LeftX,
+            ControllerAxisThreshold::greater_than(0.5)
+        ));
+
+        assert_eq!(button.sources().len(), 3);
+        assert!(button.has_source(ButtonSource::Key(Scancode::A)));
+        assert!(!button.has_source(ButtonSource::Key(Scancode::B)));
+
+        // same axis direction with a different threshold value
+        let axis_source = ButtonSource::ControllerAxis(
+            sdl2::controller::Axis::LeftX,
+            ControllerAxisThreshold::greater_than(0.8)
+        );
+        assert!(button.has_source(axis_source));
+        assert!(!button.has_source(ButtonSource::ControllerAxis(
+            sdl2::controller::Axis::LeftX,
+            ControllerAxisThreshold::lesser_than(-0.5)
+        )));
+
+        button.rem_source(axis_source);
+        assert_eq!(button.sources().len(), 2);
+
+        button.clear();
+        assert!(button.sources().is_empty());
+    }
 }
@@ -0,0 +1,689 @@
+// Input mapping config
+//
+// Line based text format to save and load input mappings (custom bindings). Names are the ones of
+// the sdl2 enums (Scancode, MouseButton, controller Button and Axis). Indices, thresholds and axis
+// settings are checked against the ranges the input system can read.
+
+/* Format
+
+# comment
+controller_bind = any        # none | any | <controller index>
+joystick_bind = any          # none | any | <joystick index>
+
+[button UP]
+key = W
+mouse_button = Left
+controller_button = DPadUp
+controller_axis = LeftY < -0.5
+joystick_button = 3          # <button>
+joystick_axis = 1 > 0.5      # <axis> <threshold>
+joystick_hat = 0 Up          # <hat> <direction>
+touch = Tap                  # Touch | Tap | SwipeLeft | SwipeRight | SwipeUp | SwipeDown
+
+[axis HORIZONTAL]
+keys = A D                   # <negative> <positive>
+mouse_wheel = Vertical       # Horizontal | Vertical
+touch = DragX                # DragX | DragY | Pinch
+controller_axis = LeftX
+dead_zone = 0.2
+curve = power 2              # linear | power <exponent>
+sensitivity = 1
+inverted = false
+
+[axis_2d MOVE]
+x.keys = A D
+y.keys = W S
+dead_zone = 0.2
+*/
+
+/* Usage
+
+let mut input_mapping = default_input_mapping();
+match InputMapping::load("config/input.cfg") {
+    Ok(mapping) => input_mapping = mapping,
+    Err(e) => println!("{}", e),
+}
+
+// after rebinding
+
+input_mapping.save("config/input.cfg").unwrap();
+*/
+
+use std::fmt::Debug;
+use std::fs;
+
+use crate::app::sdl2::{
+    keyboard::Scancode,
+    mouse::MouseButton,
+};
+
+use super::{
+    ControllerAxisDirection,
+    ControllerAxisThreshold,
+    JoystickHatDirection,
+    axis::{Axis, Axis2D, AxisCurve, AxisSettings, AxisSource, MouseWheelAxis},
+    button::{Button, ButtonSource},
+    mapping::{ControllerBind, InputMapping},
+    system::{
+        CONTROLLER_AXES, CONTROLLER_BUTTONS, JOYSTICK_HAT_DIRECTIONS,
+        MAX_CONTROLLERS, MAX_JOYSTICK_AXES, MAX_JOYSTICK_BUTTONS, MAX_JOYSTICK_HATS, MAX_JOYSTICKS,
+    },
+    touch::{TOUCH_GESTURES, TouchAxis},
+};
+
+// Valid SDL_Scancode values. Scancode::from_i32 can't be called with values outside the enum
+const SCANCODE_RANGES: [(i32, i32); 5] = [(4, 129), (133, 164), (176, 221), (224, 231), (257, 286)];
+
+impl InputMapping {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("[input_mapping load] could not read {}: {}", path, e))?;
+
+        Self::from_config_str(&text)
+            .map_err(|e| format!("[input_mapping load] {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_config_string())
+            .map_err(|e| format!("[input_mapping save] could not write {}: {}", path, e))
+    }
+
+    pub fn to_config_string(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str(&format!("controller_bind = {}\n", bind_string(self.controller_bind)));
+        out.push_str(&format!("joystick_bind = {}\n", bind_string(self.joystick_bind)));
+
+        for (name, button) in self.button_mapping.iter() {
+            out.push_str(&format!("\n[button {}]\n", name));
+            for source in button.sources() {
+                out.push_str(&button_source_entry(source));
+                out.push('\n');
+            }
+        }
+
+        for (name, axis) in self.axis_mapping.iter() {
+            out.push_str(&format!("\n[axis {}]\n", name));
+            write_axis(&mut out, "", axis);
+            write_settings(&mut out, "", &axis.settings);
+        }
+
+        for (name, axis) in self.axis_2d_mapping.iter() {
+            out.push_str(&format!("\n[axis_2d {}]\n", name));
+            write_axis(&mut out, "x.", &axis.x);
+            write_settings(&mut out, "x.", &axis.x.settings);
+            write_axis(&mut out, "y.", &axis.y);
+            write_settings(&mut out, "y.", &axis.y.settings);
+            write_settings(&mut out, "", &axis.settings);
+        }
+
+        out
+    }
+
+    // Errors name the line and the entry
+    pub fn from_config_str(text: &str) -> Result<Self, String> {
+        let mut mapping = InputMapping::new();
+        let mut section = Section::None;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            }.trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |section: &Section, message: String| {
+                format!("line {}: {}{}", line_number, section.describe(), message)
+            };
+
+            // Section header
+            if line.starts_with('[') {
+                if !line.ends_with(']') {
+                    return Err(error(&section, format!("invalid section '{}'", line)));
+                }
+
+                let header = line[1..line.len() - 1].trim();
+                let (kind, name) = match header.find(char::is_whitespace) {
+                    Some(split) => (&header[..split], header[split..].trim()),
+                    None => return Err(error(&section, format!("missing name in section '{}'", line))),
+                };
+
+                std::mem::replace(&mut section, Section::None).finish(&mut mapping);
+
+                let repeated = match kind {
+                    "button"  => mapping.button_mapping.contains_key(name),
+                    "axis"    => mapping.axis_mapping.contains_key(name),
+                    "axis_2d" => mapping.axis_2d_mapping.contains_key(name),
+                    _ => return Err(error(&section, format!("unknown section kind '{}'", kind))),
+                };
+
+                if repeated {
+                    return Err(error(&section, format!("repeated {} '{}'", kind, name)));
+                }
+
+                section = match kind {
+                    "button" => Section::Button(name.to_string(), Button::new()),
+                    "axis"   => Section::Axis(name.to_string(), Axis::new()),
+                    _        => Section::Axis2D(name.to_string(), Box::new(Axis2D::new(Axis::new(), Axis::new()))),
+                };
+                continue;
+            }
+
+            // Entry
+            let (key, value) = match line.find('=') {
+                Some(split) => (line[..split].trim(), line[split + 1..].trim()),
+                None => return Err(error(&section, format!("expected 'key = value', found '{}'", line))),
+            };
+
+            let result = match &mut section {
+                Section::None => match key {
+                    "controller_bind" => {
+                        parse_bind(value, MAX_CONTROLLERS, "controller").map(|bind| mapping.controller_bind = bind)
+                    },
+                    "joystick_bind" => {
+                        parse_bind(value, MAX_JOYSTICKS, "joystick").map(|bind| mapping.joystick_bind = bind)
+                    },
+                    _ => Err(format!("unknown entry '{}'", key)),
+                },
+
+                Section::Button(_, button) => parse_button_source(key, value).and_then(|source| {
+                    if button.has_source(source) {
+                        Err(format!("repeated entry '{} = {}'", key, value))
+                    } else {
+                        button.add_source(source);
+                        Ok(())
+                    }
+                }),
+
+                Section::Axis(_, axis) => parse_axis_entry(axis, key, value),
+
+                Section::Axis2D(_, axis) => {
+                    if let Some(key) = key.strip_prefix("x.") {
+                        parse_axis_entry(&mut axis.x, key, value)
+                    } else if let Some(key) = key.strip_prefix("y.") {
+                        parse_axis_entry(&mut axis.y, key, value)
+                    } else {
+                        parse_settings_entry(&mut axis.settings, key, value)
+                    }
+                },
+            };
+
+            result.map_err(|e| error(&section, e))?;
+        }
+
+        section.finish(&mut mapping);
+
+        Ok(mapping)
+    }
+}
+
+enum Section {
+    None,
+    Button(String, Button),
+    Axis(String, Axis),
+    Axis2D(String, Box<Axis2D>),
+}
+
+impl Section {
+    fn describe(&self) -> String {
+        match self {
+            Section::None => String::new(),
+            Section::Button(name, _) => format!("button {}: ", name),
+            Section::Axis(name, _) => format!("axis {}: ", name),
+            Section::Axis2D(name, _) => format!("axis_2d {}: ", name),
+        }
+    }
+
+    // Names are checked to be unique when the section starts
+    fn finish(self, mapping: &mut InputMapping) {
+        match self {
+            Section::None => {},
+            Section::Button(name, button) => { mapping.button_mapping.insert(name, button); },
+            Section::Axis(name, axis) => { mapping.axis_mapping.insert(name, axis); },
+            Section::Axis2D(name, axis) => { mapping.axis_2d_mapping.insert(name, *axis); },
+        }
+    }
+}
+
+// -------------
+// Serialization
+// -------------
+
+fn bind_string(bind: ControllerBind) -> String {
+    match bind {
+        ControllerBind::No => "none".to_string(),
+        ControllerBind::Any => "any".to_string(),
+        ControllerBind::Index(index) => index.to_string(),
+    }
+}
+
+fn threshold_string(threshold: ControllerAxisThreshold) -> String {
+    match threshold.direction {
+        ControllerAxisDirection::GreaterThan => format!("> {}", threshold.value),
+        ControllerAxisDirection::LesserThan  => format!("< {}", threshold.value),
+    }
+}
+
+fn button_source_entry(source: ButtonSource) -> String {
+    match source {
+        ButtonSource::Key(code) => format!("key = {:?}", code),
+        ButtonSource::MouseButton(button) => format!("mouse_button = {:?}", button),
+        ButtonSource::ControllerButton(button) => format!("controller_button = {:?}", button),
+        ButtonSource::ControllerAxis(axis, threshold) => {
+            format!("controller_axis = {:?} {}", axis, threshold_string(threshold))
+        },
+        ButtonSource::JoystickButton(button) => format!("joystick_button = {}", button),
+        ButtonSource::JoystickAxis(axis, threshold) => {
+            format!("joystick_axis = {} {}", axis, threshold_string(threshold))
+        },
+        ButtonSource::JoystickHat(hat, direction) => format!("joystick_hat = {} {:?}", hat, direction),
+        ButtonSource::TouchGesture(gesture) => format!("touch = {:?}", gesture),
+    }
+}
+
+fn write_axis(out: &mut String, prefix: &str, axis: &Axis) {
+    for source in axis.sources() {
+        let entry = match source {
+            AxisSource::Keys(negative, positive) => format!("keys = {:?} {:?}", negative, positive),
+            AxisSource::MouseButtons(negative, positive) => {
+                format!("mouse_buttons = {:?} {:?}", negative, positive)
+            },
+            AxisSource::ControllerButtons(negative, positive) => {
+                format!("controller_buttons = {:?} {:?}", negative, positive)
+            },
+            AxisSource::MouseWheel(axis) => format!("mouse_wheel = {:?}", axis),
+            AxisSource::ControllerAxis(axis) => format!("controller_axis = {:?}", axis),
+            AxisSource::JoystickButtons(negative, positive) => {
+                format!("joystick_buttons = {} {}", negative, positive)
+            },
+            AxisSource::JoystickAxis(axis) => format!("joystick_axis = {}", axis),
+            AxisSource::JoystickHat(hat, negative, positive) => {
+                format!("joystick_hat = {} {:?} {:?}", hat, negative, positive)
+            },
+            AxisSource::Touch(axis) => format!("touch = {:?}", axis),
+        };
+
+        out.push_str(prefix);
+        out.push_str(&entry);
+        out.push('\n');
+    }
+}
+
+fn write_settings(out: &mut String, prefix: &str, settings: &AxisSettings) {
+    let curve = match settings.curve {
+        AxisCurve::Linear => "linear".to_string(),
+        AxisCurve::Power(exponent) => format!("power {}", exponent),
+    };
+
+    out.push_str(&format!("{}dead_zone = {}\n", prefix, settings.dead_zone));
+    out.push_str(&format!("{}curve = {}\n", prefix, curve));
+    out.push_str(&format!("{}sensitivity = {}\n", prefix, settings.sensitivity));
+    out.push_str(&format!("{}inverted = {}\n", prefix, settings.inverted));
+}
+
+// -------
+// Parsing
+// -------
+
+// Enum values are matched by their Debug name
+fn parse_name<T: Debug>(mut values: impl Iterator<Item = T>, kind: &str, name: &str) -> Result<T, String> {
+    values
+        .find(|value| format!("{:?}", value) == name)
+        .ok_or_else(|| format!("unknown {} '{}'", kind, name))
+}
+
+pub(super) fn parse_scancode(name: &str) -> Result<Scancode, String> {
+    let scancodes = SCANCODE_RANGES.iter()
+        .flat_map(|&(first, last)| first..=last)
+        .filter_map(Scancode::from_i32);
+
+    parse_name(scancodes, "key", name)
+}
+
+pub(super) fn parse_mouse_button(name: &str) -> Result<MouseButton, String> {
+    parse_name((1..=5).map(MouseButton::from_ll), "mouse button", name)
+}
+
+pub(super) fn parse_controller_button(name: &str) -> Result<sdl2::controller::Button, String> {
+    parse_name(CONTROLLER_BUTTONS.iter().copied(), "controller button", name)
+}
+
+pub(super) fn parse_controller_axis(name: &str) -> Result<sdl2::controller::Axis, String> {
+    parse_name(CONTROLLER_AXES.iter().copied(), "controller axis", name)
+}
+
+fn parse_mouse_wheel_axis(name: &str) -> Result<MouseWheelAxis, String> {
+    parse_name([MouseWheelAxis::Horizontal, MouseWheelAxis::Vertical].iter().copied(), "mouse wheel axis", name)
+}
+
+fn parse_touch_axis(name: &str) -> Result<TouchAxis, String> {
+    parse_name([TouchAxis::DragX, TouchAxis::DragY, TouchAxis::Pinch].iter().copied(), "touch axis", name)
+}
+
+fn parse_hat_direction(name: &str) -> Result<JoystickHatDirection, String> {
+    parse_name(JOYSTICK_HAT_DIRECTIONS.iter().copied(), "hat direction", name)
+}
+
+pub(super) fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid number '{}'", value))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    value.parse().map_err(|_| format!("invalid bool '{}'", value))
+}
+
+// Indices of the devices, buttons and axes the input system keeps
+fn parse_index(value: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = parse_number(value)?;
+    if index >= count {
+        return Err(format!("invalid {} index {} (expected less than {})", kind, index, count));
+    }
+
+    Ok(index)
+}
+
+fn parse_joystick_button(value: &str) -> Result<u8, String> {
+    parse_index(value, MAX_JOYSTICK_BUTTONS, "joystick button").map(|index| index as u8)
+}
+
+fn parse_joystick_axis(value: &str) -> Result<u8, String> {
+    parse_index(value, MAX_JOYSTICK_AXES, "joystick axis").map(|index| index as u8)
+}
+
+fn parse_joystick_hat(value: &str) -> Result<u8, String> {
+    parse_index(value, MAX_JOYSTICK_HATS, "joystick hat").map(|index| index as u8)
+}
+
+// Positive and finite (sensitivity, curve exponent)
+fn parse_factor(value: &str, name: &str) -> Result<f32, String> {
+    let factor: f32 = parse_number(value)?;
+    if !(factor.is_finite() && factor > 0.0) {
+        return Err(format!("{} must be positive, found {}", name, factor));
+    }
+
+    Ok(factor)
+}
+
+fn parse_threshold(direction: &str, value: &str) -> Result<ControllerAxisThreshold, String> {
+    let value: f32 = parse_number(value)?;
+    if !(-1.0..=1.0).contains(&value) {
+        return Err(format!("threshold must be in [-1, 1], found {}", value));
+    }
+
+    match direction {
+        ">" => Ok(ControllerAxisThreshold::greater_than(value)),
+        "<" => Ok(ControllerAxisThreshold::lesser_than(value)),
+        _ => Err(format!("invalid threshold direction '{}' (expected '<' or '>')", direction)),
+    }
+}
+
+fn parse_bind(value: &str, count: usize, kind: &str) -> Result<ControllerBind, String> {
+    match value {
+        "none" => Ok(ControllerBind::No),
+        "any" => Ok(ControllerBind::Any),
+        _ => parse_index(value, count, kind).map(ControllerBind::Index),
+    }
+}
+
+// Splits the value in exactly N tokens
+fn tokens<'a, const N: usize>(key: &str, value: &'a str) -> Result<[&'a str; N], String> {
+    let mut result = [""; N];
+    let mut count = 0;
+
+    for token in value.split_whitespace() {
+        if count < N {
+            result[count] = token;
+        }
+        count += 1;
+    }
+
+    if count != N {
+        return Err(format!("'{}' expects {} values, found {}", key, N, count));
+    }
+
+    Ok(result)
+}
+
+fn parse_button_source(key: &str, value: &str) -> Result<ButtonSource, String> {
+    match key {
+        "key" => {
+            let [code] = tokens(key, value)?;
+            Ok(ButtonSource::Key(parse_scancode(code)?))
+        },
+        "mouse_button" => {
+            let [button] = tokens(key, value)?;
+            Ok(ButtonSource::MouseButton(parse_mouse_button(button)?))
+        },
+        "controller_button" => {
+            let [button] = tokens(key, value)?;
+            Ok(ButtonSource::ControllerButton(parse_controller_button(button)?))
+        },
+        "controller_axis" => {
+            let [axis, direction, threshold] = tokens(key, value)?;
+            Ok(ButtonSource::ControllerAxis(
+                parse_controller_axis(axis)?,
+                parse_threshold(direction, threshold)?
+            ))
+        },
+        "joystick_button" => {
+            let [button] = tokens(key, value)?;
+            Ok(ButtonSource::JoystickButton(parse_joystick_button(button)?))
+        },
+        "joystick_axis" => {
+            let [axis, direction, threshold] = tokens(key, value)?;
+            Ok(ButtonSource::JoystickAxis(parse_joystick_axis(axis)?, parse_threshold(direction, threshold)?))
+        },
+        "joystick_hat" => {
+            let [hat, direction] = tokens(key, value)?;
+            Ok(ButtonSource::JoystickHat(parse_joystick_hat(hat)?, parse_hat_direction(direction)?))
+        },
+        "touch" => {
+            let [gesture] = tokens(key, value)?;
+            Ok(ButtonSource::TouchGesture(parse_name(TOUCH_GESTURES.iter().copied(), "touch gesture", gesture)?))
+        },
+        _ => Err(format!("unknown entry '{}'", key)),
+    }
+}
+
+fn parse_axis_source(key: &str, value: &str) -> Result<Option<AxisSource>, String> {
+    let source = match key {
+        "keys" => {
+            let [negative, positive] = tokens(key, value)?;
+            AxisSource::Keys(parse_scancode(negative)?, parse_scancode(positive)?)
+        },
+        "mouse_buttons" => {
+            let [negative, positive] = tokens(key, value)?;
+            AxisSource::MouseButtons(parse_mouse_button(negative)?, parse_mouse_button(positive)?)
+        },
+        "mouse_wheel" => {
+            let [axis] = tokens(key, value)?;
+            AxisSource::MouseWheel(parse_mouse_wheel_axis(axis)?)
+        },
+        "controller_buttons" => {
+            let [negative, positive] = tokens(key, value)?;
+            AxisSource::ControllerButtons(
+                parse_controller_button(negative)?,
+                parse_controller_button(positive)?
+            )
+        },
+        "controller_axis" => {
+            let [axis] = tokens(key, value)?;
+            AxisSource::ControllerAxis(parse_controller_axis(axis)?)
+        },
+        "joystick_buttons" => {
+            let [negative, positive] = tokens(key, value)?;
+            AxisSource::JoystickButtons(parse_joystick_button(negative)?, parse_joystick_button(positive)?)
+        },
+        "joystick_axis" => {
+            let [axis] = tokens(key, value)?;
+            AxisSource::JoystickAxis(parse_joystick_axis(axis)?)
+        },
+        "joystick_hat" => {
+            let [hat, negative, positive] = tokens(key, value)?;
+            AxisSource::JoystickHat(
+                parse_joystick_hat(hat)?,
+                parse_hat_direction(negative)?,
+                parse_hat_direction(positive)?
+            )
+        },
+        "touch" => {
+            let [axis] = tokens(key, value)?;
+            AxisSource::Touch(parse_touch_axis(axis)?)
+        },
+        _ => return Ok(None),
+    };
+
+    Ok(Some(source))
+}
+
+fn parse_axis_entry(axis: &mut Axis, key: &str, value: &str) -> Result<(), String> {
+    match parse_axis_source(key, value)? {
+        Some(source) => {
+            if axis.has_source(source) {
+                return Err(format!("repeated entry '{} = {}'", key, value));
+            }
+
+            axis.add_source(source);
+            Ok(())
+        },
+        None => parse_settings_entry(&mut axis.settings, key, value),
+    }
+}
+
+fn parse_settings_entry(settings: &mut AxisSettings, key: &str, value: &str) -> Result<(), String> {
+    match key {
+        "dead_zone" => {
+            let dead_zone: f32 = parse_number(value)?;
+            if !(0.0..1.0).contains(&dead_zone) {
+                return Err(format!("dead_zone must be in [0, 1), found {}", dead_zone));
+            }
+            settings.dead_zone = dead_zone;
+        },
+        "curve" => {
+            settings.curve = match value.split_whitespace().collect::<Vec<_>>().as_slice() {
+                ["linear"] => AxisCurve::Linear,
+                ["power", exponent] => AxisCurve::Power(parse_factor(exponent, "curve exponent")?),
+                _ => return Err(format!("invalid curve '{}' (expected 'linear' or 'power <exponent>')", value)),
+            };
+        },
+        "sensitivity" => settings.sensitivity = parse_factor(value, "sensitivity")?,
+        "inverted" => settings.inverted = parse_bool(value)?,
+        _ => return Err(format!("unknown entry '{}'", key)),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::touch::TouchGesture;
+
+    #[test]
+    fn test_input_mapping_config_roundtrip() {
+        let mut mapping = InputMapping::new();
+        mapping.set_controller_bind(ControllerBind::Index(1));
+        mapping.set_joystick_bind(ControllerBind::No);
+
+        let mut button = Button::new();
+        button.add_key(Scancode::W);
+        button.add_mouse_button(MouseButton::X1);
+        button.add_controller_button(sdl2::controller::Button::DPadUp);
+        button.add_controller_axis(sdl2::controller::Axis::LeftY, ControllerAxisThreshold::lesser_than(-0.5));
+        button.add_joystick_hat(1, JoystickHatDirection::Up);
+        button.add_touch_gesture(TouchGesture::SwipeUp);
+        mapping.add_button_mapping("UP".to_string(), button);
+
+        let mut axis = Axis::new();
+        axis.add_keys(Scancode::A, Scancode::D);
+        axis.add_joystick_axis(3);
+        axis.add_mouse_wheel(MouseWheelAxis::Vertical);
+        axis.add_touch_axis(TouchAxis::Pinch);
+        axis.settings.curve = AxisCurve::Power(1.5);
+        axis.settings.inverted = true;
+        mapping.add_axis_mapping("HORIZONTAL".to_string(), axis);
+
+        let mut x = Axis::new();
+        x.add_controller_axis(sdl2::controller::Axis::LeftX);
+        let mut axis = Axis2D::new(x, Axis::new());
+        axis.settings.dead_zone = 0.25;
+        mapping.add_axis_2d_mapping("MOVE".to_string(), axis);
+
+        let text = mapping.to_config_string();
+        let loaded = InputMapping::from_config_str(&text).unwrap();
+
+        assert_eq!(loaded.controller_bind(), ControllerBind::Index(1));
+        assert_eq!(loaded.joystick_bind(), ControllerBind::No);
+        assert_eq!(loaded.button("UP".to_string()).sources(), mapping.button("UP".to_string()).sources());
+        assert_eq!(
+            loaded.axis("HORIZONTAL".to_string()).sources(),
+            mapping.axis("HORIZONTAL".to_string()).sources()
+        );
+        assert_eq!(loaded.axis("HORIZONTAL".to_string()).settings.curve, AxisCurve::Power(1.5));
+        assert!(loaded.axis("HORIZONTAL".to_string()).settings.inverted);
+        assert_eq!(loaded.axis_2d("MOVE".to_string()).x.sources(), vec![
+            AxisSource::ControllerAxis(sdl2::controller::Axis::LeftX)
+        ]);
+        assert_eq!(loaded.axis_2d("MOVE".to_string()).settings.dead_zone, 0.25);
+
+        // stable output
+        assert_eq!(loaded.to_config_string(), text);
+    }
+
+    #[test]
+    fn test_input_mapping_config_errors() {
+        let error = |text: &str| InputMapping::from_config_str(text).err().unwrap();
+
+        assert_eq!(
+            error("[button UP]\nkey = W\nkey = Foo\n"),
+            "line 3: button UP: unknown key 'Foo'"
+        );
+        assert_eq!(
+            error("[button UP]\ncontroller_axis = LeftY -0.5\n"),
+            "line 2: button UP: 'controller_axis' expects 3 values, found 2"
+        );
+        assert_eq!(
+            error("[axis X]\ndead_zone = 2\n"),
+            "line 2: axis X: dead_zone must be in [0, 1), found 2"
+        );
+        assert_eq!(
+            error("[button UP]\nkey = W\nkey = W\n"),
+            "line 3: button UP: repeated entry 'key = W'"
+        );
+        assert_eq!(
+            error("[button UP]\n[button UP]\n"),
+            "line 2: repeated button 'UP'"
+        );
+        assert_eq!(error("[trigger T]\n"), "line 1: unknown section kind 'trigger'");
+
+        // ranges
+        assert_eq!(
+            error("joystick_bind = 16\n"),
+            "line 1: invalid joystick index 16 (expected less than 16)"
+        );
+        assert_eq!(
+            error("[button UP]\njoystick_hat = 4 Up\n"),
+            "line 2: button UP: invalid joystick hat index 4 (expected less than 4)"
+        );
+        assert_eq!(
+            error("[button UP]\ncontroller_axis = LeftY < NaN\n"),
+            "line 2: button UP: threshold must be in [-1, 1], found NaN"
+        );
+        assert_eq!(
+            error("[button UP]\njoystick_axis = 1 > 1.5\n"),
+            "line 2: button UP: threshold must be in [-1, 1], found 1.5"
+        );
+        assert_eq!(
+            error("[axis_2d MOVE]\nx.sensitivity = -1\n"),
+            "line 2: axis_2d MOVE: sensitivity must be positive, found -1"
+        );
+        assert_eq!(
+            error("[axis X]\ncurve = power inf\n"),
+            "line 2: axis X: curve exponent must be positive, found inf"
+        );
+    }
+}
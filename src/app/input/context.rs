@@ -0,0 +1,320 @@
+// Input contexts
+//
+// Input mappings can be assigned to a named context (gameplay, menu, debug console). Contexts are
+// pushed on a stack and the ones on top can consume the inputs of the ones below: the buttons and
+// axes of a consumed mapping read as released/zero. Mappings without context are never consumed,
+// and mappings whose context is not on the stack are fully consumed.
+
+/* Usage
+
+gameplay_mapping.set_context("gameplay");
+menu_mapping.set_context("menu");
+
+app.push_input_context(&gameplay_mapping, InputContextMode::Passthrough);
+
+// open the pause menu: gameplay actions stop firing
+app.push_input_context(&menu_mapping, InputContextMode::ConsumeAll);
+
+// debug console: only the inputs mapped by the console are taken from the contexts below
+app.push_input_context(&console_mapping, InputContextMode::ConsumeMapped);
+
+// close the pause menu
+app.pop_input_context("menu");
+*/
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+};
+use crate::app::sdl2::{
+    keyboard::Scancode,
+    mouse::MouseButton,
+};
+
+use super::{
+    axis::{AxisSource, MouseWheelAxis},
+    button::ButtonSource,
+    mapping::InputMapping,
+    system::InputSystem,
+};
+
+// What a context takes from the contexts below it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputContextMode {
+    Passthrough,
+    // Only the physical inputs used by the context mapping
+    ConsumeMapped,
+    ConsumeAll,
+}
+
+impl_imdraw_todo!(InputContextMode);
+
+// Physical input without direction or threshold. A context mapping using the positive direction of
+// an axis consumes the whole axis
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum PhysicalInput {
+    Key(Scancode),
+    MouseButton(MouseButton),
+    MouseWheel(MouseWheelAxis),
+    ControllerButton(sdl2::controller::Button),
+    ControllerAxis(sdl2::controller::Axis),
+    JoystickButton(u8),
+    JoystickAxis(u8),
+    JoystickHat(u8),
+    // All the gestures and axes come from the same fingers
+    Touch,
+}
+
+impl ButtonSource {
+    pub(super) fn physical_input(&self) -> PhysicalInput {
+        match *self {
+            ButtonSource::Key(code) => PhysicalInput::Key(code),
+            ButtonSource::MouseButton(button) => PhysicalInput::MouseButton(button),
+            ButtonSource::ControllerButton(button) => PhysicalInput::ControllerButton(button),
+            ButtonSource::ControllerAxis(axis, _) => PhysicalInput::ControllerAxis(axis),
+            ButtonSource::JoystickButton(button) => PhysicalInput::JoystickButton(button),
+            ButtonSource::JoystickAxis(axis, _) => PhysicalInput::JoystickAxis(axis),
+            ButtonSource::JoystickHat(hat, _) => PhysicalInput::JoystickHat(hat),
+            ButtonSource::TouchGesture(_) => PhysicalInput::Touch,
+        }
+    }
+}
+
+impl AxisSource {
+    pub(super) fn physical_inputs(&self) -> Vec<PhysicalInput> {
+        match *self {
+            AxisSource::Keys(negative, positive) => {
+                vec![PhysicalInput::Key(negative), PhysicalInput::Key(positive)]
+            },
+            AxisSource::MouseButtons(negative, positive) => {
+                vec![PhysicalInput::MouseButton(negative), PhysicalInput::MouseButton(positive)]
+            },
+            AxisSource::MouseWheel(axis) => vec![PhysicalInput::MouseWheel(axis)],
+            AxisSource::ControllerButtons(negative, positive) => {
+                vec![PhysicalInput::ControllerButton(negative), PhysicalInput::ControllerButton(positive)]
+            },
+            AxisSource::ControllerAxis(axis) => vec![PhysicalInput::ControllerAxis(axis)],
+            AxisSource::JoystickButtons(negative, positive) => {
+                vec![PhysicalInput::JoystickButton(negative), PhysicalInput::JoystickButton(positive)]
+            },
+            AxisSource::JoystickAxis(axis) => vec![PhysicalInput::JoystickAxis(axis)],
+            AxisSource::JoystickHat(hat, _, _) => vec![PhysicalInput::JoystickHat(hat)],
+            AxisSource::Touch(_) => vec![PhysicalInput::Touch],
+        }
+    }
+}
+
+impl InputMapping {
+    fn physical_inputs(&self) -> Vec<PhysicalInput> {
+        let mut inputs = Vec::new();
+
+        for button in self.button_mapping.values() {
+            inputs.extend(button.sources().iter().map(|source| source.physical_input()));
+        }
+
+        for axis in self.axis_mapping.values() {
+            inputs.extend(axis.sources().iter().flat_map(|source| source.physical_inputs()));
+        }
+
+        for axis in self.axis_2d_mapping.values() {
+            for axis in [&axis.x, &axis.y].iter() {
+                inputs.extend(axis.sources().iter().flat_map(|source| source.physical_inputs()));
+            }
+        }
+
+        inputs
+    }
+}
+
+// Inputs consumed for a mapping update
+pub(super) enum InputBlock {
+    None,
+    All,
+    Inputs(Vec<PhysicalInput>),
+}
+
+impl InputBlock {
+    fn blocks(&self, input: PhysicalInput) -> bool {
+        match self {
+            InputBlock::None => false,
+            InputBlock::All => true,
+            InputBlock::Inputs(inputs) => inputs.contains(&input),
+        }
+    }
+
+    pub(super) fn blocks_button(&self, source: ButtonSource) -> bool {
+        self.blocks(source.physical_input())
+    }
+
+    // Any of the inputs of the source
+    pub(super) fn blocks_axis(&self, source: AxisSource) -> bool {
+        match self {
+            InputBlock::None => false,
+            InputBlock::All => true,
+            InputBlock::Inputs(_) => source.physical_inputs().into_iter().any(|input| self.blocks(input)),
+        }
+    }
+}
+
+pub(super) struct InputContext {
+    name: String,
+    mode: InputContextMode,
+    // Physical inputs of the context mapping, refreshed every time it's updated
+    inputs: Vec<PhysicalInput>,
+}
+
+#[derive(Default)]
+pub(super) struct InputContextStack {
+    contexts: Vec<InputContext>,
+}
+
+impl InputContextStack {
+    fn push(&mut self, name: &str, mode: InputContextMode, inputs: Vec<PhysicalInput>) {
+        if self.contexts.iter().any(|context| context.name == name) {
+            panic!("[input_system push_input_context] context {} already in the stack", name);
+        }
+
+        self.contexts.push(InputContext { name: name.to_string(), mode, inputs });
+    }
+
+    fn pop(&mut self, name: &str) {
+        match self.contexts.last() {
+            Some(context) if context.name == name => { self.contexts.pop(); },
+            _ => panic!("[input_system pop_input_context] context {} is not on top of the stack", name),
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.contexts.iter().position(|context| context.name == name)
+    }
+
+    pub(super) fn block(&self, context: Option<&str>) -> InputBlock {
+        let context = match context {
+            Some(context) => context,
+            None => return InputBlock::None,
+        };
+
+        let position = match self.position(context) {
+            Some(position) => position,
+            None => return InputBlock::All,
+        };
+
+        let mut inputs = Vec::new();
+        for above in self.contexts[position + 1..].iter() {
+            match above.mode {
+                InputContextMode::Passthrough => {},
+                InputContextMode::ConsumeMapped => inputs.extend(above.inputs.iter().copied()),
+                InputContextMode::ConsumeAll => return InputBlock::All,
+            }
+        }
+
+        if inputs.is_empty() {
+            InputBlock::None
+        } else {
+            InputBlock::Inputs(inputs)
+        }
+    }
+
+    pub(super) fn refresh_inputs(&mut self, mapping: &InputMapping) {
+        let position = match mapping.context.as_deref().and_then(|name| self.position(name)) {
+            Some(position) => position,
+            None => return,
+        };
+
+        if self.contexts[position].mode == InputContextMode::ConsumeMapped {
+            self.contexts[position].inputs = mapping.physical_inputs();
+        }
+    }
+}
+
+impl<S> App<'_, S> {
+    // The mapping context name is pushed
+    pub fn push_input_context(&mut self, mapping: &InputMapping, mode: InputContextMode) {
+        self.input_system.push_input_context(mapping, mode);
+    }
+
+    // Has to be the top context
+    pub fn pop_input_context(&mut self, name: &str) {
+        self.input_system.pop_input_context(name);
+    }
+
+    pub fn is_input_context_active(&self, name: &str) -> bool {
+        self.input_system.contexts.position(name).is_some()
+    }
+
+    // Names from bottom to top
+    pub fn input_contexts(&self) -> Vec<&str> {
+        self.input_system.contexts.contexts.iter().map(|context| context.name.as_str()).collect()
+    }
+}
+
+impl InputSystem {
+    pub(super) fn push_input_context(&mut self, mapping: &InputMapping, mode: InputContextMode) {
+        let name = mapping.context.as_deref()
+            .expect("[input_system push_input_context] mapping without context");
+
+        self.contexts.push(name, mode, mapping.physical_inputs());
+    }
+
+    pub(super) fn pop_input_context(&mut self, name: &str) {
+        self.contexts.pop(name);
+    }
+
+    pub(super) fn input_block(&self, mapping: &InputMapping) -> InputBlock {
+        self.contexts.block(mapping.context.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::button::Button;
+
+    fn mapping(context: &str, key: Scancode) -> InputMapping {
+        let mut mapping = InputMapping::new();
+        mapping.set_context(context);
+
+        let mut button = Button::new();
+        button.add_key(key);
+        mapping.add_button_mapping("ACTION".to_string(), button);
+
+        mapping
+    }
+
+    #[test]
+    fn test_input_context_block() {
+        let gameplay = mapping("gameplay", Scancode::Space);
+        let console = mapping("console", Scancode::Grave);
+        let menu = mapping("menu", Scancode::Return);
+
+        let mut stack = InputContextStack::default();
+        stack.push("gameplay", InputContextMode::Passthrough, gameplay.physical_inputs());
+
+        assert!(matches!(stack.block(Some("gameplay")), InputBlock::None));
+        assert!(matches!(stack.block(None), InputBlock::None));
+        // not on the stack
+        assert!(matches!(stack.block(Some("menu")), InputBlock::All));
+
+        stack.push("console", InputContextMode::ConsumeMapped, console.physical_inputs());
+        let block = stack.block(Some("gameplay"));
+        assert!(block.blocks_button(ButtonSource::Key(Scancode::Grave)));
+        assert!(!block.blocks_button(ButtonSource::Key(Scancode::Space)));
+        assert!(block.blocks_axis(AxisSource::Keys(Scancode::A, Scancode::Grave)));
+
+        stack.push("menu", InputContextMode::ConsumeAll, menu.physical_inputs());
+        assert!(matches!(stack.block(Some("gameplay")), InputBlock::All));
+        assert!(matches!(stack.block(Some("menu")), InputBlock::None));
+
+        stack.pop("menu");
+        assert!(matches!(stack.block(Some("gameplay")), InputBlock::Inputs(_)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_input_context_pop_not_top() {
+        let mut stack = InputContextStack::default();
+        stack.push("gameplay", InputContextMode::Passthrough, Vec::new());
+        stack.push("menu", InputContextMode::ConsumeAll, Vec::new());
+        stack.pop("gameplay");
+    }
+}
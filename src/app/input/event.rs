@@ -0,0 +1,45 @@
+// Input events
+//
+// Device events resolved to device indices (SDL instance ids are only used internally by the input
+// system). All the input state changes go through them, so they can be recorded and replayed.
+
+use crate::app::imdraw::ImDraw;
+use crate::app::sdl2::{
+    keyboard::Scancode,
+    mouse::MouseButton,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InputEvent {
+    KeyDown(Scancode),
+    KeyUp(Scancode),
+
+    MouseButtonDown(MouseButton),
+    MouseButtonUp(MouseButton),
+    // Position and relative motion
+    MouseMotion(i32, i32, i32, i32),
+    // Horizontal and vertical scroll, positive to the right and away from the user
+    MouseWheel(i32, i32),
+
+    ControllerConnected(usize),
+    ControllerDisconnected(usize),
+    ControllerButtonDown(usize, sdl2::controller::Button),
+    ControllerButtonUp(usize, sdl2::controller::Button),
+    // Normalized value in [-1, 1]
+    ControllerAxisMotion(usize, sdl2::controller::Axis, f32),
+
+    JoystickConnected(usize),
+    JoystickDisconnected(usize),
+    JoystickButtonDown(usize, u8),
+    JoystickButtonUp(usize, u8),
+    JoystickAxisMotion(usize, u8, f32),
+    // Hat state bits, same as sdl2::joystick::HatState
+    JoystickHatMotion(usize, u8, u8),
+
+    // Finger id and position normalized to [0, 1]
+    FingerDown(i64, f32, f32),
+    FingerMotion(i64, f32, f32),
+    FingerUp(i64, f32, f32),
+}
+
+impl_imdraw_todo!(InputEvent);
@@ -0,0 +1,303 @@
+// Haptics
+//
+// Haptic patterns: intensity envelopes over time for the controller motors (low and high frequency)
+// and triggers. A pattern is played per controller from the game time, and playing another one
+// replaces it. The motors are updated once per frame with a short timeout, so they stop by
+// themselves if the game stalls.
+//
+// Trigger motors need SDL_GameControllerRumbleTriggers (SDL 2.0.14), which rust-sdl2 0.34 doesn't
+// expose yet. Trigger envelopes are kept in the patterns but ignored for now.
+
+/* Usage
+
+// construction
+
+let perfect_hit = HapticPattern::new()
+    .pulse(HapticChannel::HighFrequency, Duration::ZERO, Duration::from_millis(40), 1.0)
+    .ramp(HapticChannel::LowFrequency, Duration::ZERO, Duration::from_millis(120), 0.8, 0.0);
+
+let good_hit = HapticPattern::new()
+    .pulse(HapticChannel::LowFrequency, Duration::ZERO, Duration::from_millis(60), 0.4);
+
+// update
+
+if judgement == Judgement::Perfect {
+    app.play_haptic_pattern(controller_index, perfect_hit.clone());
+}
+
+// song paused
+app.stop_haptics(controller_index);
+*/
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+    time_system::{Duration, GameTime},
+};
+
+use super::system::{InputDevices, InputSystem};
+
+// Duration of every motor update. Longer than a frame so the motors don't stop between updates
+const HAPTIC_RUMBLE_TIMEOUT_MS: u32 = 100;
+
+// Unchanged intensities are sent again after this, before the previous update times out
+const HAPTIC_RESEND_INTERVAL: Duration = Duration::from_millis(50);
+
+const HAPTIC_CHANNEL_COUNT: usize = 4;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum HapticChannel {
+    LowFrequency,
+    HighFrequency,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl_imdraw_todo!(HapticChannel);
+
+// Linear ramp of intensity in [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq, ImDraw)]
+pub struct HapticEnvelope {
+    pub channel: HapticChannel,
+    // Since the pattern start
+    pub start: Duration,
+    pub duration: Duration,
+    pub from: f32,
+    pub to: f32,
+}
+
+impl HapticEnvelope {
+    fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+
+    fn intensity(&self, time: Duration) -> Option<f32> {
+        if time < self.start || time >= self.end() {
+            return None;
+        }
+
+        let t = (time - self.start).as_micros() as f64 / self.duration.as_micros() as f64;
+        Some(self.from + (self.to - self.from) * t as f32)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, ImDraw)]
+pub struct HapticPattern {
+    envelopes: Vec<HapticEnvelope>,
+}
+
+impl HapticPattern {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Overlapping envelopes of the same channel use the greatest intensity
+    pub fn envelope(mut self, envelope: HapticEnvelope) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&envelope.from) && (0.0..=1.0).contains(&envelope.to),
+            "[haptic_pattern envelope] intensity outside of [0, 1]"
+        );
+
+        self.envelopes.push(envelope);
+        self
+    }
+
+    pub fn ramp(self, channel: HapticChannel, start: Duration, duration: Duration, from: f32, to: f32) -> Self {
+        self.envelope(HapticEnvelope { channel, start, duration, from, to })
+    }
+
+    pub fn pulse(self, channel: HapticChannel, start: Duration, duration: Duration, intensity: f32) -> Self {
+        self.ramp(channel, start, duration, intensity, intensity)
+    }
+
+    pub fn envelopes(&self) -> &[HapticEnvelope] {
+        &self.envelopes
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.envelopes.iter().map(HapticEnvelope::end).max().unwrap_or_default()
+    }
+
+    // Intensity of every channel, indexed by HapticChannel
+    pub fn intensities(&self, time: Duration) -> [f32; HAPTIC_CHANNEL_COUNT] {
+        let mut intensities = [0.0f32; HAPTIC_CHANNEL_COUNT];
+        for envelope in self.envelopes.iter() {
+            if let Some(intensity) = envelope.intensity(time) {
+                let channel = &mut intensities[envelope.channel as usize];
+                *channel = channel.max(intensity);
+            }
+        }
+
+        intensities
+    }
+}
+
+pub(super) struct HapticPlayback {
+    pattern: HapticPattern,
+    start: GameTime,
+
+    last_sent: Option<([u16; HAPTIC_CHANNEL_COUNT], GameTime)>,
+}
+
+fn motor_value(intensity: f32) -> u16 {
+    (intensity.clamp(0.0, 1.0) * u16::MAX as f32) as u16
+}
+
+impl InputSystem {
+    // The haptics go to the live controllers, also during a playback
+    pub(super) fn live_devices_mut(&mut self) -> &mut InputDevices {
+        match self.playback.as_mut() {
+            Some(playback) => &mut playback.live_devices,
+            None => &mut self.devices,
+        }
+    }
+
+    fn send_haptics(&mut self, controller_index: usize, values: [u16; HAPTIC_CHANNEL_COUNT], duration_ms: u32) {
+        let devices = self.live_devices_mut();
+        if let Some(controller_state) = devices.controllers.controller_state_mut(controller_index) {
+            let low = values[HapticChannel::LowFrequency as usize];
+            let high = values[HapticChannel::HighFrequency as usize];
+            controller_state.set_rumble(low, high, duration_ms);
+
+            // @TODO trigger rumble (values of LeftTrigger and RightTrigger)
+        }
+    }
+
+    pub(super) fn play_haptic_pattern(&mut self, controller_index: usize, pattern: HapticPattern, now: GameTime) {
+        self.haptics.insert(controller_index, HapticPlayback {
+            pattern,
+            start: now,
+            last_sent: None,
+        });
+
+        self.update_haptics(now);
+    }
+
+    pub(super) fn stop_haptics(&mut self, controller_index: usize) {
+        if self.haptics.remove(&controller_index).is_some() {
+            self.send_haptics(controller_index, [0; HAPTIC_CHANNEL_COUNT], 0);
+        }
+    }
+
+    // Called once per frame
+    pub(in crate::app) fn update_haptics(&mut self, now: GameTime) {
+        let mut finished = Vec::new();
+        let mut updates = Vec::new();
+
+        let devices = match self.playback.as_ref() {
+            Some(playback) => &playback.live_devices,
+            None => &self.devices,
+        };
+
+        for (&index, playback) in self.haptics.iter_mut() {
+            let time = now.duration_since(playback.start);
+            if devices.controllers.controller_state(index).is_none() || time >= playback.pattern.duration() {
+                finished.push(index);
+                continue;
+            }
+
+            let values = playback.pattern.intensities(time).map(motor_value);
+            let resend = match playback.last_sent {
+                Some((last_values, last_time)) => {
+                    last_values != values || now.duration_since(last_time) >= HAPTIC_RESEND_INTERVAL
+                },
+                None => true,
+            };
+
+            if resend {
+                playback.last_sent = Some((values, now));
+                updates.push((index, values));
+            }
+        }
+
+        for (index, values) in updates {
+            self.send_haptics(index, values, HAPTIC_RUMBLE_TIMEOUT_MS);
+        }
+
+        for index in finished {
+            self.stop_haptics(index);
+        }
+    }
+
+    pub(super) fn is_playing_haptics(&self, controller_index: usize) -> bool {
+        self.haptics.contains_key(&controller_index)
+    }
+}
+
+impl<S> App<'_, S> {
+    // Replaces the pattern playing on the controller
+    pub fn play_haptic_pattern(&mut self, controller_index: usize, pattern: HapticPattern) {
+        let now = self.time_system.game_time();
+        self.input_system.play_haptic_pattern(controller_index, pattern, now);
+    }
+
+    pub fn stop_haptics(&mut self, controller_index: usize) {
+        self.input_system.stop_haptics(controller_index);
+    }
+
+    pub fn is_playing_haptics(&self, controller_index: usize) -> bool {
+        self.input_system.is_playing_haptics(controller_index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::event::InputEvent;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn test_haptic_pattern_intensities() {
+        let pattern = HapticPattern::new()
+            .ramp(HapticChannel::LowFrequency, ms(0), ms(100), 1.0, 0.0)
+            .pulse(HapticChannel::LowFrequency, ms(50), ms(20), 0.8)
+            .pulse(HapticChannel::RightTrigger, ms(100), ms(50), 0.5);
+
+        assert_eq!(pattern.duration(), ms(150));
+
+        let intensities = pattern.intensities(ms(25));
+        assert!((intensities[HapticChannel::LowFrequency as usize] - 0.75).abs() < 1e-6);
+        assert_eq!(intensities[HapticChannel::HighFrequency as usize], 0.0);
+
+        // overlapping envelopes
+        assert_eq!(pattern.intensities(ms(60))[HapticChannel::LowFrequency as usize], 0.8);
+
+        let intensities = pattern.intensities(ms(120));
+        assert_eq!(intensities[HapticChannel::LowFrequency as usize], 0.0);
+        assert_eq!(intensities[HapticChannel::RightTrigger as usize], 0.5);
+    }
+
+    #[test]
+    fn test_haptic_playback() {
+        let mut input_system = InputSystem::headless();
+        input_system.devices.apply(InputEvent::ControllerConnected(0), GameTime::default());
+
+        let pattern = HapticPattern::new().pulse(HapticChannel::HighFrequency, ms(0), ms(100), 1.0);
+        let start = GameTime::default() + ms(1_000);
+
+        input_system.play_haptic_pattern(0, pattern.clone(), start);
+        input_system.update_haptics(start + ms(50));
+        assert!(input_system.is_playing_haptics(0));
+
+        input_system.update_haptics(start + ms(100));
+        assert!(!input_system.is_playing_haptics(0));
+
+        // replaced
+        input_system.play_haptic_pattern(0, HapticPattern::new(), start);
+        input_system.play_haptic_pattern(0, pattern.clone(), start);
+        input_system.update_haptics(start + ms(10));
+        assert!(input_system.is_playing_haptics(0));
+
+        // stopped when the controller disconnects
+        input_system.devices.apply(InputEvent::ControllerDisconnected(0), start);
+        input_system.update_haptics(start + ms(20));
+        assert!(!input_system.is_playing_haptics(0));
+
+        // controllers not connected
+        input_system.play_haptic_pattern(3, pattern, start);
+        assert!(!input_system.is_playing_haptics(3));
+    }
+}
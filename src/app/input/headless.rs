@@ -0,0 +1,362 @@
+// Headless input
+//
+// Input system without SDL devices, driven frame by frame with input events on virtual devices
+// (keyboard, mouse, controllers and joysticks). Used to test the mapping logic on machines without
+// devices.
+
+/* Usage
+
+let mut input = HeadlessInput::new();
+
+input.send(InputEvent::ControllerConnected(0));
+input.send_at(Duration::from_millis(4), InputEvent::ControllerButtonDown(0, sdl2::controller::Button::A));
+input.frame(&mut [&mut mapping]);
+
+assert!(mapping.button("JUMP".to_string()).pressed());
+*/
+
+use crate::app::time_system::{Duration, GameTime};
+
+use super::{
+    event::InputEvent,
+    mapping::InputMapping,
+    system::InputSystem,
+};
+
+const HEADLESS_FRAME_DURATION: Duration = Duration::from_millis(16);
+
+pub(super) struct HeadlessInput {
+    pub(super) input_system: InputSystem,
+    time: GameTime,
+    // Events of the next frame, with their offset from the current frame time
+    queued: Vec<(Duration, InputEvent)>,
+}
+
+impl HeadlessInput {
+    pub(super) fn new() -> Self {
+        Self {
+            input_system: InputSystem::headless(),
+            time: GameTime::default(),
+            queued: Vec::new(),
+        }
+    }
+
+    // Current frame time
+    pub(super) fn time(&self) -> GameTime {
+        self.time
+    }
+
+    // The event happens at the next frame time
+    pub(super) fn send(&mut self, event: InputEvent) {
+        self.send_at(HEADLESS_FRAME_DURATION, event);
+    }
+
+    // The event happens during the next frame, offset from the current frame time
+    pub(super) fn send_at(&mut self, offset: Duration, event: InputEvent) {
+        assert!(offset <= HEADLESS_FRAME_DURATION, "[headless_input send_at] offset outside of the frame");
+        self.queued.push((offset, event));
+    }
+
+    // Advances one frame: applies the queued events and updates the mappings
+    pub(super) fn frame(&mut self, mappings: &mut [&mut InputMapping]) {
+        let frame_start = self.time;
+        self.time += HEADLESS_FRAME_DURATION;
+        self.input_system.new_frame();
+
+        // Stable sort: events with the same offset keep the sending order
+        self.queued.sort_by_key(|(offset, _)| *offset);
+        for (offset, event) in self.queued.drain(..) {
+            self.input_system.apply_input_event(event, frame_start + offset);
+        }
+
+        self.input_system.update_playback(self.time);
+
+        for mapping in mappings.iter_mut() {
+            self.input_system.update_input_mapping(mapping, self.time);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::sdl2::keyboard::Scancode;
+    use super::super::{
+        ControllerAxisThreshold,
+        axis::{Axis, MouseWheelAxis},
+        button::Button,
+        context::InputContextMode,
+        mapping::ControllerBind,
+        touch::TouchGesture,
+    };
+
+    fn mapping(button: Button) -> InputMapping {
+        let mut mapping = InputMapping::new();
+        mapping.add_button_mapping("ACTION".to_string(), button);
+        mapping
+    }
+
+    fn action(mapping: &InputMapping) -> &Button {
+        mapping.button("ACTION".to_string())
+    }
+
+    #[test]
+    fn test_headless_multiple_keys() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_key(Scancode::A);
+        button.add_key(Scancode::B);
+        let mut mapping = mapping(button);
+
+        // A down, B down, A up, B up: pressed+down, down, down, released
+        input.send(InputEvent::KeyDown(Scancode::A));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).down() && action(&mapping).pressed());
+
+        input.send(InputEvent::KeyDown(Scancode::B));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).down() && !action(&mapping).pressed());
+
+        input.send(InputEvent::KeyUp(Scancode::A));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).down() && !action(&mapping).released());
+
+        input.send(InputEvent::KeyUp(Scancode::B));
+        input.frame(&mut [&mut mapping]);
+        assert!(!action(&mapping).down() && action(&mapping).released());
+        assert_eq!(action(&mapping).timestamp(), input.time());
+
+        input.frame(&mut [&mut mapping]);
+        assert!(!action(&mapping).released());
+    }
+
+    #[test]
+    fn test_headless_sub_frame_timestamps() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_mouse_button(sdl2::mouse::MouseButton::Left);
+        let mut mapping = mapping(button);
+
+        input.frame(&mut [&mut mapping]);
+
+        let frame_start = input.time();
+        input.send_at(Duration::from_millis(5), InputEvent::MouseButtonDown(sdl2::mouse::MouseButton::Left));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).pressed());
+        assert_eq!(action(&mapping).timestamp(), frame_start + Duration::from_millis(5));
+    }
+
+    #[test]
+    fn test_headless_controller_bind() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_controller_button(sdl2::controller::Button::A);
+        let mut mapping = mapping(button);
+        mapping.set_controller_bind(ControllerBind::Index(1));
+
+        input.send(InputEvent::ControllerConnected(0));
+        input.send(InputEvent::ControllerConnected(1));
+        input.send(InputEvent::ControllerButtonDown(0, sdl2::controller::Button::A));
+        input.frame(&mut [&mut mapping]);
+        assert!(!action(&mapping).down());
+
+        input.send(InputEvent::ControllerButtonDown(1, sdl2::controller::Button::A));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).pressed());
+
+        // disconnecting the bound controller releases the button
+        input.send(InputEvent::ControllerDisconnected(1));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).released());
+    }
+
+    #[test]
+    fn test_headless_joystick_bind() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_joystick_button(3);
+        let mut player_1 = mapping(button);
+        player_1.set_joystick_bind(ControllerBind::Index(0));
+
+        let mut button = Button::new();
+        button.add_joystick_button(3);
+        let mut player_2 = mapping(button);
+        player_2.set_joystick_bind(ControllerBind::Index(1));
+
+        input.send(InputEvent::JoystickConnected(0));
+        input.send(InputEvent::JoystickConnected(1));
+        input.send(InputEvent::JoystickButtonDown(1, 3));
+        input.frame(&mut [&mut player_1, &mut player_2]);
+        assert!(!action(&player_1).down());
+        assert!(action(&player_2).pressed());
+
+        input.send(InputEvent::JoystickButtonDown(0, 3));
+        input.frame(&mut [&mut player_1, &mut player_2]);
+        assert!(action(&player_1).pressed());
+        assert!(action(&player_2).down() && !action(&player_2).pressed());
+    }
+
+    #[test]
+    fn test_headless_controller_axis_threshold() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_controller_axis(sdl2::controller::Axis::LeftY, ControllerAxisThreshold::lesser_than(-0.5));
+        let mut mapping = mapping(button);
+
+        input.send(InputEvent::ControllerConnected(0));
+        input.send(InputEvent::ControllerAxisMotion(0, sdl2::controller::Axis::LeftY, -0.3));
+        input.frame(&mut [&mut mapping]);
+        assert!(!action(&mapping).down());
+
+        let frame_start = input.time();
+        input.send_at(
+            Duration::from_millis(10),
+            InputEvent::ControllerAxisMotion(0, sdl2::controller::Axis::LeftY, -0.8)
+        );
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).pressed());
+        assert_eq!(action(&mapping).timestamp(), frame_start + Duration::from_millis(10));
+
+        // still over the threshold
+        input.send(InputEvent::ControllerAxisMotion(0, sdl2::controller::Axis::LeftY, -0.9));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).down() && !action(&mapping).pressed());
+
+        input.send(InputEvent::ControllerAxisMotion(0, sdl2::controller::Axis::LeftY, 0.0));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).released());
+    }
+
+    #[test]
+    fn test_headless_context_consume() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_key(Scancode::Space);
+        let mut gameplay = mapping(button);
+        gameplay.set_context("gameplay");
+
+        let mut menu = InputMapping::new();
+        menu.set_context("menu");
+
+        input.input_system.push_input_context(&gameplay, InputContextMode::Passthrough);
+
+        input.send(InputEvent::KeyDown(Scancode::Space));
+        input.frame(&mut [&mut gameplay]);
+        assert!(action(&gameplay).pressed());
+
+        // consumed while held: released without a release event
+        input.input_system.push_input_context(&menu, InputContextMode::ConsumeAll);
+        input.frame(&mut [&mut gameplay]);
+        assert!(action(&gameplay).released());
+        assert_eq!(action(&gameplay).timestamp(), input.time());
+
+        // still held when the menu closes: down, but not a new press
+        input.input_system.pop_input_context("menu");
+        input.frame(&mut [&mut gameplay]);
+        assert!(action(&gameplay).down() && !action(&gameplay).pressed());
+    }
+
+    #[test]
+    fn test_headless_lost_events() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_key(Scancode::A);
+        button.add_controller_button(sdl2::controller::Button::A);
+        let mut mapping = mapping(button);
+
+        // repeated press (release lost)
+        input.send_at(Duration::from_millis(2), InputEvent::KeyDown(Scancode::A));
+        input.send_at(Duration::from_millis(8), InputEvent::KeyDown(Scancode::A));
+        input.frame(&mut [&mut mapping]);
+        assert_eq!(action(&mapping).timestamp(), GameTime::from_micros(2_000));
+
+        // devices not connected or out of range
+        input.send(InputEvent::ControllerButtonDown(3, sdl2::controller::Button::A));
+        input.send(InputEvent::ControllerConnected(100));
+        input.send(InputEvent::JoystickHatMotion(100, 0, 1));
+        input.send(InputEvent::KeyUp(Scancode::A));
+        input.send(InputEvent::KeyUp(Scancode::A));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).released());
+    }
+
+    #[test]
+    fn test_headless_focus_resync() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_key(Scancode::A);
+        let mut mapping = mapping(button);
+
+        input.send(InputEvent::KeyDown(Scancode::A));
+        input.frame(&mut [&mut mapping]);
+
+        // focus lost while held
+        let time = input.time();
+        input.input_system.resync_keyboard_and_mouse(&[], &[], time);
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).released());
+
+        // focus gained while held
+        let time = input.time();
+        input.input_system.resync_keyboard_and_mouse(&[Scancode::A], &[], time);
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).pressed());
+
+        // the release event arrives after the resync
+        input.send(InputEvent::KeyUp(Scancode::A));
+        input.frame(&mut [&mut mapping]);
+        assert!(action(&mapping).released());
+    }
+
+    #[test]
+    fn test_headless_mouse_wheel_and_motion() {
+        let mut input = HeadlessInput::new();
+        let mut axis = Axis::new();
+        axis.add_mouse_wheel(MouseWheelAxis::Vertical);
+        let mut mapping = InputMapping::new();
+        mapping.add_axis_mapping("SCROLL".to_string(), axis);
+
+        input.send(InputEvent::MouseWheel(0, 1));
+        input.send(InputEvent::MouseWheel(0, 1));
+        input.send(InputEvent::MouseMotion(10, 10, 4, -2));
+        input.send(InputEvent::MouseMotion(12, 9, 2, -1));
+        input.frame(&mut [&mut mapping]);
+        assert_eq!(mapping.axis("SCROLL".to_string()).value(), 1.0);
+        assert_eq!(input.input_system.devices.mouse.get_wheel(), (0, 2));
+        assert_eq!(input.input_system.devices.mouse.get_motion(), (6, -3));
+        assert_eq!(input.input_system.devices.mouse.get_pos(), (12, 9));
+
+        // accumulated per frame
+        input.frame(&mut [&mut mapping]);
+        assert_eq!(mapping.axis("SCROLL".to_string()).value(), 0.0);
+        assert_eq!(input.input_system.devices.mouse.get_motion(), (0, 0));
+        assert_eq!(input.input_system.devices.mouse.get_pos(), (12, 9));
+    }
+
+    #[test]
+    fn test_headless_touch_gestures() {
+        let mut input = HeadlessInput::new();
+        let mut button = Button::new();
+        button.add_touch_gesture(TouchGesture::Tap);
+        let mut tap = mapping(button);
+        let mut button = Button::new();
+        button.add_touch_gesture(TouchGesture::Touch);
+        let mut touch = mapping(button);
+
+        input.send_at(Duration::from_millis(2), InputEvent::FingerDown(1, 0.5, 0.5));
+        input.frame(&mut [&mut tap, &mut touch]);
+        assert!(!action(&tap).down());
+        assert!(action(&touch).pressed());
+
+        let frame_start = input.time();
+        input.send_at(Duration::from_millis(6), InputEvent::FingerUp(1, 0.5, 0.5));
+        input.frame(&mut [&mut tap, &mut touch]);
+        assert!(action(&tap).pressed());
+        assert_eq!(action(&tap).timestamp(), frame_start + Duration::from_millis(6));
+        assert!(action(&touch).released());
+
+        // down for a single frame
+        input.frame(&mut [&mut tap, &mut touch]);
+        assert!(action(&tap).released());
+    }
+}
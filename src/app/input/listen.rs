@@ -0,0 +1,206 @@
+// Listen mode
+//
+// Captures the next physical input (key, mouse button, controller button or axis direction) so the
+// player can rebind the controls. The captured input still updates the input state as usual.
+
+/* Usage
+
+// "press a key for LANE_1"
+app.listen_for_input();
+
+// update
+
+if let Some(input) = app.listened_input() {
+    let conflicts = self.input_mapping.button_conflicts(input.source);
+    if conflicts.is_empty() {
+        self.input_mapping.button_mut("LANE_1".to_string()).add_source(input.source);
+    } else {
+        // ask to swap with conflicts[0]
+    }
+}
+*/
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+};
+use super::{
+    ControllerAxisThreshold,
+    button::ButtonSource,
+    event::InputEvent,
+    mapping::InputDevice,
+    system::{InputSystem, JOYSTICK_HAT_DIRECTIONS},
+};
+
+// Axis motion needed to capture an axis direction
+const LISTEN_AXIS_THRESHOLD: f32 = 0.5;
+
+#[derive(Copy, Clone, Debug, PartialEq, ImDraw)]
+pub struct ListenedInput {
+    pub source: ButtonSource,
+    pub device: InputDevice,
+}
+
+#[derive(Default)]
+pub(super) struct ListenState {
+    captured: Option<ListenedInput>,
+}
+
+impl InputSystem {
+    pub(super) fn update_listen(&mut self, event: InputEvent) {
+        let listen = match self.listen.as_ref() {
+            Some(listen) => listen,
+            None => return,
+        };
+
+        if listen.captured.is_some() {
+            return;
+        }
+
+        // Axes are captured when crossing the threshold, so axes already past it are ignored
+        let crossed = |previous: f32, value: f32| {
+            value.abs() > LISTEN_AXIS_THRESHOLD && previous * value.signum() <= LISTEN_AXIS_THRESHOLD
+        };
+        let controller_axis = |index: usize, axis: sdl2::controller::Axis| {
+            self.devices.controllers.controller_state(index).map_or(0.0, |c| c.axis_state(axis).value)
+        };
+        let joystick_axis = |index: usize, axis: u8| {
+            self.devices.joysticks.joystick_state(index).map_or(0.0, |j| j.axis_state(axis).value)
+        };
+
+        let axis_threshold = |value: f32| {
+            if value > 0.0 {
+                ControllerAxisThreshold::greater_than(LISTEN_AXIS_THRESHOLD)
+            } else {
+                ControllerAxisThreshold::lesser_than(-LISTEN_AXIS_THRESHOLD)
+            }
+        };
+
+        let captured = match event {
+            InputEvent::KeyDown(key) => Some((ButtonSource::Key(key), InputDevice::Keyboard)),
+
+            InputEvent::MouseButtonDown(button) => {
+                Some((ButtonSource::MouseButton(button), InputDevice::Mouse))
+            },
+
+            InputEvent::ControllerButtonDown(index, button) => {
+                Some((ButtonSource::ControllerButton(button), InputDevice::Controller(index)))
+            },
+
+            InputEvent::ControllerAxisMotion(index, axis, value)
+                if crossed(controller_axis(index, axis), value) => Some((
+                    ButtonSource::ControllerAxis(axis, axis_threshold(value)),
+                    InputDevice::Controller(index)
+                )),
+
+            InputEvent::JoystickButtonDown(index, button) => {
+                Some((ButtonSource::JoystickButton(button), InputDevice::Joystick(index)))
+            },
+
+            InputEvent::JoystickAxisMotion(index, axis, value)
+                if crossed(joystick_axis(index, axis), value) => Some((
+                    ButtonSource::JoystickAxis(axis, axis_threshold(value)),
+                    InputDevice::Joystick(index)
+                )),
+
+            InputEvent::JoystickHatMotion(index, hat, bits) => {
+                JOYSTICK_HAT_DIRECTIONS.iter()
+                    .find(|direction| direction.is_set(bits))
+                    .map(|direction| (ButtonSource::JoystickHat(hat, *direction), InputDevice::Joystick(index)))
+            },
+
+            _ => None,
+        };
+
+        if let Some((source, device)) = captured {
+            self.listen.as_mut().unwrap().captured = Some(ListenedInput { source, device });
+        }
+    }
+}
+
+impl<S> App<'_, S> {
+    // Inputs already held when listening starts are not captured until pressed again (axes, until
+    // they cross the threshold again)
+    pub fn listen_for_input(&mut self) {
+        self.input_system.listen = Some(ListenState::default());
+    }
+
+    pub fn stop_listening_for_input(&mut self) {
+        self.input_system.listen = None;
+    }
+
+    pub fn is_listening_for_input(&self) -> bool {
+        self.input_system.listen.is_some()
+    }
+
+    // Returns the captured input and stops listening
+    pub fn listened_input(&mut self) -> Option<ListenedInput> {
+        let captured = self.input_system.listen.as_ref().and_then(|listen| listen.captured);
+        if captured.is_some() {
+            self.input_system.listen = None;
+        }
+
+        captured
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::sdl2::keyboard::Scancode;
+    use super::super::headless::HeadlessInput;
+
+    fn listen(input: &mut HeadlessInput) {
+        input.input_system.listen = Some(ListenState::default());
+    }
+
+    fn captured(input: &HeadlessInput) -> Option<ListenedInput> {
+        input.input_system.listen.as_ref().and_then(|listen| listen.captured)
+    }
+
+    #[test]
+    fn test_listen_key() {
+        let mut input = HeadlessInput::new();
+        listen(&mut input);
+
+        input.send(InputEvent::KeyUp(Scancode::A));
+        input.frame(&mut []);
+        assert_eq!(captured(&input), None);
+
+        // the first input is kept
+        input.send(InputEvent::KeyDown(Scancode::B));
+        input.send(InputEvent::KeyDown(Scancode::C));
+        input.frame(&mut []);
+        assert_eq!(captured(&input), Some(ListenedInput {
+            source: ButtonSource::Key(Scancode::B),
+            device: InputDevice::Keyboard,
+        }));
+    }
+
+    #[test]
+    fn test_listen_axis_crossing() {
+        let axis = sdl2::controller::Axis::TriggerLeft;
+        let mut input = HeadlessInput::new();
+        input.send(InputEvent::ControllerConnected(0));
+        input.send(InputEvent::ControllerAxisMotion(0, axis, 0.8));
+        input.frame(&mut []);
+
+        // already past the threshold
+        listen(&mut input);
+        input.send(InputEvent::ControllerAxisMotion(0, axis, 0.9));
+        input.frame(&mut []);
+        assert_eq!(captured(&input), None);
+
+        input.send(InputEvent::ControllerAxisMotion(0, axis, 0.2));
+        input.send(InputEvent::ControllerAxisMotion(0, axis, 0.4));
+        input.frame(&mut []);
+        assert_eq!(captured(&input), None);
+
+        input.send(InputEvent::ControllerAxisMotion(0, axis, 0.7));
+        input.frame(&mut []);
+        assert_eq!(captured(&input), Some(ListenedInput {
+            source: ButtonSource::ControllerAxis(axis, ControllerAxisThreshold::greater_than(LISTEN_AXIS_THRESHOLD)),
+            device: InputDevice::Controller(0),
+        }));
+    }
+}
@@ -1,34 +1,145 @@
 use std::collections::BTreeMap;
 
-use crate::app::imdraw::ImDraw;
-use super::button::Button;
-
-// @XXX Somehow we should be able to verify if the last key pressed was on the keyboard/mouse
-//      or gamepad
+use crate::app::{
+    imdraw::ImDraw,
+    time_system::GameTime,
+};
+use super::{
+    axis::{Axis, Axis2D},
+    buffer::InputBuffer,
+    button::{Button, ButtonSource},
+};
 
 /*
-// @TODO bind the controller to the input mapping, instead of to the button
-enum ControllerBind {
+    // local multiplayer: one mapping per player, each bound to its own controller (or joystick)
+    player_1_mapping.set_controller_bind(ControllerBind::Index(0));
+    player_2_mapping.set_controller_bind(ControllerBind::Index(1));
+    player_2_mapping.set_joystick_bind(ControllerBind::No);
+
+    // update
+
+    app.update_input_mapping(&mut player_1_mapping);
+
+    let glyphs = match player_1_mapping.last_device() {
+        Some(device) if device.is_gamepad() => &gamepad_glyphs,
+        _ => &keyboard_glyphs,
+    };
+*/
+
+// Controllers used by the controller sources (buttons and axes) of the mapping. Also used for the
+// joysticks of the joystick sources, which have their own indices
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ControllerBind {
     No,
     Any,
     Index(usize),
 }
 
 impl_imdraw_todo!(ControllerBind);
-*/
+
+impl ControllerBind {
+    pub fn includes(&self, controller_index: usize) -> bool {
+        match *self {
+            ControllerBind::No => false,
+            ControllerBind::Any => true,
+            ControllerBind::Index(index) => index == controller_index,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InputDevice {
+    Keyboard,
+    Mouse,
+    Controller(usize),
+    Joystick(usize),
+    Touch,
+}
+
+impl_imdraw_todo!(InputDevice);
+
+impl InputDevice {
+    pub fn is_gamepad(&self) -> bool {
+        matches!(self, InputDevice::Controller(_) | InputDevice::Joystick(_))
+    }
+
+    // Keyboard, mouse and touch are shared by all mappings
+    fn is_bound(&self, controller_bind: ControllerBind, joystick_bind: ControllerBind) -> bool {
+        match *self {
+            InputDevice::Keyboard | InputDevice::Mouse | InputDevice::Touch => true,
+            InputDevice::Controller(index) => controller_bind.includes(index),
+            InputDevice::Joystick(index) => joystick_bind.includes(index),
+        }
+    }
+}
 
 // @TODO use hashed string
 #[derive(ImDraw)]
 pub struct InputMapping {
     pub(super) button_mapping: BTreeMap<String, Button>,
-    //controller_bind: ControllerBind,
+    pub(super) axis_mapping: BTreeMap<String, Axis>,
+    pub(super) axis_2d_mapping: BTreeMap<String, Axis2D>,
+    pub(super) controller_bind: ControllerBind,
+    pub(super) joystick_bind: ControllerBind,
+    pub(super) context: Option<String>,
+    pub(super) buffer: InputBuffer,
+    last_device: Option<InputDevice>,
 }
 
 impl InputMapping {
     pub fn new() -> Self {
         Self {
             button_mapping: BTreeMap::new(),
-            //controller_bind: ControllerBind::No,
+            axis_mapping: BTreeMap::new(),
+            axis_2d_mapping: BTreeMap::new(),
+            controller_bind: ControllerBind::Any,
+            joystick_bind: ControllerBind::Any,
+            context: None,
+            buffer: InputBuffer::new(),
+            last_device: None,
+        }
+    }
+
+    // Input context of the mapping (see context.rs)
+    pub fn set_context(&mut self, context: &str) {
+        self.context = Some(context.to_string());
+    }
+
+    pub fn context(&self) -> Option<&str> {
+        self.context.as_deref()
+    }
+
+    pub fn set_controller_bind(&mut self, controller_bind: ControllerBind) {
+        self.controller_bind = controller_bind;
+    }
+
+    pub fn controller_bind(&self) -> ControllerBind {
+        self.controller_bind
+    }
+
+    pub fn set_joystick_bind(&mut self, joystick_bind: ControllerBind) {
+        self.joystick_bind = joystick_bind;
+    }
+
+    pub fn joystick_bind(&self) -> ControllerBind {
+        self.joystick_bind
+    }
+
+    // Device with the most recent activity among the ones bound to the mapping. Useful to show
+    // keyboard or gamepad prompts
+    pub fn last_device(&self) -> Option<InputDevice> {
+        self.last_device
+    }
+
+    pub(super) fn update_last_device(&mut self, device_activity: &BTreeMap<InputDevice, GameTime>) {
+        let (controller_bind, joystick_bind) = (self.controller_bind, self.joystick_bind);
+        let last_device = device_activity.iter()
+            .filter(|(device, _)| device.is_bound(controller_bind, joystick_bind))
+            .max_by_key(|(_, &timestamp)| timestamp)
+            .map(|(&device, _)| device);
+
+        if last_device.is_some() {
+            self.last_device = last_device;
         }
     }
 
@@ -44,6 +155,146 @@ impl InputMapping {
 
     pub fn button(&self, name: String) -> &Button {
         self.button_mapping.get(&name)
-            .expect(&format!("[input_system mapping] No mapping found for button: {}", name))
+            .unwrap_or_else(|| panic!("[input_system mapping] No mapping found for button: {}", name))
+    }
+
+    pub fn button_mut(&mut self, name: String) -> &mut Button {
+        self.button_mapping.get_mut(&name)
+            .unwrap_or_else(|| panic!("[input_system mapping] No mapping found for button: {}", name))
+    }
+
+    // Buttons and axes (1D and 2D) already using the source. Used to detect conflicts when rebinding.
+    // Axes use both directions of their inputs. Touch gestures don't conflict with the touch axes
+    pub fn button_conflicts(&self, source: ButtonSource) -> Vec<&str> {
+        let input = match source {
+            ButtonSource::TouchGesture(_) => None,
+            _ => Some(source.physical_input()),
+        };
+        let uses_input = |axis: &Axis| match input {
+            Some(input) => axis.sources().iter().any(|axis_source| axis_source.physical_inputs().contains(&input)),
+            None => false,
+        };
+
+        let buttons = self.button_mapping.iter()
+            .filter(|(_, button)| button.has_source(source))
+            .map(|(name, _)| name.as_str());
+
+        let axes = self.axis_mapping.iter()
+            .filter(|(_, axis)| uses_input(axis))
+            .map(|(name, _)| name.as_str());
+
+        let axes_2d = self.axis_2d_mapping.iter()
+            .filter(|(_, axis)| uses_input(&axis.x) || uses_input(&axis.y))
+            .map(|(name, _)| name.as_str());
+
+        buttons.chain(axes).chain(axes_2d).collect()
+    }
+
+    pub fn add_axis_mapping(&mut self, name: String, axis: Axis) {
+        match self.axis_mapping.insert(name, axis) {
+            None => {},
+            Some(_) => panic!("[input mapping] overwriting axis mapping"),
+        }
+    }
+
+    pub fn axis(&self, name: String) -> &Axis {
+        self.axis_mapping.get(&name)
+            .unwrap_or_else(|| panic!("[input_system mapping] No mapping found for axis: {}", name))
+    }
+
+    pub fn add_axis_2d_mapping(&mut self, name: String, axis: Axis2D) {
+        match self.axis_2d_mapping.insert(name, axis) {
+            None => {},
+            Some(_) => panic!("[input mapping] overwriting axis 2d mapping"),
+        }
+    }
+
+    pub fn axis_2d(&self, name: String) -> &Axis2D {
+        self.axis_2d_mapping.get(&name)
+            .unwrap_or_else(|| panic!("[input_system mapping] No mapping found for axis 2d: {}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::keyboard::Scancode;
+    use super::super::ControllerAxisThreshold;
+
+    #[test]
+    fn test_mapping_button_conflicts() {
+        let mut mapping = InputMapping::new();
+
+        let mut button = Button::new();
+        button.add_key(Scancode::D);
+        mapping.add_button_mapping("LANE_1".to_string(), button);
+
+        let mut button = Button::new();
+        button.add_key(Scancode::F);
+        mapping.add_button_mapping("LANE_2".to_string(), button);
+
+        assert_eq!(mapping.button_conflicts(ButtonSource::Key(Scancode::F)), vec!["LANE_2"]);
+        assert!(mapping.button_conflicts(ButtonSource::Key(Scancode::J)).is_empty());
+
+        // swap
+        mapping.button_mut("LANE_2".to_string()).clear();
+        mapping.button_mut("LANE_2".to_string()).add_source(ButtonSource::Key(Scancode::D));
+        mapping.button_mut("LANE_1".to_string()).rem_source(ButtonSource::Key(Scancode::D));
+
+        assert_eq!(mapping.button_conflicts(ButtonSource::Key(Scancode::D)), vec!["LANE_2"]);
+
+        // axes
+        let mut axis = Axis::new();
+        axis.add_keys(Scancode::Left, Scancode::Right);
+        axis.add_controller_axis(sdl2::controller::Axis::LeftX);
+        mapping.add_axis_mapping("SCROLL".to_string(), axis);
+
+        let mut y = Axis::new();
+        y.add_keys(Scancode::Down, Scancode::D);
+        mapping.add_axis_2d_mapping("MOVE".to_string(), Axis2D::new(Axis::new(), y));
+
+        assert_eq!(mapping.button_conflicts(ButtonSource::Key(Scancode::Right)), vec!["SCROLL"]);
+        assert_eq!(mapping.button_conflicts(ButtonSource::Key(Scancode::D)), vec!["LANE_2", "MOVE"]);
+
+        let threshold = ControllerAxisThreshold::lesser_than(-0.5);
+        let source = ButtonSource::ControllerAxis(sdl2::controller::Axis::LeftX, threshold);
+        assert_eq!(mapping.button_conflicts(source), vec!["SCROLL"]);
+    }
+
+    #[test]
+    fn test_mapping_last_device() {
+        let mut device_activity = BTreeMap::new();
+        device_activity.insert(InputDevice::Keyboard, GameTime::from_micros(10));
+        device_activity.insert(InputDevice::Controller(1), GameTime::from_micros(20));
+
+        let mut mapping = InputMapping::new();
+        assert_eq!(mapping.last_device(), None);
+
+        mapping.update_last_device(&device_activity);
+        assert_eq!(mapping.last_device(), Some(InputDevice::Controller(1)));
+
+        // activity on other player controller is ignored
+        mapping.set_controller_bind(ControllerBind::Index(0));
+        mapping.update_last_device(&device_activity);
+        assert_eq!(mapping.last_device(), Some(InputDevice::Keyboard));
+
+        device_activity.insert(InputDevice::Controller(0), GameTime::from_micros(30));
+        mapping.update_last_device(&device_activity);
+        assert_eq!(mapping.last_device(), Some(InputDevice::Controller(0)));
+        assert!(mapping.last_device().unwrap().is_gamepad());
+
+        mapping.set_controller_bind(ControllerBind::No);
+        mapping.update_last_device(&device_activity);
+        assert_eq!(mapping.last_device(), Some(InputDevice::Keyboard));
+
+        // joysticks follow the joystick bind
+        device_activity.insert(InputDevice::Joystick(1), GameTime::from_micros(40));
+        mapping.set_joystick_bind(ControllerBind::Index(0));
+        mapping.update_last_device(&device_activity);
+        assert_eq!(mapping.last_device(), Some(InputDevice::Keyboard));
+
+        mapping.set_joystick_bind(ControllerBind::Index(1));
+        mapping.update_last_device(&device_activity);
+        assert_eq!(mapping.last_device(), Some(InputDevice::Joystick(1)));
     }
 }
@@ -7,9 +7,8 @@ let mut input_mapping = InputMapping::new();
 {
     let mut button = Button::new();
     button.add_key(sdl2::keyboard::Scancode::W);
-    button.add_controller_button(0, sdl2::controller::Button::DPadUp);
+    button.add_controller_button(sdl2::controller::Button::DPadUp);
     button.add_controller_axis(
-        0,
         sdl2::controller::Axis::LeftY,
         ControllerAxisThreshold::lesser_than(-0.5)
     );
@@ -20,9 +19,8 @@ let mut input_mapping = InputMapping::new();
 {
     let mut button = Button::new();
     button.add_key(sdl2::keyboard::Scancode::S);
-    button.add_controller_button(0, sdl2::controller::Button::DPadDown);
+    button.add_controller_button(sdl2::controller::Button::DPadDown);
     button.add_controller_axis(
-        0,
         sdl2::controller::Axis::LeftY,
         ControllerAxisThreshold::greater_than(0.5)
     );
@@ -33,9 +31,8 @@ let mut input_mapping = InputMapping::new();
 {
     let mut button = Button::new();
     button.add_key(sdl2::keyboard::Scancode::D);
-    button.add_controller_button(0, sdl2::controller::Button::DPadRight);
+    button.add_controller_button(sdl2::controller::Button::DPadRight);
     button.add_controller_axis(
-        0,
         sdl2::controller::Axis::LeftX,
         ControllerAxisThreshold::greater_than(0.5)
     );
@@ -46,9 +43,8 @@ let mut input_mapping = InputMapping::new();
 {
     let mut button = Button::new();
     button.add_key(sdl2::keyboard::Scancode::A);
-    button.add_controller_button(0, sdl2::controller::Button::DPadLeft);
+    button.add_controller_button(sdl2::controller::Button::DPadLeft);
     button.add_controller_axis(
-        0,
         sdl2::controller::Axis::LeftX,
         ControllerAxisThreshold::lesser_than(-0.5)
     );
@@ -71,18 +67,32 @@ let move_direction = Vec2 {
 
 */
 
+pub mod axis;
+pub mod buffer;
 pub mod button;
+pub mod config;
+pub mod context;
+pub mod event;
+pub mod haptics;
+#[cfg(test)]
+mod headless;
+pub mod listen;
 pub mod mapping;
+pub mod recording;
 pub mod system;
+pub mod text;
+pub mod touch;
 
+pub use axis::*;
 pub use button::*;
 pub use mapping::*;
 pub(in crate::app) use system::*; // @XXX how to avoid this???
+pub use system::JoystickHatDirection;
 
 use crate::app::imdraw::ImDraw;
 
 
-#[derive(Copy, Clone, Debug, ImDraw)]
+#[derive(Copy, Clone, Debug, PartialEq, ImDraw)]
 pub struct ControllerAxisThreshold {
     value: f32,
     direction: ControllerAxisDirection
@@ -111,3 +121,4 @@ impl ControllerAxisThreshold {
 }
 
 impl_imdraw_todo!(ControllerAxisDirection);
+impl_imdraw_todo!(JoystickHatDirection);
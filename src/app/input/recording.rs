@@ -0,0 +1,503 @@
+// Input recording
+//
+// Records the input events handled by the input system (with their time since the recording start)
+// and feeds them back through it. During playback the mappings only see the replayed devices, so a
+// song play can be replayed exactly (bug reports, ghost runs, automated tests of input mappings).
+
+/* Format
+
+input_recording 3
+duration 5000000                # microseconds
+0 MouseMotion 640 360 0 0       # <microseconds since start> <event> <values>
+16000 KeyDown W
+16000 ControllerConnected 0
+33000 ControllerAxisMotion 0 LeftX 0.75
+50000 JoystickHatMotion 0 0 3   # <joystick index> <hat> <direction bits>
+66000 FingerDown 1 0.5 0.25     # <finger id> <normalized position>
+*/
+
+/* Usage
+
+app.start_input_recording();
+
+// song ends
+
+let recording = app.stop_input_recording();
+recording.save("replays/last.rec").unwrap();
+
+// later
+
+let recording = InputRecording::load("replays/last.rec").unwrap();
+app.play_input_recording(recording);
+
+// update (the mappings read the replayed input)
+
+app.update_input_mapping(&mut self.input_mapping);
+
+if !app.is_playing_input_recording() {
+    // replay finished
+}
+*/
+
+use std::fs;
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+    time_system::{Duration, GameTime},
+};
+
+use super::{
+    config::{parse_controller_axis, parse_controller_button, parse_mouse_button, parse_number, parse_scancode},
+    event::InputEvent,
+    system::{InputDevices, InputSystem},
+};
+
+// Older versions are still read
+// 2: mouse relative motion and wheel
+// 3: touch
+const RECORDING_VERSION: u32 = 3;
+
+#[derive(Copy, Clone, Debug, PartialEq, ImDraw)]
+pub struct RecordedInputEvent {
+    // Since the recording start
+    pub time: Duration,
+    pub event: InputEvent,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, ImDraw)]
+pub struct InputRecording {
+    events: Vec<RecordedInputEvent>,
+    duration: Duration,
+}
+
+impl InputRecording {
+    // Ordered by time. The first ones bring the devices to their state when the recording started
+    pub fn events(&self) -> &[RecordedInputEvent] {
+        &self.events
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("[input_recording load] could not read {}: {}", path, e))?;
+
+        Self::from_recording_str(&text)
+            .map_err(|e| format!("[input_recording load] {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_recording_string())
+            .map_err(|e| format!("[input_recording save] could not write {}: {}", path, e))
+    }
+
+    pub fn to_recording_string(&self) -> String {
+        let mut out = format!("input_recording {}\n", RECORDING_VERSION);
+        out.push_str(&format!("duration {}\n", self.duration.as_micros()));
+
+        for recorded in self.events.iter() {
+            out.push_str(&format!("{} {}\n", recorded.time.as_micros(), event_string(recorded.event)));
+        }
+
+        out
+    }
+
+    // Errors name the line
+    pub fn from_recording_str(text: &str) -> Result<Self, String> {
+        let mut lines = text.lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+
+        let version = match lines.next() {
+            Some((number, line)) => match line.strip_prefix("input_recording ").map(|v| v.trim().parse::<u32>()) {
+                Some(Ok(version)) if (1..=RECORDING_VERSION).contains(&version) => version,
+                Some(Ok(version)) => return Err(format!("line {}: unsupported version {}", number, version)),
+                _ => return Err(format!("line {}: invalid header '{}'", number, line)),
+            },
+            None => return Err("empty recording".to_string()),
+        };
+
+        let duration = match lines.next() {
+            Some((number, line)) => match line.strip_prefix("duration ") {
+                Some(value) => parse_number(value.trim())
+                    .map(Duration::from_micros)
+                    .map_err(|e| format!("line {}: {}", number, e))?,
+                None => return Err(format!("line {}: expected duration", number)),
+            },
+            None => return Err("missing duration".to_string()),
+        };
+
+        let mut events: Vec<RecordedInputEvent> = Vec::new();
+        for (number, line) in lines {
+            let recorded = parse_recorded_event(line, version).map_err(|e| format!("line {}: {}", number, e))?;
+
+            if events.last().is_some_and(|last| last.time > recorded.time) {
+                return Err(format!("line {}: event out of order", number));
+            }
+
+            events.push(recorded);
+        }
+
+        Ok(Self { events, duration })
+    }
+}
+
+fn event_string(event: InputEvent) -> String {
+    match event {
+        InputEvent::KeyDown(key) => format!("KeyDown {:?}", key),
+        InputEvent::KeyUp(key) => format!("KeyUp {:?}", key),
+
+        InputEvent::MouseButtonDown(button) => format!("MouseButtonDown {:?}", button),
+        InputEvent::MouseButtonUp(button) => format!("MouseButtonUp {:?}", button),
+        InputEvent::MouseMotion(x, y, x_rel, y_rel) => format!("MouseMotion {} {} {} {}", x, y, x_rel, y_rel),
+        InputEvent::MouseWheel(x, y) => format!("MouseWheel {} {}", x, y),
+
+        InputEvent::ControllerConnected(index) => format!("ControllerConnected {}", index),
+        InputEvent::ControllerDisconnected(index) => format!("ControllerDisconnected {}", index),
+        InputEvent::ControllerButtonDown(index, button) => format!("ControllerButtonDown {} {:?}", index, button),
+        InputEvent::ControllerButtonUp(index, button) => format!("ControllerButtonUp {} {:?}", index, button),
+        InputEvent::ControllerAxisMotion(index, axis, value) => {
+            format!("ControllerAxisMotion {} {:?} {}", index, axis, value)
+        },
+
+        InputEvent::JoystickConnected(index) => format!("JoystickConnected {}", index),
+        InputEvent::JoystickDisconnected(index) => format!("JoystickDisconnected {}", index),
+        InputEvent::JoystickButtonDown(index, button) => format!("JoystickButtonDown {} {}", index, button),
+        InputEvent::JoystickButtonUp(index, button) => format!("JoystickButtonUp {} {}", index, button),
+        InputEvent::JoystickAxisMotion(index, axis, value) => {
+            format!("JoystickAxisMotion {} {} {}", index, axis, value)
+        },
+        InputEvent::JoystickHatMotion(index, hat, bits) => format!("JoystickHatMotion {} {} {}", index, hat, bits),
+
+        InputEvent::FingerDown(id, x, y) => format!("FingerDown {} {} {}", id, x, y),
+        InputEvent::FingerMotion(id, x, y) => format!("FingerMotion {} {} {}", id, x, y),
+        InputEvent::FingerUp(id, x, y) => format!("FingerUp {} {} {}", id, x, y),
+    }
+}
+
+fn parse_recorded_event(line: &str, version: u32) -> Result<RecordedInputEvent, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let (time, name, values) = match tokens.as_slice() {
+        [time, name, values @ ..] => (*time, *name, values),
+        _ => return Err(format!("invalid event '{}'", line)),
+    };
+
+    let expect = |count: usize| {
+        if values.len() == count {
+            Ok(())
+        } else {
+            Err(format!("'{}' expects {} values, found {}", name, count, values.len()))
+        }
+    };
+
+    let event = match name {
+        "KeyDown" => { expect(1)?; InputEvent::KeyDown(parse_scancode(values[0])?) },
+        "KeyUp" => { expect(1)?; InputEvent::KeyUp(parse_scancode(values[0])?) },
+
+        "MouseButtonDown" => { expect(1)?; InputEvent::MouseButtonDown(parse_mouse_button(values[0])?) },
+        "MouseButtonUp" => { expect(1)?; InputEvent::MouseButtonUp(parse_mouse_button(values[0])?) },
+        // Version 1 had no relative motion
+        "MouseMotion" if version < 2 => {
+            expect(2)?;
+            InputEvent::MouseMotion(parse_number(values[0])?, parse_number(values[1])?, 0, 0)
+        },
+        "MouseMotion" => {
+            expect(4)?;
+            InputEvent::MouseMotion(
+                parse_number(values[0])?,
+                parse_number(values[1])?,
+                parse_number(values[2])?,
+                parse_number(values[3])?
+            )
+        },
+        "MouseWheel" => { expect(2)?; InputEvent::MouseWheel(parse_number(values[0])?, parse_number(values[1])?) },
+
+        "ControllerConnected" => { expect(1)?; InputEvent::ControllerConnected(parse_index(values[0])?) },
+        "ControllerDisconnected" => { expect(1)?; InputEvent::ControllerDisconnected(parse_index(values[0])?) },
+        "ControllerButtonDown" => {
+            expect(2)?;
+            InputEvent::ControllerButtonDown(parse_index(values[0])?, parse_controller_button(values[1])?)
+        },
+        "ControllerButtonUp" => {
+            expect(2)?;
+            InputEvent::ControllerButtonUp(parse_index(values[0])?, parse_controller_button(values[1])?)
+        },
+        "ControllerAxisMotion" => {
+            expect(3)?;
+            InputEvent::ControllerAxisMotion(
+                parse_index(values[0])?,
+                parse_controller_axis(values[1])?,
+                parse_number(values[2])?
+            )
+        },
+
+        "JoystickConnected" => { expect(1)?; InputEvent::JoystickConnected(parse_index(values[0])?) },
+        "JoystickDisconnected" => { expect(1)?; InputEvent::JoystickDisconnected(parse_index(values[0])?) },
+        "JoystickButtonDown" => {
+            expect(2)?;
+            InputEvent::JoystickButtonDown(parse_index(values[0])?, parse_number(values[1])?)
+        },
+        "JoystickButtonUp" => {
+            expect(2)?;
+            InputEvent::JoystickButtonUp(parse_index(values[0])?, parse_number(values[1])?)
+        },
+        "JoystickAxisMotion" => {
+            expect(3)?;
+            InputEvent::JoystickAxisMotion(parse_index(values[0])?, parse_number(values[1])?, parse_number(values[2])?)
+        },
+        "JoystickHatMotion" => {
+            expect(3)?;
+            InputEvent::JoystickHatMotion(parse_index(values[0])?, parse_number(values[1])?, parse_number(values[2])?)
+        },
+
+        "FingerDown" => {
+            expect(3)?;
+            InputEvent::FingerDown(parse_number(values[0])?, parse_number(values[1])?, parse_number(values[2])?)
+        },
+        "FingerMotion" => {
+            expect(3)?;
+            InputEvent::FingerMotion(parse_number(values[0])?, parse_number(values[1])?, parse_number(values[2])?)
+        },
+        "FingerUp" => {
+            expect(3)?;
+            InputEvent::FingerUp(parse_number(values[0])?, parse_number(values[1])?, parse_number(values[2])?)
+        },
+
+        _ => return Err(format!("unknown event '{}'", name)),
+    };
+
+    Ok(RecordedInputEvent {
+        time: Duration::from_micros(parse_number(time)?),
+        event,
+    })
+}
+
+// Device index, checked since the device containers have a fixed size
+fn parse_index(value: &str) -> Result<usize, String> {
+    let index = parse_number(value)?;
+    if index >= InputDevices::MAX_DEVICE_INDEX {
+        return Err(format!("invalid device index {}", index));
+    }
+
+    Ok(index)
+}
+
+pub(super) struct InputRecorder {
+    start: GameTime,
+    events: Vec<RecordedInputEvent>,
+}
+
+impl InputRecorder {
+    // The recording starts with the current state of the devices
+    pub(super) fn new(devices: &InputDevices, start: GameTime) -> Self {
+        let events = devices.snapshot_events().into_iter()
+            .map(|event| RecordedInputEvent { time: Duration::ZERO, event })
+            .collect();
+
+        Self { start, events }
+    }
+
+    pub(super) fn record(&mut self, event: InputEvent, timestamp: GameTime) {
+        self.events.push(RecordedInputEvent {
+            time: timestamp.duration_since(self.start),
+            event,
+        });
+    }
+
+    fn finish(self, now: GameTime) -> InputRecording {
+        InputRecording {
+            events: self.events,
+            duration: now.duration_since(self.start),
+        }
+    }
+}
+
+pub(super) struct InputPlayback {
+    // Devices updated by the events handled during playback, restored when it ends
+    pub(super) live_devices: InputDevices,
+    start: GameTime,
+    recording: InputRecording,
+    next: usize,
+}
+
+impl InputSystem {
+    // Applies the events due at the current time
+    pub(in crate::app) fn update_playback(&mut self, now: GameTime) {
+        loop {
+            let playback = match self.playback.as_mut() {
+                Some(playback) => playback,
+                None => return,
+            };
+
+            let recorded = match playback.recording.events.get(playback.next) {
+                Some(recorded) if playback.start + recorded.time <= now => *recorded,
+                Some(_) => return,
+                None => break,
+            };
+
+            playback.next += 1;
+            let timestamp = playback.start + recorded.time;
+            self.apply_input_event(recorded.event, timestamp);
+        }
+
+        let playback = self.playback.as_ref().unwrap();
+        if playback.start + playback.recording.duration <= now {
+            self.stop_playback();
+        }
+    }
+
+    fn stop_playback(&mut self) {
+        if let Some(playback) = self.playback.take() {
+            self.devices = playback.live_devices;
+        }
+    }
+}
+
+impl<S> App<'_, S> {
+    pub fn start_input_recording(&mut self) {
+        if self.input_system.recorder.is_some() {
+            panic!("[input_system start_input_recording] already recording");
+        }
+
+        let now = self.time_system.game_time();
+        self.input_system.recorder = Some(InputRecorder::new(&self.input_system.devices, now));
+    }
+
+    pub fn stop_input_recording(&mut self) -> InputRecording {
+        let now = self.time_system.game_time();
+        self.input_system.recorder.take()
+            .expect("[input_system stop_input_recording] not recording")
+            .finish(now)
+    }
+
+    pub fn is_recording_input(&self) -> bool {
+        self.input_system.recorder.is_some()
+    }
+
+    // Replaces the current playback, if any. The live input is ignored until the playback ends
+    pub fn play_input_recording(&mut self, recording: InputRecording) {
+        if self.input_system.recorder.is_some() {
+            panic!("[input_system play_input_recording] can't play a recording while recording");
+        }
+
+        self.input_system.stop_playback();
+
+        let live_devices = std::mem::take(&mut self.input_system.devices);
+        self.input_system.devices.touch.set_canvas_size(live_devices.touch.canvas_size());
+        self.input_system.playback = Some(InputPlayback {
+            live_devices,
+            start: self.time_system.game_time(),
+            recording,
+            next: 0,
+        });
+    }
+
+    pub fn stop_input_playback(&mut self) {
+        self.input_system.stop_playback();
+    }
+
+    pub fn is_playing_input_recording(&self) -> bool {
+        self.input_system.playback.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::sdl2::keyboard::Scancode;
+    use super::super::system::JoystickHatDirection;
+
+    fn replay(recording: &InputRecording) -> InputDevices {
+        let mut devices = InputDevices::default();
+        for recorded in recording.events() {
+            devices.apply(recorded.event, GameTime::from_micros(0) + recorded.time);
+        }
+
+        devices
+    }
+
+    #[test]
+    fn test_input_recording_replay() {
+        let mut live = InputDevices::default();
+        live.apply(InputEvent::KeyDown(Scancode::A), GameTime::from_micros(0));
+        live.apply(InputEvent::ControllerConnected(1), GameTime::from_micros(0));
+
+        let start = GameTime::from_micros(1_000);
+        let mut recorder = InputRecorder::new(&live, start);
+
+        let events = [
+            (2_000, InputEvent::KeyUp(Scancode::A)),
+            (3_000, InputEvent::KeyDown(Scancode::Space)),
+            (3_000, InputEvent::ControllerAxisMotion(1, sdl2::controller::Axis::LeftX, 0.75)),
+            (4_000, InputEvent::JoystickConnected(0)),
+            (5_000, InputEvent::JoystickHatMotion(0, 0, 0b0011)),
+            (5_000, InputEvent::FingerDown(7, 0.25, 0.5)),
+        ];
+
+        for &(time, event) in events.iter() {
+            let timestamp = GameTime::from_micros(time);
+            live.apply(event, timestamp);
+            recorder.record(event, timestamp);
+        }
+
+        let recording = recorder.finish(GameTime::from_micros(6_000));
+        assert_eq!(recording.duration(), Duration::from_micros(5_000));
+
+        let text = recording.to_recording_string();
+        let loaded = InputRecording::from_recording_str(&text).unwrap();
+        assert_eq!(loaded, recording);
+
+        let replayed = replay(&loaded);
+        assert!(!replayed.keyboard.button_state(Scancode::A).down);
+        assert!(replayed.keyboard.button_state(Scancode::Space).down);
+        assert_eq!(
+            replayed.keyboard.button_state(Scancode::Space).timestamp,
+            GameTime::from_micros(2_000)
+        );
+
+        let controller = replayed.controllers.controller_state(1).unwrap();
+        assert_eq!(controller.axis_state(sdl2::controller::Axis::LeftX).value, 0.75);
+
+        let joystick = replayed.joysticks.joystick_state(0).unwrap();
+        assert!(joystick.hat_state(0, JoystickHatDirection::Up).down);
+        assert!(joystick.hat_state(0, JoystickHatDirection::Right).down);
+        assert!(!joystick.hat_state(0, JoystickHatDirection::Down).down);
+
+        assert_eq!(replayed.touch.points()[0].id, 7);
+        assert_eq!(replayed.touch.points()[0].pos.x, 320.0);
+    }
+
+    #[test]
+    fn test_input_recording_older_versions() {
+        let recording = InputRecording::from_recording_str("input_recording 1\nduration 10\n0 MouseMotion 640 360\n")
+            .unwrap();
+        assert_eq!(recording.events[0].event, InputEvent::MouseMotion(640, 360, 0, 0));
+
+        // relative motion only from version 2
+        assert!(InputRecording::from_recording_str("input_recording 1\nduration 10\n0 MouseMotion 1 2 3 4\n").is_err());
+        assert!(InputRecording::from_recording_str("input_recording 2\nduration 10\n0 MouseMotion 1 2 3 4\n").is_ok());
+    }
+
+    #[test]
+    fn test_input_recording_errors() {
+        assert!(InputRecording::from_recording_str("").is_err());
+        let err = InputRecording::from_recording_str("input_recording 4\nduration 0\n").unwrap_err();
+        assert_eq!(err, "line 1: unsupported version 4");
+
+        let err = InputRecording::from_recording_str("input_recording 3\nduration 10\n0 KeyDown Foo\n")
+            .unwrap_err();
+        assert!(err.starts_with("line 3:"), "{}", err);
+
+        let err = InputRecording::from_recording_str("input_recording 3\nduration 10\n5 KeyDown A\n2 KeyUp A\n")
+            .unwrap_err();
+        assert_eq!(err, "line 4: event out of order");
+
+        let err = InputRecording::from_recording_str("input_recording 3\nduration 10\n0 ControllerConnected 99\n")
+            .unwrap_err();
+        assert_eq!(err, "line 3: invalid device index 99");
+    }
+}
@@ -4,7 +4,10 @@
 
 use std::collections::BTreeMap;
 
-use crate::app::App;
+use crate::app::{
+    App,
+    time_system::GameTime,
+};
 use crate::app::sdl2::{
     event::Event,
     keyboard::Scancode,
@@ -13,133 +16,241 @@ use crate::app::sdl2::{
 use super::{
     ControllerAxisDirection,
     ControllerAxisThreshold,
-    mapping::InputMapping,
+    context::InputContextStack,
+    haptics::HapticPlayback,
+    event::InputEvent,
+    listen::ListenState,
+    recording::{InputPlayback, InputRecorder},
+    text::TextInputState,
+    touch::TouchState,
+    mapping::{ControllerBind, InputDevice, InputMapping},
 };
 
 const MAX_KEYBOARD_KEYS      : usize = 512;
 const MAX_MOUSE_BUTTONS      : usize = 16;
 const MAX_CONTROLLER_BUTTONS : usize = 16;
 const MAX_CONTROLLER_AXIS    : usize = 16;
-const MAX_CONTROLLERS        : usize = 16;
+pub(super) const MAX_CONTROLLERS        : usize = 16;
+pub(super) const MAX_JOYSTICK_BUTTONS   : usize = 32;
+pub(super) const MAX_JOYSTICK_AXES      : usize = 16;
+pub(super) const MAX_JOYSTICK_HATS      : usize = 4;
+pub(super) const MAX_JOYSTICKS          : usize = 16;
+
+// Ordered by value
+pub(super) const CONTROLLER_BUTTONS: [sdl2::controller::Button; 15] = [
+    sdl2::controller::Button::A,
+    sdl2::controller::Button::B,
+    sdl2::controller::Button::X,
+    sdl2::controller::Button::Y,
+    sdl2::controller::Button::Back,
+    sdl2::controller::Button::Guide,
+    sdl2::controller::Button::Start,
+    sdl2::controller::Button::LeftStick,
+    sdl2::controller::Button::RightStick,
+    sdl2::controller::Button::LeftShoulder,
+    sdl2::controller::Button::RightShoulder,
+    sdl2::controller::Button::DPadUp,
+    sdl2::controller::Button::DPadDown,
+    sdl2::controller::Button::DPadLeft,
+    sdl2::controller::Button::DPadRight,
+];
+
+pub(super) const CONTROLLER_AXES: [sdl2::controller::Axis; 6] = [
+    sdl2::controller::Axis::LeftX,
+    sdl2::controller::Axis::LeftY,
+    sdl2::controller::Axis::RightX,
+    sdl2::controller::Axis::RightY,
+    sdl2::controller::Axis::TriggerLeft,
+    sdl2::controller::Axis::TriggerRight,
+];
+
+// Axis motion under this value doesn't count as device activity (stick drift, resting triggers)
+const DEVICE_ACTIVITY_AXIS_THRESHOLD: f32 = 0.5;
+
+// Subsystems to open the SDL devices
+struct SdlInputSubsystems {
+    controller_subsystem: sdl2::GameControllerSubsystem,
+    joystick_subsystem: sdl2::JoystickSubsystem,
+}
 
 pub(in crate::app) struct InputSystem {
-    controller_subsystem: sdl2::GameControllerSubsystem,
-    pub(super) keyboard: KeyboardState,
-    pub(super) mouse: MouseState,
-    pub(super) controllers: ControllerStateContainer,
+    // Headless input systems (tests) have no SDL devices, only virtual ones driven by input events
+    sdl: Option<SdlInputSubsystems>,
+
+    // State read by the mappings. During playback, it's the replayed state
+    pub(super) devices: InputDevices,
+
+    // Last time each device was used
+    device_activity: BTreeMap<InputDevice, GameTime>,
+
+    pub(super) listen: Option<ListenState>,
+    pub(super) contexts: InputContextStack,
+    pub(super) recorder: Option<InputRecorder>,
+    pub(super) playback: Option<InputPlayback>,
+    pub(super) text_input: TextInputState,
+    // By controller index
+    pub(super) haptics: BTreeMap<usize, HapticPlayback>,
 }
 
 impl InputSystem {
-    pub(in crate::app) fn new(controller_subsystem: sdl2::GameControllerSubsystem) -> Self {
+    pub(in crate::app) fn new(
+        controller_subsystem: sdl2::GameControllerSubsystem,
+        joystick_subsystem: sdl2::JoystickSubsystem,
+    ) -> Self {
         Self {
-            controller_subsystem,
-            keyboard: Default::default(),
-            mouse: Default::default(),
-            controllers: Default::default(),
+            sdl: Some(SdlInputSubsystems { controller_subsystem, joystick_subsystem }),
+            ..Self::headless()
         }
     }
 
-    pub(in crate::app) fn handle_input(&mut self, event: &Event, timestamp: u64) {
-        // @XXX window_id may be useful when we add multiple window support
-
-        match event {
-            Event::KeyDown { scancode: Some(key), repeat, .. } => {
-                if !repeat {
-                    self.keyboard.press(*key, timestamp);
-                }
-            },
+    pub(super) fn headless() -> Self {
+        Self {
+            sdl: None,
+            devices: InputDevices::default(),
+            device_activity: BTreeMap::new(),
+            listen: None,
+            contexts: InputContextStack::default(),
+            recorder: None,
+            playback: None,
+            text_input: TextInputState::default(),
+            haptics: BTreeMap::new(),
+        }
+    }
 
-            Event::KeyUp { scancode: Some(key), repeat, .. } => {
-                if !repeat {
-                    self.keyboard.release(*key, timestamp);
-                }
+    fn register_activity(&mut self, event: InputEvent, timestamp: GameTime) {
+        let device = match event {
+            InputEvent::KeyDown(_) => InputDevice::Keyboard,
+            InputEvent::MouseButtonDown(_) => InputDevice::Mouse,
+            InputEvent::ControllerButtonDown(index, _) => InputDevice::Controller(index),
+            InputEvent::ControllerAxisMotion(index, _, value) if value.abs() > DEVICE_ACTIVITY_AXIS_THRESHOLD => {
+                InputDevice::Controller(index)
             },
-
-            // Mouse
-            // @XXX which
-            // @XXX clicks
-            Event::MouseButtonDown { mouse_btn: button, .. } => {
-                self.mouse.press(*button, timestamp);
+            InputEvent::JoystickButtonDown(index, _) => InputDevice::Joystick(index),
+            InputEvent::JoystickAxisMotion(index, _, value) if value.abs() > DEVICE_ACTIVITY_AXIS_THRESHOLD => {
+                InputDevice::Joystick(index)
             },
+            InputEvent::JoystickHatMotion(index, _, bits) if bits != 0 => InputDevice::Joystick(index),
+            InputEvent::FingerDown(..) => InputDevice::Touch,
+            _ => return,
+        };
 
-            Event::MouseButtonUp { mouse_btn: button, .. } => {
-                self.mouse.release(*button, timestamp);
-            },
+        self.device_activity.insert(device, timestamp);
+    }
 
-            Event::MouseMotion { x, y, .. } => {
-                self.mouse.set_pos(*x, *y);
-            },
+    // Connected controllers included in the bind
+    pub(super) fn bound_controllers(
+        &self,
+        controller_bind: ControllerBind
+    ) -> impl Iterator<Item = &ControllerState> {
+        (0..MAX_CONTROLLERS)
+            .filter(move |&index| controller_bind.includes(index))
+            .filter_map(move |index| self.devices.controllers.controller_state(index))
+    }
 
-            // Joystick
-            /*
-            Event::JoyDeviceAdded { which, .. } => {
-                println!("joy connected: {}", which);
-            }
+    // Connected joysticks included in the bind
+    pub(super) fn bound_joysticks(&self, joystick_bind: ControllerBind) -> impl Iterator<Item = &JoystickState> {
+        (0..MAX_JOYSTICKS)
+            .filter(move |&index| joystick_bind.includes(index))
+            .filter_map(move |index| self.devices.joysticks.joystick_state(index))
+    }
 
-            Event::JoyDeviceRemoved { which, .. } => {
-                println!("joy disconnected: {}", which);
-            }
-            */
+    pub(in crate::app) fn handle_input(&mut self, event: &Event, timestamp: GameTime) {
+        if self.handle_text_event(event) {
+            return;
+        }
 
-            // Controller
-            // Connection
-            Event::ControllerDeviceAdded { which, .. } => {
-                let index = *which;
+        let sdl = match self.sdl.as_ref() {
+            Some(sdl) => sdl,
+            None => return,
+        };
+
+        // During playback the live devices are still tracked, but they don't affect the mappings
+        let live_devices = match self.playback.as_mut() {
+            Some(playback) => &mut playback.live_devices,
+            None => &mut self.devices,
+        };
+
+        let input_event = match live_devices.input_event(
+            event,
+            &sdl.controller_subsystem,
+            &sdl.joystick_subsystem
+        ) {
+            Some(input_event) => input_event,
+            None => return,
+        };
+
+        self.handle_input_event(input_event, timestamp);
+    }
 
-                match self.controller_subsystem.open(index) {
-                    Ok(c) => self.controllers.connect(index, c),
-                    Err(_) => println!("connect failed ({})", which)
-                };
-            },
+    // Resets the values accumulated during the frame (mouse motion and wheel). Called before handling
+    // the frame events
+    pub(in crate::app) fn new_frame(&mut self) {
+        self.devices.new_frame();
+        if let Some(playback) = self.playback.as_mut() {
+            playback.live_devices.new_frame();
+        }
+    }
 
-            Event::ControllerDeviceRemoved { which, .. } => {
-                let id = *which;
-                self.controllers.disconnect(id);
-            },
+    // Keyboard and mouse events are lost while the window is not focused. Called with nothing held
+    // when the focus is lost (releases everything) and with the SDL state when it's gained
+    pub(in crate::app) fn resync_keyboard_and_mouse(
+        &mut self,
+        keys: &[Scancode],
+        mouse_buttons: &[sdl2::mouse::MouseButton],
+        timestamp: GameTime
+    ) {
+        let live_devices = match self.playback.as_ref() {
+            Some(playback) => &playback.live_devices,
+            None => &self.devices,
+        };
 
-            // Buttons
-            Event::ControllerButtonDown { which, button, .. } => {
-                let id = *which;
-                match self.controllers.controller_state_from_id(id) {
-                    Some(c) => c.press_button(*button, timestamp),
-                    None => {}
-                }
-            },
+        for event in live_devices.resync_events(keys, mouse_buttons) {
+            self.handle_input_event(event, timestamp);
+        }
+    }
 
-            Event::ControllerButtonUp { which, button, .. } => {
-                let id = *which;
-                match self.controllers.controller_state_from_id(id) {
-                    Some(c) => c.release_button(*button, timestamp),
-                    None => {}
-                }
-            },
+    // During playback the live devices are still tracked, but they don't affect the mappings
+    fn handle_input_event(&mut self, event: InputEvent, timestamp: GameTime) {
+        match self.playback.as_mut() {
+            Some(playback) => playback.live_devices.apply(event, timestamp),
+            None => self.apply_input_event(event, timestamp),
+        }
+    }
 
-            // Axis
-            Event::ControllerAxisMotion { which, axis, value, .. } => {
-                let id = *which;
-                let axis = *axis;
-                let value = if *value > 0 {
-                    (*value) as f32 / (i16::MAX as f32)
-                } else {
-                    - ((*value) as f32 / (i16::MIN as f32))
-                };
-
-                match self.controllers.controller_state_from_id(id) {
-                    Some(c) => c.update_axis(axis, value, timestamp),
-                    None => {}
-                }
-            },
+    pub(super) fn apply_input_event(&mut self, event: InputEvent, timestamp: GameTime) {
+        // Before applying the event, to compare with the previous axis values
+        if self.listen.is_some() {
+            self.update_listen(event);
+        }
 
-            // Touchpad (not supported in rust-sdl2)
+        self.devices.apply(event, timestamp);
+        self.register_activity(event, timestamp);
 
-            _ => {}
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(event, timestamp);
         }
     }
 
-    fn update_input_mapping(&mut self, mapping: &mut InputMapping, timestamp: u64) {
+    pub(super) fn update_input_mapping(&mut self, mapping: &mut InputMapping, timestamp: GameTime) {
+        let controller_bind = mapping.controller_bind;
+        let joystick_bind = mapping.joystick_bind;
+        let block = self.input_block(mapping);
+
         for button in mapping.button_mapping.values_mut() {
-            button.update(self, timestamp);
+            button.update(self, controller_bind, joystick_bind, &block, timestamp);
         }
+
+        for axis in mapping.axis_mapping.values_mut() {
+            axis.update(self, controller_bind, joystick_bind, &block);
+        }
+
+        for axis in mapping.axis_2d_mapping.values_mut() {
+            axis.update(self, controller_bind, joystick_bind, &block);
+        }
+
+        mapping.buffer.update(&mapping.button_mapping, timestamp);
+        mapping.update_last_device(&self.device_activity);
+        self.contexts.refresh_inputs(mapping);
     }
 
     fn set_controller_rumble(
@@ -149,16 +260,27 @@ impl InputSystem {
         high_frequency: u16,
         duration: u32
     ) {
-        match self.controllers.controller_state_mut(controller_index) {
+        // Replaces the haptic pattern playing
+        self.haptics.remove(&controller_index);
+
+        match self.live_devices_mut().controllers.controller_state_mut(controller_index) {
             Some(c) => c.set_rumble(low_frequency, high_frequency, duration),
             None => {}
         }
     }
 }
 
+pub(super) fn normalize_axis_value(value: i16) -> f32 {
+    if value > 0 {
+        value as f32 / (i16::MAX as f32)
+    } else {
+        - (value as f32 / (i16::MIN as f32))
+    }
+}
+
 impl<S> App<'_, S> {
     pub fn update_input_mapping(&mut self, mapping: &mut InputMapping) {
-        let timestamp = self.time_system.game_time;
+        let timestamp = self.time_system.game_time();
         self.input_system.update_input_mapping(mapping, timestamp);
     }
 
@@ -176,6 +298,348 @@ impl<S> App<'_, S> {
             duration
         );
     }
+
+    // GUID of the joystick connected (or last connected) to the index
+    pub fn joystick_guid(&self, joystick_index: usize) -> Option<&str> {
+        self.input_system.devices.joysticks.joystick_states.get(joystick_index)?.guid.as_deref()
+    }
+
+    pub fn joystick_name(&self, joystick_index: usize) -> Option<String> {
+        self.input_system.devices.joysticks.joystick_state(joystick_index)
+            .and_then(|j| j.joystick.as_ref())
+            .map(|j| j.name())
+    }
+
+    // Mouse
+
+    pub fn mouse_position(&self) -> (i32, i32) {
+        self.input_system.devices.mouse.get_pos()
+    }
+
+    // Relative motion during the last frame
+    pub fn mouse_motion(&self) -> (i32, i32) {
+        self.input_system.devices.mouse.get_motion()
+    }
+
+    // Scroll during the last frame, positive to the right and away from the user
+    pub fn mouse_wheel(&self) -> (i32, i32) {
+        self.input_system.devices.mouse.get_wheel()
+    }
+
+    // Hides and captures the cursor. Only the relative motion changes
+    pub fn set_relative_mouse_mode(&mut self, enabled: bool) {
+        self.sdl_context.sdl.mouse().set_relative_mouse_mode(enabled);
+    }
+
+    pub fn is_relative_mouse_mode(&self) -> bool {
+        self.sdl_context.sdl.mouse().relative_mouse_mode()
+    }
+}
+
+// -------
+// Devices
+// -------
+
+#[derive(Default)]
+pub(super) struct InputDevices {
+    pub(super) keyboard: KeyboardState,
+    pub(super) mouse: MouseState,
+    pub(super) controllers: ControllerStateContainer,
+    pub(super) joysticks: JoystickStateContainer,
+    pub(super) touch: TouchState,
+}
+
+impl InputDevices {
+    // Controller and joystick indices are lower than this
+    pub(super) const MAX_DEVICE_INDEX: usize =
+        if MAX_CONTROLLERS < MAX_JOYSTICKS { MAX_CONTROLLERS } else { MAX_JOYSTICKS };
+
+    // Resolves the SDL event to device indices. Connections are handled here since they need the
+    // SDL device
+    fn input_event(
+        &mut self,
+        event: &Event,
+        controller_subsystem: &sdl2::GameControllerSubsystem,
+        joystick_subsystem: &sdl2::JoystickSubsystem,
+    ) -> Option<InputEvent> {
+        // @XXX window_id may be useful when we add multiple window support
+
+        match event {
+            Event::KeyDown { scancode: Some(key), repeat: false, .. } => Some(InputEvent::KeyDown(*key)),
+            Event::KeyUp { scancode: Some(key), repeat: false, .. } => Some(InputEvent::KeyUp(*key)),
+
+            // Mouse
+            // @XXX which
+            // @XXX clicks
+            Event::MouseButtonDown { mouse_btn: button, .. } => Some(InputEvent::MouseButtonDown(*button)),
+            Event::MouseButtonUp { mouse_btn: button, .. } => Some(InputEvent::MouseButtonUp(*button)),
+            Event::MouseMotion { x, y, xrel, yrel, .. } => Some(InputEvent::MouseMotion(*x, *y, *xrel, *yrel)),
+            Event::MouseWheel { x, y, direction, .. } => {
+                let sign = if *direction == sdl2::mouse::MouseWheelDirection::Flipped { -1 } else { 1 };
+                Some(InputEvent::MouseWheel(x * sign, y * sign))
+            },
+
+            // Joystick
+            // Only devices not mapped as game controllers by SDL are handled as joysticks. Joystick
+            // events are also sent for game controllers, but those are not mapped as joysticks
+            Event::JoyDeviceAdded { which, .. } => {
+                let index = *which;
+
+                if controller_subsystem.is_game_controller(index) {
+                    return None;
+                }
+
+                match joystick_subsystem.open(index) {
+                    Ok(j) => self.joysticks.connect(j).map(InputEvent::JoystickConnected),
+                    Err(_) => {
+                        println!("joystick connect failed ({})", which);
+                        None
+                    }
+                }
+            },
+
+            Event::JoyDeviceRemoved { which, .. } => {
+                self.joysticks.index_from_id(*which).map(InputEvent::JoystickDisconnected)
+            },
+
+            Event::JoyButtonDown { which, button_idx, .. } => {
+                self.joysticks.index_from_id(*which).map(|index| InputEvent::JoystickButtonDown(index, *button_idx))
+            },
+
+            Event::JoyButtonUp { which, button_idx, .. } => {
+                self.joysticks.index_from_id(*which).map(|index| InputEvent::JoystickButtonUp(index, *button_idx))
+            },
+
+            Event::JoyAxisMotion { which, axis_idx, value, .. } => {
+                let value = normalize_axis_value(*value);
+                self.joysticks.index_from_id(*which)
+                    .map(|index| InputEvent::JoystickAxisMotion(index, *axis_idx, value))
+            },
+
+            Event::JoyHatMotion { which, hat_idx, state, .. } => {
+                let bits = *state as u8;
+                self.joysticks.index_from_id(*which).map(|index| InputEvent::JoystickHatMotion(index, *hat_idx, bits))
+            },
+
+            // Controller
+            // Connection
+            Event::ControllerDeviceAdded { which, .. } => {
+                let device_index = *which;
+
+                match controller_subsystem.open(device_index) {
+                    // Repeated added event for a controller already open
+                    Ok(c) if self.controllers.index_from_id(c.instance_id()).is_some() => None,
+                    Ok(c) => match self.controllers.free_slot(device_index as usize) {
+                        Some(index) => {
+                            self.controllers.connect(index, Some(c));
+                            Some(InputEvent::ControllerConnected(index))
+                        },
+                        None => {
+                            // @TODO log
+                            println!("[input_system controller_state_container] no free controller slot ({})", which);
+                            None
+                        }
+                    },
+                    Err(_) => {
+                        println!("connect failed ({})", which);
+                        None
+                    }
+                }
+            },
+
+            Event::ControllerDeviceRemoved { which, .. } => {
+                let index = self.controllers.index_from_id(*which);
+                if index.is_none() {
+                    // @TODO log
+                    println!(
+                        "[input_system controller_state_container] tried to disconnect unmapped controller id {}",
+                        which
+                    );
+                }
+
+                index.map(InputEvent::ControllerDisconnected)
+            },
+
+            // Buttons
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.controllers.index_from_id(*which).map(|index| InputEvent::ControllerButtonDown(index, *button))
+            },
+
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.controllers.index_from_id(*which).map(|index| InputEvent::ControllerButtonUp(index, *button))
+            },
+
+            // Axis
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                let value = normalize_axis_value(*value);
+                self.controllers.index_from_id(*which)
+                    .map(|index| InputEvent::ControllerAxisMotion(index, *axis, value))
+            },
+
+            // Touch
+            Event::FingerDown { finger_id, x, y, .. } => Some(InputEvent::FingerDown(*finger_id, *x, *y)),
+            Event::FingerMotion { finger_id, x, y, .. } => Some(InputEvent::FingerMotion(*finger_id, *x, *y)),
+            Event::FingerUp { finger_id, x, y, .. } => Some(InputEvent::FingerUp(*finger_id, *x, *y)),
+
+            _ => None,
+        }
+    }
+
+    fn new_frame(&mut self) {
+        self.mouse.new_frame();
+        self.touch.new_frame();
+    }
+
+    pub(super) fn apply(&mut self, event: InputEvent, timestamp: GameTime) {
+        match event {
+            InputEvent::KeyDown(key) => self.keyboard.press(key, timestamp),
+            InputEvent::KeyUp(key) => self.keyboard.release(key, timestamp),
+
+            InputEvent::MouseButtonDown(button) => self.mouse.press(button, timestamp),
+            InputEvent::MouseButtonUp(button) => self.mouse.release(button, timestamp),
+            InputEvent::MouseMotion(x, y, x_rel, y_rel) => self.mouse.motion(x, y, x_rel, y_rel),
+            InputEvent::MouseWheel(x, y) => self.mouse.wheel(x, y),
+
+            // Live devices are connected when the SDL event is resolved. Replayed ones are connected
+            // without SDL device
+            InputEvent::ControllerConnected(index) => {
+                if self.controllers.is_free(index) {
+                    self.controllers.connect(index, None);
+                }
+            },
+            InputEvent::ControllerDisconnected(index) => self.controllers.disconnect(index),
+            InputEvent::ControllerButtonDown(index, button) => {
+                if let Some(c) = self.controllers.controller_state_mut(index) {
+                    c.press_button(button, timestamp);
+                }
+            },
+            InputEvent::ControllerButtonUp(index, button) => {
+                if let Some(c) = self.controllers.controller_state_mut(index) {
+                    c.release_button(button, timestamp);
+                }
+            },
+            InputEvent::ControllerAxisMotion(index, axis, value) => {
+                if let Some(c) = self.controllers.controller_state_mut(index) {
+                    c.update_axis(axis, value, timestamp);
+                }
+            },
+
+            InputEvent::JoystickConnected(index) => {
+                if self.joysticks.is_free(index) {
+                    self.joysticks.connect_virtual(index);
+                }
+            },
+            InputEvent::JoystickDisconnected(index) => self.joysticks.disconnect(index),
+            InputEvent::JoystickButtonDown(index, button) => {
+                if let Some(j) = self.joysticks.joystick_state_mut(index) {
+                    j.press_button(button, timestamp);
+                }
+            },
+            InputEvent::JoystickButtonUp(index, button) => {
+                if let Some(j) = self.joysticks.joystick_state_mut(index) {
+                    j.release_button(button, timestamp);
+                }
+            },
+            InputEvent::JoystickAxisMotion(index, axis, value) => {
+                if let Some(j) = self.joysticks.joystick_state_mut(index) {
+                    j.update_axis(axis, value, timestamp);
+                }
+            },
+            InputEvent::JoystickHatMotion(index, hat, bits) => {
+                if let Some(j) = self.joysticks.joystick_state_mut(index) {
+                    j.update_hat(hat, bits, timestamp);
+                }
+            },
+
+            InputEvent::FingerDown(id, x, y) => self.touch.finger_down(id, x, y, timestamp),
+            InputEvent::FingerMotion(id, x, y) => self.touch.finger_motion(id, x, y),
+            InputEvent::FingerUp(id, x, y) => self.touch.finger_up(id, x, y, timestamp),
+        }
+    }
+
+    // Events that bring the keyboard and mouse to the given state
+    fn resync_events(&self, keys: &[Scancode], mouse_buttons: &[sdl2::mouse::MouseButton]) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        // Only valid scancodes can be down (from_i32 panics on some invalid values)
+        for (index, _) in self.keyboard.keys.iter().enumerate().filter(|(_, key)| key.down) {
+            match Scancode::from_i32(index as i32) {
+                Some(code) if !keys.contains(&code) => events.push(InputEvent::KeyUp(code)),
+                _ => {},
+            }
+        }
+
+        for &key in keys.iter() {
+            if !self.keyboard.button_state(key).down {
+                events.push(InputEvent::KeyDown(key));
+            }
+        }
+
+        for (index, button) in self.mouse.buttons.iter().enumerate() {
+            let mouse_button = sdl2::mouse::MouseButton::from_ll(index as u8);
+            if button.down && !mouse_buttons.contains(&mouse_button) {
+                events.push(InputEvent::MouseButtonUp(mouse_button));
+            }
+        }
+
+        for &button in mouse_buttons.iter() {
+            if !self.mouse.button_state(button).down {
+                events.push(InputEvent::MouseButtonDown(button));
+            }
+        }
+
+        events
+    }
+
+    // Events that bring default devices to the current state
+    pub(super) fn snapshot_events(&self) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        for (index, key) in self.keyboard.keys.iter().enumerate() {
+            if key.down {
+                if let Some(code) = Scancode::from_i32(index as i32) {
+                    events.push(InputEvent::KeyDown(code));
+                }
+            }
+        }
+
+        for (index, button) in self.mouse.buttons.iter().enumerate() {
+            if button.down {
+                events.push(InputEvent::MouseButtonDown(sdl2::mouse::MouseButton::from_ll(index as u8)));
+            }
+        }
+        events.push(InputEvent::MouseMotion(self.mouse.pos.0, self.mouse.pos.1, 0, 0));
+
+        for index in 0..MAX_CONTROLLERS {
+            if let Some(controller_state) = self.controllers.controller_state(index) {
+                events.push(InputEvent::ControllerConnected(index));
+
+                for button in CONTROLLER_BUTTONS.iter() {
+                    if controller_state.button_state(*button).down {
+                        events.push(InputEvent::ControllerButtonDown(index, *button));
+                    }
+                }
+
+                for axis in CONTROLLER_AXES.iter() {
+                    let value = controller_state.axis_state(*axis).value;
+                    if value != 0.0 {
+                        events.push(InputEvent::ControllerAxisMotion(index, *axis, value));
+                    }
+                }
+            }
+        }
+
+        for index in 0..MAX_JOYSTICKS {
+            if let Some(joystick_state) = self.joysticks.joystick_state(index) {
+                events.push(InputEvent::JoystickConnected(index));
+                events.extend(joystick_state.snapshot_events(index));
+            }
+        }
+
+        events.extend(self.touch.snapshot_events());
+
+        events
+    }
 }
 
 // -------
@@ -184,36 +648,38 @@ impl<S> App<'_, S> {
 
 #[derive(Copy, Clone, Default)]
 pub(super) struct ButtonState {
-    // @TODO use timestamp type (not created yet) instead of u64
-    pub(super) timestamp: u64,
+    pub(super) timestamp: GameTime,
 
     // @TODO bitflags
     pub(super) down: bool,
 }
 
 impl ButtonState {
-    fn press(&mut self, timestamp: u64) {
-        assert!(!self.down);
-        self.timestamp = timestamp;
-        self.down = true;
+    // Repeated presses and releases (events lost while the window was not focused) are ignored, so
+    // the timestamp stays the one of the first transition
+    pub(super) fn press(&mut self, timestamp: GameTime) {
+        if !self.down {
+            self.timestamp = timestamp;
+            self.down = true;
+        }
     }
 
-    fn release(&mut self, timestamp: u64) {
-        assert!(self.down);
-        self.timestamp = timestamp;
-        self.down = false;
+    pub(super) fn release(&mut self, timestamp: GameTime) {
+        if self.down {
+            self.timestamp = timestamp;
+            self.down = false;
+        }
     }
 }
 
 #[derive(Copy, Clone, Default)]
 pub(super) struct AxisState {
-    // @TODO use timestamp type (not created yet) instead of u64
-    pub(super) timestamp: u64,
+    pub(super) timestamp: GameTime,
     pub(super) value: f32,
 }
 
 impl AxisState {
-    fn update_value(&mut self, value: f32, timestamp: u64) {
+    fn update_value(&mut self, value: f32, timestamp: GameTime) {
         self.timestamp = timestamp;
         self.value = value;
     }
@@ -243,11 +709,11 @@ impl Default for KeyboardState {
 }
 
 impl KeyboardState {
-    fn press(&mut self, key: Scancode, timestamp: u64) {
+    fn press(&mut self, key: Scancode, timestamp: GameTime) {
         self.keys[key as usize].press(timestamp);
     }
 
-    fn release(&mut self, key: Scancode, timestamp: u64) {
+    fn release(&mut self, key: Scancode, timestamp: GameTime) {
         self.keys[key as usize].release(timestamp);
     }
 
@@ -264,31 +730,38 @@ impl KeyboardState {
 
 #[derive(Default)]
 pub(super) struct MouseState {
-    // @TODO relative position + moved (do we need it?)
-
     // window_id
     pos: (i32, i32),
     buttons: [ButtonState; MAX_MOUSE_BUTTONS],
 
-    //rel_pos: (i32, i32),
-    //moved: bool,
+    // Accumulated during the frame
+    motion: (i32, i32),
+    wheel: (i32, i32),
 }
 
 impl MouseState {
-    fn press(&mut self, button: sdl2::mouse::MouseButton, timestamp: u64) {
+    fn press(&mut self, button: sdl2::mouse::MouseButton, timestamp: GameTime) {
         self.buttons[button as usize].press(timestamp);
     }
 
-    fn release(&mut self, button: sdl2::mouse::MouseButton, timestamp: u64) {
+    fn release(&mut self, button: sdl2::mouse::MouseButton, timestamp: GameTime) {
         self.buttons[button as usize].release(timestamp);
     }
 
-    fn set_pos(&mut self, x: i32, y: i32) {
-        // @TODO relative position + moved (do we need it?)
-        //self.rel_pos = (x - self.pos.0, y - self.pos.1);
-        //self.moved = true;
-
+    fn motion(&mut self, x: i32, y: i32, x_rel: i32, y_rel: i32) {
         self.pos = (x, y);
+        self.motion.0 += x_rel;
+        self.motion.1 += y_rel;
+    }
+
+    fn wheel(&mut self, x: i32, y: i32) {
+        self.wheel.0 += x;
+        self.wheel.1 += y;
+    }
+
+    fn new_frame(&mut self) {
+        self.motion = (0, 0);
+        self.wheel = (0, 0);
     }
 
     pub(super) fn button_state(&self, button: sdl2::mouse::MouseButton) -> &ButtonState {
@@ -298,6 +771,15 @@ impl MouseState {
     pub(super) fn get_pos(&self) -> (i32, i32) {
         self.pos
     }
+
+    // Relative motion during the frame (also reported in relative mouse mode)
+    pub(super) fn get_motion(&self) -> (i32, i32) {
+        self.motion
+    }
+
+    pub(super) fn get_wheel(&self) -> (i32, i32) {
+        self.wheel
+    }
 }
 
 
@@ -312,104 +794,121 @@ pub(super) struct ControllerStateContainer {
 }
 
 impl ControllerStateContainer {
-    fn connect(&mut self, index: u32, controller: sdl2::controller::GameController) {
-        let id = controller.instance_id();
-        self.controller_states[index as usize].connect(controller);
+    // Replayed controllers have no SDL device
+    fn connect(&mut self, index: usize, controller: Option<sdl2::controller::GameController>) {
+        if let Some(controller) = controller.as_ref() {
+            self.id_to_index.insert(controller.instance_id(), index);
+        }
 
-        self.id_to_index.insert(id, index as usize);
+        self.controller_states[index].connect(controller);
     }
 
-    fn disconnect(&mut self, id: u32) {
-        match self.id_to_index.get(&id) {
-            Some(&index) => {
-                self.controller_states[index].disconnect();
-                self.id_to_index.remove(&id);
-            },
-            None => {
-                // @TODO log
-                println!(
-                    "[input_system controller_state_container] tried to disconnect unmapped controller id {}",
-                    id
-                );
-            }
+    fn disconnect(&mut self, index: usize) {
+        if self.controller_state(index).is_none() {
+            return;
         }
+
+        // Resets the state, so the buttons held are released
+        self.id_to_index.retain(|_, elem| *elem != index);
+        self.controller_states[index].disconnect();
     }
 
-    fn controller_state_from_id(&mut self, id: u32) -> Option<&mut ControllerState> {
-        match self.id_to_index.get(&id) {
-            Some(&index) => Some(&mut self.controller_states[index]),
-            None => None
-        }
+    fn is_free(&self, index: usize) -> bool {
+        self.controller_states.get(index).is_some_and(|c| !c.is_connected())
     }
 
-    fn controller_state_mut(&mut self, index: usize) -> Option<&mut ControllerState> {
-        match self.controller_states[index].controller {
-            Some(_) => Some(&mut self.controller_states[index]),
-            None => None,
+    // The slot of the SDL device index if it's free (keeps the usual player order), or the first
+    // free one. SDL shifts the device indices when a device is removed, so they can point to a slot
+    // in use
+    fn free_slot(&self, device_index: usize) -> Option<usize> {
+        if self.is_free(device_index) {
+            Some(device_index)
+        } else {
+            (0..MAX_CONTROLLERS).find(|&index| self.is_free(index))
         }
     }
 
+    pub(super) fn index_from_id(&self, id: u32) -> Option<usize> {
+        self.id_to_index.get(&id).copied()
+    }
+
+    pub(super) fn controller_state_mut(&mut self, index: usize) -> Option<&mut ControllerState> {
+        self.controller_states.get_mut(index).filter(|c| c.is_connected())
+    }
+
     pub(super) fn controller_state(&self, index: usize) -> Option<&ControllerState> {
-        match self.controller_states[index].controller {
-            Some(_) => Some(&self.controller_states[index]),
-            None => None,
-        }
+        self.controller_states.get(index).filter(|c| c.is_connected())
     }
 }
 
 #[derive(Default)]
 pub(super) struct ControllerState {
     // @TODO controller name, vendor, type (not supported by rust-sdl2 yet)
+    connected: bool,
     controller: Option<sdl2::controller::GameController>,
     buttons: [ButtonState; MAX_CONTROLLER_BUTTONS],
     axes: [AxisState; MAX_CONTROLLER_AXIS],
+    // Set on the first rumble error, so it's not retried (and logged) on every haptics update
+    rumble_unsupported: bool,
 }
 
 impl ControllerState {
-    fn connect(&mut self, controller: sdl2::controller::GameController) {
+    fn connect(&mut self, controller: Option<sdl2::controller::GameController>) {
         assert!(!self.is_connected());
-        println!("controller connected: {}", controller.name() );
-        self.controller = Some(controller);
+        if let Some(controller) = controller.as_ref() {
+            println!("controller connected: {}", controller.name());
+        }
+
+        self.connected = true;
+        self.controller = controller;
     }
 
     fn disconnect(&mut self) {
         assert!(self.is_connected());
-        println!("controller disconnected: {}", self.controller.as_ref().unwrap().name());
-        self.controller.take();
+        if let Some(controller) = self.controller.as_ref() {
+            println!("controller disconnected: {}", controller.name());
+        }
+
+        *self = Self::default();
     }
 
     fn is_connected(&self) -> bool {
-        self.controller.is_some()
+        self.connected
     }
 
-    fn press_button(&mut self, button: sdl2::controller::Button, timestamp: u64) {
+    fn press_button(&mut self, button: sdl2::controller::Button, timestamp: GameTime) {
         assert!(self.is_connected());
         self.buttons[button as usize].press(timestamp);
     }
 
-    fn release_button(&mut self, button: sdl2::controller::Button, timestamp: u64) {
+    fn release_button(&mut self, button: sdl2::controller::Button, timestamp: GameTime) {
         assert!(self.is_connected());
         self.buttons[button as usize].release(timestamp);
     }
 
-    fn update_axis(&mut self, axis: sdl2::controller::Axis, value: f32, timestamp: u64) {
+    fn update_axis(&mut self, axis: sdl2::controller::Axis, value: f32, timestamp: GameTime) {
         assert!(self.is_connected());
         self.axes[axis as usize].update_value(value, timestamp);
     }
 
-    fn set_rumble(
+    pub(super) fn set_rumble(
         &mut self,
         low_frequency: u16,
         high_frequency: u16,
         duration: u32
     ) {
         assert!(self.is_connected());
-        let controller_mut = self.controller.as_mut().unwrap();
+        let controller_mut = match self.controller.as_mut() {
+            Some(controller) if !self.rumble_unsupported => controller,
+            _ => return,
+        };
+
         match controller_mut.set_rumble(low_frequency, high_frequency, duration) {
             Ok(_) => {},
             Err(e) => {
                 // @TODO log
                 println!("[input_system controller_state set_rumble] {}", e);
+                self.rumble_unsupported = true;
             }
         }
     }
@@ -422,3 +921,281 @@ impl ControllerState {
         &self.axes[axis as usize]
     }
 }
+
+// --------
+// Joystick
+// --------
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum JoystickHatDirection {
+    Up    = 0,
+    Right = 1,
+    Down  = 2,
+    Left  = 3,
+}
+
+impl JoystickHatDirection {
+    // Same bits as sdl2::joystick::HatState
+    fn bit(self) -> u8 {
+        1 << (self as u8)
+    }
+
+    pub(super) fn is_set(self, bits: u8) -> bool {
+        bits & self.bit() != 0
+    }
+}
+
+pub(super) const JOYSTICK_HAT_DIRECTIONS: [JoystickHatDirection; 4] = [
+    JoystickHatDirection::Up,
+    JoystickHatDirection::Right,
+    JoystickHatDirection::Down,
+    JoystickHatDirection::Left,
+];
+
+#[derive(Default)]
+pub(super) struct JoystickStateContainer {
+    pub(super) joystick_states: [JoystickState; MAX_JOYSTICKS],
+    id_to_index: BTreeMap<u32, usize>,
+}
+
+impl JoystickStateContainer {
+    fn connect(&mut self, joystick: sdl2::joystick::Joystick) -> Option<usize> {
+        let id = joystick.instance_id();
+        let guid = joystick.guid().string();
+
+        // Repeated added event for a joystick already open
+        if self.id_to_index.contains_key(&id) {
+            return None;
+        }
+
+        // Reconnecting a device keeps its index (and the bindings using it). If it's a new device,
+        // we use a slot never used before or, if there's none, any free slot
+        let index = self.joystick_states.iter()
+            .position(|j| !j.is_connected() && j.guid.as_deref() == Some(guid.as_str()))
+            .or_else(|| self.joystick_states.iter().position(|j| j.guid.is_none()))
+            .or_else(|| self.joystick_states.iter().position(|j| !j.is_connected()));
+
+        match index {
+            Some(index) => {
+                self.joystick_states[index].connect(Some(joystick), Some(guid));
+                self.id_to_index.insert(id, index);
+            },
+            None => {
+                // @TODO log
+                println!("[input_system joystick_state_container] no free joystick slot ({})", guid);
+            }
+        }
+
+        index
+    }
+
+    // Replayed joysticks have no SDL device
+    fn connect_virtual(&mut self, index: usize) {
+        self.joystick_states[index].connect(None, None);
+    }
+
+    fn disconnect(&mut self, index: usize) {
+        if self.joystick_state(index).is_none() {
+            return;
+        }
+
+        // Resets the state, so the buttons held are released
+        self.id_to_index.retain(|_, elem| *elem != index);
+        self.joystick_states[index].disconnect();
+    }
+
+    fn is_free(&self, index: usize) -> bool {
+        self.joystick_states.get(index).is_some_and(|j| !j.is_connected())
+    }
+
+    pub(super) fn index_from_id(&self, id: u32) -> Option<usize> {
+        self.id_to_index.get(&id).copied()
+    }
+
+    fn joystick_state_mut(&mut self, index: usize) -> Option<&mut JoystickState> {
+        self.joystick_states.get_mut(index).filter(|j| j.is_connected())
+    }
+
+    pub(super) fn joystick_state(&self, index: usize) -> Option<&JoystickState> {
+        self.joystick_states.get(index).filter(|j| j.is_connected())
+    }
+}
+
+#[derive(Default)]
+pub(super) struct JoystickState {
+    connected: bool,
+    joystick: Option<sdl2::joystick::Joystick>,
+    guid: Option<String>,
+    buttons: [ButtonState; MAX_JOYSTICK_BUTTONS],
+    axes: [AxisState; MAX_JOYSTICK_AXES],
+    hats: [[ButtonState; 4]; MAX_JOYSTICK_HATS],
+}
+
+impl JoystickState {
+    fn connect(&mut self, joystick: Option<sdl2::joystick::Joystick>, guid: Option<String>) {
+        assert!(!self.is_connected());
+        if let Some(joystick) = joystick.as_ref() {
+            println!("joystick connected: {} ({})", joystick.name(), guid.as_deref().unwrap_or(""));
+        }
+
+        self.connected = true;
+        self.joystick = joystick;
+        if guid.is_some() {
+            self.guid = guid;
+        }
+    }
+
+    fn disconnect(&mut self) {
+        assert!(self.is_connected());
+        if let Some(joystick) = self.joystick.as_ref() {
+            println!("joystick disconnected: {}", joystick.name());
+        }
+
+        // Keep the guid to reuse the slot if the same device is reconnected
+        *self = Self {
+            guid: self.guid.take(),
+            ..Self::default()
+        };
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    fn press_button(&mut self, button: u8, timestamp: GameTime) {
+        if let Some(button_state) = self.buttons.get_mut(button as usize) {
+            button_state.press(timestamp);
+        }
+    }
+
+    fn release_button(&mut self, button: u8, timestamp: GameTime) {
+        if let Some(button_state) = self.buttons.get_mut(button as usize) {
+            button_state.release(timestamp);
+        }
+    }
+
+    fn update_axis(&mut self, axis: u8, value: f32, timestamp: GameTime) {
+        if let Some(axis_state) = self.axes.get_mut(axis as usize) {
+            axis_state.update_value(value, timestamp);
+        }
+    }
+
+    fn update_hat(&mut self, hat: u8, bits: u8, timestamp: GameTime) {
+        let hat_state = match self.hats.get_mut(hat as usize) {
+            Some(hat_state) => hat_state,
+            None => return,
+        };
+
+        for direction in JOYSTICK_HAT_DIRECTIONS.iter() {
+            let button_state = &mut hat_state[*direction as usize];
+            let down = direction.is_set(bits);
+
+            if down && !button_state.down {
+                button_state.press(timestamp);
+            } else if !down && button_state.down {
+                button_state.release(timestamp);
+            }
+        }
+    }
+
+    fn snapshot_events(&self, index: usize) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+
+        for (button, button_state) in self.buttons.iter().enumerate() {
+            if button_state.down {
+                events.push(InputEvent::JoystickButtonDown(index, button as u8));
+            }
+        }
+
+        for (axis, axis_state) in self.axes.iter().enumerate() {
+            if axis_state.value != 0.0 {
+                events.push(InputEvent::JoystickAxisMotion(index, axis as u8, axis_state.value));
+            }
+        }
+
+        for (hat, hat_state) in self.hats.iter().enumerate() {
+            let bits = JOYSTICK_HAT_DIRECTIONS.iter()
+                .filter(|direction| hat_state[**direction as usize].down)
+                .fold(0, |bits, direction| bits | direction.bit());
+
+            if bits != 0 {
+                events.push(InputEvent::JoystickHatMotion(index, hat as u8, bits));
+            }
+        }
+
+        events
+    }
+
+    // Out of range buttons, axes and hats are never pressed
+    pub(super) fn button_state(&self, button: u8) -> ButtonState {
+        self.buttons.get(button as usize).copied().unwrap_or_default()
+    }
+
+    pub(super) fn axis_state(&self, axis: u8) -> AxisState {
+        self.axes.get(axis as usize).copied().unwrap_or_default()
+    }
+
+    pub(super) fn hat_state(&self, hat: u8, direction: JoystickHatDirection) -> ButtonState {
+        self.hats.get(hat as usize)
+            .map(|hat_state| hat_state[direction as usize])
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl2::joystick::HatState;
+
+    #[test]
+    fn test_joystick_hat_directions() {
+        let mut joystick = JoystickState::default();
+
+        joystick.update_hat(0, HatState::LeftUp as u8, GameTime::from_micros(1));
+        assert!(joystick.hat_state(0, JoystickHatDirection::Up).down);
+        assert!(joystick.hat_state(0, JoystickHatDirection::Left).down);
+        assert!(!joystick.hat_state(0, JoystickHatDirection::Right).down);
+
+        // Up keeps its timestamp, Left is released
+        joystick.update_hat(0, HatState::Up as u8, GameTime::from_micros(2));
+        assert_eq!(joystick.hat_state(0, JoystickHatDirection::Up).timestamp, GameTime::from_micros(1));
+        assert!(!joystick.hat_state(0, JoystickHatDirection::Left).down);
+        assert_eq!(joystick.hat_state(0, JoystickHatDirection::Left).timestamp, GameTime::from_micros(2));
+
+        // Out of range hats are ignored
+        joystick.update_hat(MAX_JOYSTICK_HATS as u8, HatState::Down as u8, GameTime::from_micros(3));
+        assert!(!joystick.hat_state(MAX_JOYSTICK_HATS as u8, JoystickHatDirection::Down).down);
+    }
+
+    #[test]
+    fn test_controller_free_slot() {
+        let mut controllers = ControllerStateContainer::default();
+        controllers.connect(0, None);
+        controllers.connect(1, None);
+
+        assert_eq!(controllers.free_slot(2), Some(2));
+        // device index shifted to a slot in use
+        assert_eq!(controllers.free_slot(1), Some(2));
+        assert_eq!(controllers.free_slot(MAX_CONTROLLERS + 3), Some(2));
+
+        for index in 2..MAX_CONTROLLERS {
+            controllers.connect(index, None);
+        }
+        assert_eq!(controllers.free_slot(0), None);
+    }
+
+    #[test]
+    fn test_button_state_repeated_events() {
+        let mut button_state = ButtonState::default();
+
+        button_state.press(GameTime::from_micros(1));
+        button_state.press(GameTime::from_micros(2));
+        assert!(button_state.down);
+        assert_eq!(button_state.timestamp, GameTime::from_micros(1));
+
+        button_state.release(GameTime::from_micros(3));
+        button_state.release(GameTime::from_micros(4));
+        assert!(!button_state.down);
+        assert_eq!(button_state.timestamp, GameTime::from_micros(3));
+    }
+}
@@ -0,0 +1,165 @@
+// Text input
+//
+// Typed text (with the keyboard layout and IME applied) for name entry and the chart editor. The
+// text is accumulated while the text input is active and taken by the game. The IME composition
+// (text being composed but not committed yet) is exposed to draw it at the cursor.
+//
+// Text input doesn't go through the mappings and isn't recorded.
+
+/* Usage
+
+// open the name entry
+app.start_text_input();
+app.set_text_input_rect(x, y, w, h); // IME candidates window position
+
+// update
+
+self.name.push_str(&app.take_text_input());
+
+if let Some(composition) = app.text_composition() {
+    // draw composition.text after the name, underlined
+}
+
+// close the name entry
+app.stop_text_input();
+*/
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+    sdl2::event::Event,
+};
+
+use super::system::InputSystem;
+
+#[derive(Clone, Debug, Default, PartialEq, ImDraw)]
+pub struct TextComposition {
+    pub text: String,
+    // In characters
+    pub cursor: i32,
+    pub selection_length: i32,
+}
+
+#[derive(Default)]
+pub(super) struct TextInputState {
+    active: bool,
+    text: String,
+    composition: TextComposition,
+}
+
+impl TextInputState {
+    pub(super) fn start(&mut self) {
+        *self = Self { active: true, ..Self::default() };
+    }
+
+    pub(super) fn stop(&mut self) {
+        *self = Self::default();
+    }
+
+    pub(super) fn commit(&mut self, text: &str) {
+        if !self.active {
+            return;
+        }
+
+        self.text.push_str(text);
+        self.composition = TextComposition::default();
+    }
+
+    pub(super) fn compose(&mut self, text: &str, cursor: i32, selection_length: i32) {
+        if !self.active {
+            return;
+        }
+
+        self.composition = TextComposition {
+            text: text.to_string(),
+            cursor,
+            selection_length,
+        };
+    }
+
+    fn take(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+
+    fn composition(&self) -> Option<&TextComposition> {
+        if self.composition.text.is_empty() {
+            None
+        } else {
+            Some(&self.composition)
+        }
+    }
+}
+
+impl InputSystem {
+    // Returns if the event was a text event
+    pub(super) fn handle_text_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::TextInput { text, .. } => self.text_input.commit(text),
+            Event::TextEditing { text, start, length, .. } => self.text_input.compose(text, *start, *length),
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+impl<S> App<'_, S> {
+    // Clears the text typed before
+    pub fn start_text_input(&mut self) {
+        self.sdl_context.video_subsystem.text_input().start();
+        self.input_system.text_input.start();
+    }
+
+    pub fn stop_text_input(&mut self) {
+        self.sdl_context.video_subsystem.text_input().stop();
+        self.input_system.text_input.stop();
+    }
+
+    pub fn is_text_input_active(&self) -> bool {
+        self.input_system.text_input.active
+    }
+
+    // Where the text is drawn, in window coordinates, so the IME can place its windows next to it
+    pub fn set_text_input_rect(&mut self, x: i32, y: i32, w: u32, h: u32) {
+        let rect = sdl2::rect::Rect::new(x, y, w, h);
+        self.sdl_context.video_subsystem.text_input().set_rect(rect);
+    }
+
+    // Text committed since the last call
+    pub fn take_text_input(&mut self) -> String {
+        self.input_system.text_input.take()
+    }
+
+    // None if nothing is being composed
+    pub fn text_composition(&self) -> Option<&TextComposition> {
+        self.input_system.text_input.composition()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_input_state() {
+        let mut state = TextInputState::default();
+
+        // ignored while inactive
+        state.commit("a");
+        assert_eq!(state.take(), "");
+
+        state.start();
+        state.compose("ka", 2, 0);
+        assert_eq!(state.composition().map(|composition| composition.text.as_str()), Some("ka"));
+
+        state.commit("か");
+        state.commit("b");
+        assert!(state.composition().is_none());
+        assert_eq!(state.take(), "かb");
+        assert_eq!(state.take(), "");
+
+        state.compose("x", 1, 0);
+        state.stop();
+        assert!(state.composition().is_none());
+    }
+}
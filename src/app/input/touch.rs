@@ -0,0 +1,355 @@
+// Touch input
+//
+// Touch points (fingers on a touchscreen or touchpad) tracked by finger id, with their positions
+// mapped to the virtual canvas. Single finger taps and swipes are recognized when the finger is
+// lifted, and the drag (single finger) and pinch (first two fingers) amounts are accumulated during
+// the frame. Gestures can be mapped as Button sources and drag/pinch as Axis sources.
+
+/* Usage
+
+// the canvas the positions are mapped to (defaults to the renderer projection)
+app.set_touch_canvas_size(Vec2 { x: 1280.0, y: 960.0 });
+
+let mut button = Button::new();
+button.add_key(sdl2::keyboard::Scancode::Space);
+button.add_touch_gesture(TouchGesture::Tap);
+input_mapping.add_button_mapping("HIT".to_string(), button);
+
+// chart editor zoom (fraction of the canvas height the fingers moved apart during the frame)
+let mut zoom = Axis::new();
+zoom.add_touch_axis(TouchAxis::Pinch);
+zoom.settings.sensitivity = 10.0;
+input_mapping.add_axis_mapping("ZOOM".to_string(), zoom);
+
+// update
+
+for point in app.touch_points() {
+    // draw a ripple at point.pos
+}
+*/
+
+use crate::app::{
+    App,
+    imdraw::ImDraw,
+    time_system::{Duration, GameTime},
+};
+use crate::linalg::Vec2;
+
+use super::{
+    event::InputEvent,
+    system::{ButtonState, InputSystem},
+};
+
+// Same as the renderer projection
+const DEFAULT_TOUCH_CANVAS_SIZE: Vec2 = Vec2 { x: 1280.0, y: 960.0 };
+
+const MAX_TOUCH_POINTS: usize = 10;
+
+// Distances in canvas units
+const TAP_MAX_DURATION   : Duration = Duration::from_millis(250);
+const TAP_MAX_DISTANCE   : f32 = 20.0;
+const SWIPE_MAX_DURATION : Duration = Duration::from_millis(500);
+const SWIPE_MIN_DISTANCE : f32 = 100.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TouchGesture {
+    // Down while any finger is down
+    Touch,
+    // Down for a single frame when the finger is lifted
+    Tap,
+    SwipeLeft,
+    SwipeRight,
+    SwipeUp,
+    SwipeDown,
+}
+
+impl_imdraw_todo!(TouchGesture);
+
+pub(super) const TOUCH_GESTURES: [TouchGesture; 6] = [
+    TouchGesture::Touch,
+    TouchGesture::Tap,
+    TouchGesture::SwipeLeft,
+    TouchGesture::SwipeRight,
+    TouchGesture::SwipeUp,
+    TouchGesture::SwipeDown,
+];
+
+// Amounts during the frame, as a fraction of the canvas size
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TouchAxis {
+    // Single finger motion, positive to the right
+    DragX,
+    // Single finger motion, positive down
+    DragY,
+    // Change of distance between the first two fingers (of the canvas height), positive apart
+    Pinch,
+}
+
+impl_imdraw_todo!(TouchAxis);
+
+#[derive(Copy, Clone, Debug, PartialEq, ImDraw)]
+pub struct TouchPoint {
+    pub id: i64,
+    // Canvas position
+    pub pos: Vec2,
+    pub start_pos: Vec2,
+    pub start_timestamp: GameTime,
+
+    // Another finger was down at some point (a pinch, not a tap or a swipe)
+    multi_touch: bool,
+}
+
+pub(super) struct TouchState {
+    canvas_size: Vec2,
+    // In touch order
+    points: Vec<TouchPoint>,
+    gestures: [ButtonState; TOUCH_GESTURES.len()],
+
+    // Accumulated during the frame, in canvas units
+    drag: Vec2,
+    pinch: f32,
+}
+
+impl Default for TouchState {
+    fn default() -> Self {
+        Self {
+            canvas_size: DEFAULT_TOUCH_CANVAS_SIZE,
+            points: Vec::new(),
+            gestures: Default::default(),
+            drag: Vec2::new(),
+            pinch: 0.0,
+        }
+    }
+}
+
+impl TouchState {
+    pub(super) fn canvas_size(&self) -> Vec2 {
+        self.canvas_size
+    }
+
+    pub(super) fn set_canvas_size(&mut self, canvas_size: Vec2) {
+        assert!(canvas_size.x > 0.0 && canvas_size.y > 0.0, "[touch_state set_canvas_size] invalid canvas size");
+        self.canvas_size = canvas_size;
+    }
+
+    fn to_canvas(&self, x: f32, y: f32) -> Vec2 {
+        Vec2 { x: x * self.canvas_size.x, y: y * self.canvas_size.y }
+    }
+
+    fn pinch_distance(&self) -> Option<f32> {
+        match self.points.as_slice() {
+            [first, second, ..] => Some((second.pos - first.pos).mag()),
+            _ => None,
+        }
+    }
+
+    // Positions normalized to [0, 1]
+    pub(super) fn finger_down(&mut self, id: i64, x: f32, y: f32, timestamp: GameTime) {
+        // Repeated down event (the up event was lost)
+        if self.points.iter().any(|point| point.id == id) || self.points.len() >= MAX_TOUCH_POINTS {
+            return;
+        }
+
+        let multi_touch = !self.points.is_empty();
+        for point in self.points.iter_mut() {
+            point.multi_touch = true;
+        }
+
+        let pos = self.to_canvas(x, y);
+        self.points.push(TouchPoint {
+            id,
+            pos,
+            start_pos: pos,
+            start_timestamp: timestamp,
+            multi_touch,
+        });
+
+        self.gestures[TouchGesture::Touch as usize].press(timestamp);
+    }
+
+    pub(super) fn finger_motion(&mut self, id: i64, x: f32, y: f32) {
+        let index = match self.points.iter().position(|point| point.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+
+        let previous_distance = self.pinch_distance();
+        let pos = self.to_canvas(x, y);
+        let motion = pos - self.points[index].pos;
+        self.points[index].pos = pos;
+
+        if self.points.len() == 1 {
+            self.drag += motion;
+        } else if index < 2 {
+            self.pinch += self.pinch_distance().unwrap() - previous_distance.unwrap();
+        }
+    }
+
+    pub(super) fn finger_up(&mut self, id: i64, x: f32, y: f32, timestamp: GameTime) {
+        self.finger_motion(id, x, y);
+
+        let index = match self.points.iter().position(|point| point.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let point = self.points.remove(index);
+
+        if !point.multi_touch {
+            if let Some(gesture) = recognize(&point, timestamp) {
+                self.gestures[gesture as usize].press(timestamp);
+            }
+        }
+
+        if self.points.is_empty() {
+            self.gestures[TouchGesture::Touch as usize].release(timestamp);
+        }
+    }
+
+    pub(super) fn new_frame(&mut self) {
+        self.drag = Vec2::new();
+        self.pinch = 0.0;
+
+        // Taps and swipes were down during the previous frame only
+        for gesture in TOUCH_GESTURES.iter().filter(|gesture| **gesture != TouchGesture::Touch) {
+            let state = &mut self.gestures[*gesture as usize];
+            let timestamp = state.timestamp;
+            state.release(timestamp);
+        }
+    }
+
+    pub(super) fn gesture_state(&self, gesture: TouchGesture) -> &ButtonState {
+        &self.gestures[gesture as usize]
+    }
+
+    pub(super) fn axis_value(&self, axis: TouchAxis) -> f32 {
+        match axis {
+            TouchAxis::DragX => self.drag.x / self.canvas_size.x,
+            TouchAxis::DragY => self.drag.y / self.canvas_size.y,
+            TouchAxis::Pinch => self.pinch / self.canvas_size.y,
+        }
+    }
+
+    pub(super) fn points(&self) -> &[TouchPoint] {
+        &self.points
+    }
+
+    pub(super) fn snapshot_events(&self) -> Vec<InputEvent> {
+        self.points.iter()
+            .map(|point| {
+                InputEvent::FingerDown(point.id, point.pos.x / self.canvas_size.x, point.pos.y / self.canvas_size.y)
+            })
+            .collect()
+    }
+}
+
+// Taps and swipes, from a single finger
+fn recognize(point: &TouchPoint, timestamp: GameTime) -> Option<TouchGesture> {
+    let duration = timestamp - point.start_timestamp;
+    let motion = point.pos - point.start_pos;
+    let distance = motion.mag();
+
+    if duration <= TAP_MAX_DURATION && distance <= TAP_MAX_DISTANCE {
+        Some(TouchGesture::Tap)
+    } else if duration <= SWIPE_MAX_DURATION && distance >= SWIPE_MIN_DISTANCE {
+        // Canvas y goes down
+        let gesture = if motion.x.abs() > motion.y.abs() {
+            if motion.x > 0.0 { TouchGesture::SwipeRight } else { TouchGesture::SwipeLeft }
+        } else if motion.y > 0.0 {
+            TouchGesture::SwipeDown
+        } else {
+            TouchGesture::SwipeUp
+        };
+
+        Some(gesture)
+    } else {
+        None
+    }
+}
+
+impl InputSystem {
+    // Replayed devices use the same canvas
+    pub(super) fn set_touch_canvas_size(&mut self, canvas_size: Vec2) {
+        self.devices.touch.set_canvas_size(canvas_size);
+        if let Some(playback) = self.playback.as_mut() {
+            playback.live_devices.touch.set_canvas_size(canvas_size);
+        }
+    }
+}
+
+impl<S> App<'_, S> {
+    pub fn set_touch_canvas_size(&mut self, canvas_size: Vec2) {
+        self.input_system.set_touch_canvas_size(canvas_size);
+    }
+
+    pub fn touch_canvas_size(&self) -> Vec2 {
+        self.input_system.devices.touch.canvas_size()
+    }
+
+    // Fingers down, in touch order
+    pub fn touch_points(&self) -> &[TouchPoint] {
+        self.input_system.devices.touch.points()
+    }
+
+    pub fn touch_point(&self, id: i64) -> Option<&TouchPoint> {
+        self.input_system.devices.touch.points().iter().find(|point| point.id == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> GameTime {
+        GameTime::from_micros(ms * 1_000)
+    }
+
+    #[test]
+    fn test_touch_tap_and_swipe() {
+        let mut touch = TouchState::default();
+
+        touch.finger_down(1, 0.5, 0.5, ms(0));
+        assert!(touch.gesture_state(TouchGesture::Touch).down);
+        assert_eq!(touch.points()[0].pos, Vec2 { x: 640.0, y: 480.0 });
+
+        touch.finger_up(1, 0.505, 0.5, ms(100));
+        assert!(touch.gesture_state(TouchGesture::Tap).down);
+        assert!(!touch.gesture_state(TouchGesture::Touch).down);
+
+        // taps last a single frame
+        touch.new_frame();
+        assert!(!touch.gesture_state(TouchGesture::Tap).down);
+
+        touch.finger_down(2, 0.5, 0.5, ms(200));
+        touch.finger_motion(2, 0.4, 0.52);
+        touch.finger_up(2, 0.3, 0.55, ms(400));
+        assert!(touch.gesture_state(TouchGesture::SwipeLeft).down);
+        assert!(!touch.gesture_state(TouchGesture::Tap).down);
+        assert!((touch.axis_value(TouchAxis::DragX) + 0.2).abs() < 1e-5);
+
+        // too slow
+        touch.new_frame();
+        touch.finger_down(3, 0.5, 0.5, ms(500));
+        touch.finger_up(3, 0.5, 0.8, ms(1_500));
+        assert!(!touch.gesture_state(TouchGesture::SwipeDown).down);
+    }
+
+    #[test]
+    fn test_touch_pinch() {
+        let mut touch = TouchState::default();
+
+        touch.finger_down(1, 0.5, 0.4, ms(0));
+        touch.finger_down(2, 0.5, 0.6, ms(10));
+        touch.finger_motion(1, 0.5, 0.3);
+        touch.finger_motion(2, 0.5, 0.7);
+        assert!((touch.axis_value(TouchAxis::Pinch) - 0.2).abs() < 1e-5);
+        assert_eq!(touch.axis_value(TouchAxis::DragY), 0.0);
+
+        // fingers of a pinch are not taps
+        touch.finger_up(1, 0.5, 0.3, ms(100));
+        touch.finger_up(2, 0.5, 0.7, ms(110));
+        assert!(!touch.gesture_state(TouchGesture::Tap).down);
+
+        touch.new_frame();
+        assert_eq!(touch.axis_value(TouchAxis::Pinch), 0.0);
+    }
+}
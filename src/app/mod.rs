@@ -5,11 +5,15 @@ pub mod animation_system;
 pub mod asset_system;
 pub mod debug;
 pub mod game_state;
+#[cfg(test)]
+mod headless;
 pub mod id_manager;
 #[macro_use] pub mod imgui;
+pub mod audio; // after imgui for its macros
 pub mod input;
 pub mod renderer;
 pub mod sdl;
+pub mod sequence;
 pub mod task_system;
 pub mod transform;
 pub mod time_system;
@@ -29,10 +33,11 @@ pub use {
     video_system::*,
 };
 
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::Scancode;
 
 use asset_system::*;
+use audio::AudioSystem;
 use debug::*;
 use sdl::*;
 use time_system::*;
@@ -40,6 +45,7 @@ use time_system::*;
 pub struct App<'a, S> {
     asset_system: AssetSystem,
     animation_system: AnimationSystem,
+    audio_system: AudioSystem,
     debug: Debug,
     input_system: InputSystem,
     renderer: Renderer,
@@ -60,8 +66,12 @@ impl<'a, S: GameState> App<'a, S> {
         let sdl_context = SdlContext::new();
         let video_system = VideoSystem::new(sdl_context.video_subsystem.clone());
 
-        let input_system = InputSystem::new(sdl_context.controller_subsystem.clone());
+        let input_system = InputSystem::new(
+            sdl_context.controller_subsystem.clone(),
+            sdl_context.joystick_subsystem.clone(),
+        );
         let time_system = TimeSystem::new(sdl_context.timer_subsystem.clone());
+        let audio_system = AudioSystem::new(sdl_context.audio_subsystem.clone());
         let renderer = Renderer::new();
 
         let animation_system = AnimationSystem::new();
@@ -71,6 +81,7 @@ impl<'a, S: GameState> App<'a, S> {
         Self {
             asset_system: AssetSystem::new(),
             animation_system,
+            audio_system,
 
             sdl_context,
             video_system,
@@ -91,8 +102,11 @@ impl<'a, S: GameState> App<'a, S> {
 
         while self.running {
             self.new_frame();
+            // Song clocks are set from the audio before anything runs on them
+            self.update_song_clocks();
             self.run_tasks(&mut state);
 
+            self.input_system.new_frame();
             let events: Vec<Event> = self.sdl_context.event_pump.poll_iter().collect();
             for event in events.into_iter() {
                 // Handle game input first to allow it consuming the input
@@ -104,12 +118,30 @@ impl<'a, S: GameState> App<'a, S> {
                 self.handle_input(&event);
             }
 
+            // Replayed input is applied after the live events, before the updates
+            let now = self.time_system.game_time();
+            self.input_system.update_playback(now);
+            self.input_system.update_haptics(now);
+
+            // Fixed updates run with a constant delta, independent of the frame rate
+            let fixed_steps = self.advance_fixed_timestep();
+            for _ in 0..fixed_steps {
+                self.step_fixed_timestep();
+                state.fixed_update(self);
+            }
+
             state.update(self);
 
+            // After the updates, so new streams are buffered before the next audio callback
+            let frame_start_time = self.time_system.frame_start_time();
+            let real_frame_duration = self.last_real_frame_duration();
+            self.audio_system.update(frame_start_time, real_frame_duration);
+
             // Render
             self.renderer.prepare_render();
             state.render(self);
             self.video_system.swap_buffers();
+            self.wait_frame_cap();
         }
     }
 
@@ -118,7 +150,7 @@ impl<'a, S: GameState> App<'a, S> {
     }
 
     fn handle_input(&mut self, event: &Event) {
-        let timestamp = self.time_system.game_time;
+        let timestamp = self.time_system.event_game_time(event.get_timestamp());
 
         match event {
             Event::Quit {..}
@@ -126,6 +158,19 @@ impl<'a, S: GameState> App<'a, S> {
                 self.running = false;
                 return;
             },
+
+            // Input events are lost while the window doesn't have focus
+            Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                self.input_system.resync_keyboard_and_mouse(&[], &[], timestamp);
+            },
+            Event::Window { win_event: WindowEvent::FocusGained, .. } => {
+                let event_pump = &self.sdl_context.event_pump;
+                let keys: Vec<Scancode> = event_pump.keyboard_state().pressed_scancodes().collect();
+                let mouse_buttons: Vec<_> = event_pump.mouse_state().pressed_mouse_buttons().collect();
+
+                self.input_system.resync_keyboard_and_mouse(&keys, &mouse_buttons, timestamp);
+            },
+
             _ => {}
         }
 
@@ -6,6 +6,9 @@ pub(in crate::app) struct SdlContext {
     pub(in crate::app) video_subsystem: sdl2::VideoSubsystem,
     pub(in crate::app) timer_subsystem: sdl2::TimerSubsystem,
     pub(in crate::app) controller_subsystem: sdl2::GameControllerSubsystem,
+    pub(in crate::app) joystick_subsystem: sdl2::JoystickSubsystem,
+    // None without an audio driver, the audio system has a virtual output then
+    pub(in crate::app) audio_subsystem: Option<sdl2::AudioSubsystem>,
     pub(in crate::app) ttf_context: sdl2::ttf::Sdl2TtfContext,
 
     // Hidden since we don't need to use it directly but dropping it closes the subsystem
@@ -21,6 +24,8 @@ impl SdlContext {
         let video_subsystem = sdl.video().unwrap();
         let timer_subsystem = sdl.timer().unwrap();
         let controller_subsystem = sdl.game_controller().unwrap();
+        let joystick_subsystem = sdl.joystick().unwrap();
+        let audio_subsystem = sdl.audio().ok();
         let ttf_context = sdl2::ttf::init().unwrap();
 
         let _sdl_image_context = sdl2::image::init(sdl2::image::InitFlag::PNG).unwrap();
@@ -31,6 +36,8 @@ impl SdlContext {
             video_subsystem,
             timer_subsystem,
             controller_subsystem,
+            joystick_subsystem,
+            audio_subsystem,
             ttf_context,
             _sdl_image_context,
         }
@@ -0,0 +1,402 @@
+// Sequence
+//
+// Scripted sequences of steps (run code, wait some time, wait until a condition) executed over
+// multiple frames. Built on top of the task system: every wait schedules a task that resumes the
+// sequence.
+
+/* Usage
+
+let sequence = Sequence::new()
+    .run(|state: &mut State, _app| state.countdown = 3)
+    .wait(Duration::from_secs(1))
+    .run(|state, _app| state.countdown = 2)
+    .wait(Duration::from_secs(1))
+    .run(|state, _app| state.countdown = 1)
+    .wait_animation(|state| &state.go_animator)
+    .wait_until(|_state, app| app.game_time() > start_time)
+    .run(|state, app| state.start_song(app));
+
+self.countdown_sequence = app.play_sequence(sequence);
+
+// stop (cancels the pending wait)
+
+app.stop_sequence(&mut self.countdown_sequence);
+*/
+
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+use super::{
+    App,
+    animation_system::Animator,
+    imgui::ImDraw,
+    task_system::{Task, TaskHost},
+    time_system::{Clock, Duration},
+};
+
+pub struct Sequence<S> {
+    steps: VecDeque<SequenceStep<SequenceFn<S>, SequencePredicate<S>>>,
+}
+
+type SequenceFn<S> = Box<dyn FnMut(&mut S, &mut App<S>)>;
+type SequencePredicate<S> = Box<dyn FnMut(&mut S, &mut App<S>) -> bool>;
+
+// Generic over the step callbacks, which get the sequence host
+pub(in crate::app) enum SequenceStep<F, P> {
+    Run(F),
+    Wait(Duration),
+    WaitFrames(u32),
+    // Checked once per frame
+    WaitUntil(P),
+}
+
+// Plays the sequences: the app, or a headless app in the tests
+pub(in crate::app) trait SequenceHost<'a, S>: TaskHost<'a, S> {
+    type Run: 'a;
+    type Predicate: 'a;
+
+    fn run_step(&mut self, callback: &mut Self::Run, state: &mut S);
+    fn check_step(&mut self, predicate: &mut Self::Predicate, state: &mut S) -> bool;
+}
+
+impl<'a, S: 'static> SequenceHost<'a, S> for App<'a, S> {
+    type Run = SequenceFn<S>;
+    type Predicate = SequencePredicate<S>;
+
+    fn run_step(&mut self, callback: &mut SequenceFn<S>, state: &mut S) {
+        callback(state, self);
+    }
+
+    fn check_step(&mut self, predicate: &mut SequencePredicate<S>, state: &mut S) -> bool {
+        predicate(state, self)
+    }
+}
+
+impl<S: 'static> Sequence<S> {
+    pub fn new() -> Self {
+        Self {
+            steps: VecDeque::new(),
+        }
+    }
+
+    pub fn run<F>(mut self, callback: F) -> Self
+    where F: FnMut(&mut S, &mut App<S>) + 'static
+    {
+        self.steps.push_back(SequenceStep::Run(Box::new(callback)));
+        self
+    }
+
+    pub fn wait(mut self, duration: Duration) -> Self {
+        self.steps.push_back(SequenceStep::Wait(duration));
+        self
+    }
+
+    pub fn wait_frames(mut self, frames: u32) -> Self {
+        self.steps.push_back(SequenceStep::WaitFrames(frames));
+        self
+    }
+
+    pub fn wait_until<F>(mut self, predicate: F) -> Self
+    where F: FnMut(&mut S, &mut App<S>) -> bool + 'static
+    {
+        self.steps.push_back(SequenceStep::WaitUntil(Box::new(predicate)));
+        self
+    }
+
+    // Waits until the animator is not playing anymore
+    pub fn wait_animation<F>(self, animator: F) -> Self
+    where F: Fn(&S) -> &Animator + 'static
+    {
+        self.wait_until(move |state, app| animator(state).is_finished(app))
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl<S: 'static> Default for Sequence<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Handle to a playing sequence. Can be stored in the game state
+#[derive(Clone, Debug, Default, ImDraw)]
+pub struct SequenceHandle(Rc<RefCell<SequenceStatus>>);
+
+#[derive(Debug, Default, ImDraw)]
+struct SequenceStatus {
+    // Task that will resume the sequence
+    task: Task,
+    running: bool,
+}
+
+impl SequenceHandle {
+    pub fn is_running(&self) -> bool {
+        self.0.borrow().running
+    }
+}
+
+struct SequenceRunner<F, P> {
+    steps: VecDeque<SequenceStep<F, P>>,
+    clock: Clock,
+    status: SequenceHandle,
+}
+
+impl<S: 'static> App<'_, S> {
+    // The sequence starts on the next frame
+    #[track_caller]
+    pub fn play_sequence(&mut self, sequence: Sequence<S>) -> SequenceHandle {
+        self.play_sequence_on(Clock::GAME, sequence)
+    }
+
+    // Waits are counted on the given clock
+    #[track_caller]
+    pub fn play_sequence_on(&mut self, clock: Clock, sequence: Sequence<S>) -> SequenceHandle {
+        play_sequence(self, clock, sequence.steps)
+    }
+
+    pub fn stop_sequence(&mut self, sequence: &mut SequenceHandle) {
+        stop_sequence(self, sequence);
+    }
+}
+
+#[track_caller]
+pub(in crate::app) fn play_sequence<'a, S: 'a, H: SequenceHost<'a, S> + 'a>(
+    host: &mut H,
+    clock: Clock,
+    steps: VecDeque<SequenceStep<H::Run, H::Predicate>>
+) -> SequenceHandle {
+    let status = SequenceHandle(Rc::new(RefCell::new(SequenceStatus {
+        task: Task::default(),
+        running: true,
+    })));
+
+    let runner = Rc::new(RefCell::new(SequenceRunner {
+        steps,
+        clock,
+        status: status.clone(),
+    }));
+
+    let (task_system, time_system) = host.task_systems();
+    let task = task_system.schedule_in_frames(time_system, 1, false, move |_, state, host: &mut H| {
+        resume_sequence(runner.clone(), state, host);
+    });
+    task_system.set_label(&task, "sequence");
+    status.0.borrow_mut().task = task;
+
+    status
+}
+
+pub(in crate::app) fn stop_sequence<'a, S, H: TaskHost<'a, S>>(host: &mut H, sequence: &mut SequenceHandle) {
+    let mut status = sequence.0.borrow_mut();
+    if !status.running {
+        return;
+    }
+
+    status.running = false;
+    let (task_system, _) = host.task_systems();
+    if task_system.is_scheduled(&status.task) {
+        status.task.cancel(task_system);
+    }
+}
+
+fn resume_sequence<'a, S: 'a, H: SequenceHost<'a, S> + 'a>(
+    runner: Rc<RefCell<SequenceRunner<H::Run, H::Predicate>>>,
+    state: &mut S,
+    host: &mut H
+) {
+    let mut runner_mut = runner.borrow_mut();
+
+    loop {
+        // A step may have stopped the sequence
+        if !runner_mut.status.is_running() {
+            return;
+        }
+
+        let step = match runner_mut.steps.pop_front() {
+            Some(step) => step,
+            None => {
+                runner_mut.status.0.borrow_mut().running = false;
+                return;
+            }
+        };
+
+        let clock = runner_mut.clock;
+        let (delay, frames) = match step {
+            SequenceStep::Run(mut callback) => {
+                host.run_step(&mut callback, state);
+                continue;
+            },
+
+            SequenceStep::Wait(duration) => (Some(duration), 0),
+
+            SequenceStep::WaitFrames(frames) => (None, frames),
+
+            SequenceStep::WaitUntil(mut predicate) => {
+                if host.check_step(&mut predicate, state) {
+                    continue;
+                }
+
+                // Check again on the next frame
+                runner_mut.steps.push_front(SequenceStep::WaitUntil(predicate));
+                (None, 1)
+            },
+        };
+
+        let runner = runner.clone();
+        let resume = move |_, state: &mut S, host: &mut H| {
+            resume_sequence(runner.clone(), state, host);
+        };
+
+        let (task_system, time_system) = host.task_systems();
+        let task = match delay {
+            Some(duration) => task_system.schedule_on(time_system, clock, duration, false, resume),
+            None => task_system.schedule_in_frames(time_system, frames, false, resume),
+        };
+
+        task_system.set_label(&task, "sequence");
+        runner_mut.status.0.borrow_mut().task = task;
+        return;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::headless::{HeadlessApp, HeadlessSequenceFn, HeadlessSequencePredicate};
+
+    #[derive(Default)]
+    struct State {
+        // Step names with the game time (ms) of their executions
+        log: Vec<(&'static str, u64)>,
+        ready: bool,
+        checks: u32,
+        sequence: SequenceHandle,
+    }
+
+    type Step = SequenceStep<HeadlessSequenceFn<State>, HeadlessSequencePredicate<State>>;
+
+    fn run(name: &'static str) -> Step {
+        SequenceStep::Run(Box::new(move |state, app| {
+            state.log.push((name, app.time_system.game_time().as_micros() / 1_000));
+        }))
+    }
+
+    fn play(app: &mut HeadlessApp<State>, state: &mut State, clock: Clock, steps: Vec<Step>) {
+        state.sequence = play_sequence(app, clock, steps.into());
+    }
+
+    #[test]
+    fn test_sequence_run_steps() {
+        let mut app = HeadlessApp::new();
+        let mut state = State::default();
+
+        play(&mut app, &mut state, Clock::GAME, vec![run("a"), run("b"), SequenceStep::WaitFrames(1), run("c")]);
+        assert!(state.log.is_empty());
+
+        // Run steps chain in the same frame
+        app.frame(&mut state);
+        assert_eq!(state.log, [("a", 16), ("b", 16)]);
+        assert!(state.sequence.is_running());
+
+        app.frame(&mut state);
+        assert_eq!(state.log[2..], [("c", 32)]);
+        assert!(!state.sequence.is_running());
+    }
+
+    #[test]
+    fn test_sequence_wait() {
+        let mut app = HeadlessApp::new();
+        let mut state = State::default();
+
+        // 8ms per frame
+        let slow = app.time_system.create_clock("slow");
+        app.time_system.set_clock_scale(slow, 0.5);
+
+        play(&mut app, &mut state, slow, vec![run("a"), SequenceStep::Wait(Duration::from_millis(20)), run("b")]);
+
+        for _ in 0..4 {
+            app.frame(&mut state);
+        }
+        assert_eq!(state.log, [("a", 16), ("b", 64)]);
+        assert!(!state.sequence.is_running());
+    }
+
+    #[test]
+    fn test_sequence_wait_frames() {
+        let mut app = HeadlessApp::new();
+        let mut state = State::default();
+
+        play(&mut app, &mut state, Clock::GAME, vec![
+            run("a"), SequenceStep::WaitFrames(3), run("b"), SequenceStep::WaitFrames(0), run("c"),
+        ]);
+
+        for _ in 0..5 {
+            app.frame(&mut state);
+        }
+        assert_eq!(state.log, [("a", 16), ("b", 64), ("c", 80)]);
+    }
+
+    #[test]
+    fn test_sequence_wait_until() {
+        let mut app = HeadlessApp::new();
+        let mut state = State::default();
+
+        let predicate: HeadlessSequencePredicate<State> = Box::new(|state, _| {
+            state.checks += 1;
+            state.ready
+        });
+        play(&mut app, &mut state, Clock::GAME, vec![run("a"), SequenceStep::WaitUntil(predicate), run("b")]);
+
+        // Checked right after the previous step, then once per frame
+        for _ in 0..3 {
+            app.frame(&mut state);
+        }
+        assert_eq!(state.checks, 3);
+        assert_eq!(state.log, [("a", 16)]);
+
+        state.ready = true;
+        app.frame(&mut state);
+        assert_eq!(state.checks, 4);
+        assert_eq!(state.log[1..], [("b", 64)]);
+        assert!(!state.sequence.is_running());
+    }
+
+    #[test]
+    fn test_sequence_stop() {
+        let mut app = HeadlessApp::new();
+        let mut state = State::default();
+
+        let stop: Step = SequenceStep::Run(Box::new(|state, app| {
+            let mut sequence = state.sequence.clone();
+            stop_sequence(app, &mut sequence);
+        }));
+        play(&mut app, &mut state, Clock::GAME, vec![run("a"), stop, run("b"), SequenceStep::WaitFrames(1), run("c")]);
+
+        // Stopped from a Run step: the next steps don't run
+        app.frame(&mut state);
+        app.frame(&mut state);
+        assert_eq!(state.log, [("a", 16)]);
+        assert!(!state.sequence.is_running());
+
+        // Stopped while waiting: the resume task is cancelled
+        play(&mut app, &mut state, Clock::GAME, vec![run("d"), SequenceStep::WaitFrames(1), run("e")]);
+        app.frame(&mut state);
+        let task = state.sequence.0.borrow().task;
+        assert!(app.task_system.is_scheduled(&task));
+
+        let mut sequence = state.sequence.clone();
+        stop_sequence(&mut app, &mut sequence);
+        assert!(!app.task_system.is_scheduled(&task));
+
+        app.frame(&mut state);
+        assert_eq!(state.log[1..], [("d", 48)]);
+    }
+}
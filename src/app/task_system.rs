@@ -1,7 +1,7 @@
 // Task system
 
-// [ ] Refactor to timer wheel
-// [ ] Add next frame scheduling
+// [x] Refactor to timer wheel
+// [x] Add next frame scheduling
 
 ///
 /// // construction
@@ -10,93 +10,488 @@
 ///
 /// // scheduling
 ///
-/// let task = app.schedule_task(1_000_000, |id, _state, app| {
-///    println!("task {} {}", id, app.game_time());
+/// let task = app.schedule_task(Duration::from_secs(1), |task, _state, app| {
+///    println!("task {:?} {:?}", task, app.game_time());
 /// });
 ///
+/// // scheduling on a specific clock (not affected by pausing the game clock)
+///
+/// let ui_clock = app.create_clock("ui");
+/// let task = app.schedule_task_on(ui_clock, Duration::from_millis(500), |_, _, _| {});
+///
+/// // scheduling in frames (1 = next frame)
+///
+/// let task = app.schedule_task_in_frames(1, |_, _, _| {});
+///
+/// // repeating
+///
+/// let task = app.schedule_repeating_task(Duration::from_millis(100), |mut task, _state, app| {
+///    if done { app.cancel_task(&mut task); }
+/// });
+///
+/// // pause/resume (keeps the remaining time)
+///
+/// app.pause_task(&task);
+/// app.resume_task(&task);
+///
+/// // debug label (shown in the tasks debug window with the origin of the task)
+///
+/// app.set_task_label(&task, "countdown");
+///
 /// // cancel
 ///
 /// app.cancel_task(&mut task);
 ///
 
-use std::collections::{BinaryHeap, HashSet};
-use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::panic::Location;
 
 use crate::app::{
     App,
-    time_system::TimeSystem,
+    time_system::{Clock, Duration, GameTime, TimeSystem},
     imgui::ImDraw,
+    utils::timer_wheel::TimerWheel,
 };
 
 //#[feature(trait_alias)]
-//trait TaskFn<S> = FnMut(u64, &mut S, &mut App<S>);
+//trait TaskFn<S> = FnMut(Task, &mut S, &mut App<S>);
 
-pub(in crate::app) struct TaskSystem<'a, S> {
+// Time resolution of the clocks timer wheels. Tasks are still executed in their exact time order
+const TASK_WHEEL_TICK: Duration = Duration::from_millis(1);
+
+type TaskCallback<'a, S, H> = Rc<RefCell<dyn FnMut(Task, &mut S, &mut H) + 'a>>;
+
+// Gets passed to the task callbacks: the app, or a headless app in the tests
+pub(in crate::app) trait TaskHost<'a, S>: Sized {
+    fn task_systems(&mut self) -> (&mut TaskSystem<'a, S, Self>, &TimeSystem);
+}
+
+impl<'a, S> TaskHost<'a, S> for App<'a, S> {
+    fn task_systems(&mut self) -> (&mut TaskSystem<'a, S>, &TimeSystem) {
+        (&mut self.task_system, &self.time_system)
+    }
+}
+
+pub(in crate::app) struct TaskSystem<'a, S, H = App<'a, S>> {
     next_id: u64,
-    // @Refactor don't store whole structure in heap, only (id, execution_time)
-    tasks_scheduled: BinaryHeap<TaskData<'a, S>>,
-    tasks_cancelled: HashSet<u64>,
+    tasks: HashMap<u64, TaskData<'a, S, H>>,
+
+    // One wheel per clock, indexed by the clock id
+    clock_wheels: Vec<TimerWheel<TaskRef>>,
+    frame_wheel: TimerWheel<TaskRef>,
 }
 
-impl<'a, S> TaskSystem<'a, S> {
+impl<'a, S, H> TaskSystem<'a, S, H> {
     pub(in crate::app) fn new() -> Self {
-        let tasks_scheduled = BinaryHeap::new();
-        let tasks_cancelled = HashSet::new();
         Self {
             next_id: 1,
-            tasks_scheduled,
-            tasks_cancelled,
+            tasks: HashMap::new(),
+            clock_wheels: Vec::new(),
+            frame_wheel: TimerWheel::new(0),
+        }
+    }
+
+    fn insert(&mut self, id: u64) {
+        let task = &self.tasks[&id];
+        let task_ref = TaskRef { id, version: task.version };
+
+        match task.execution {
+            TaskExecution::Time { clock, time } => {
+                self.clock_wheels[clock.index()].insert(time_to_tick(time), task_ref);
+            },
+            TaskExecution::Frame(frame) => {
+                self.frame_wheel.insert(frame, task_ref);
+            },
+        }
+    }
+
+    fn is_valid(&self, task_ref: TaskRef) -> bool {
+        match self.tasks.get(&task_ref.id) {
+            Some(task) => task.version == task_ref.version && task.paused.is_none(),
+            None => false,
+        }
+    }
+
+    #[track_caller]
+    pub(in crate::app) fn schedule_on<F>(
+        &mut self,
+        time_system: &TimeSystem,
+        clock: Clock,
+        time_delay: Duration,
+        repeat: bool,
+        callback: F
+    ) -> Task
+        where F: FnMut(Task, &mut S, &mut H) + 'a,
+    {
+        self.schedule(time_system, clock, TaskDelay::Time(time_delay), repeat, callback)
+    }
+
+    #[track_caller]
+    pub(in crate::app) fn schedule_in_frames<F>(
+        &mut self,
+        time_system: &TimeSystem,
+        frames: u32,
+        repeat: bool,
+        callback: F
+    ) -> Task
+        where F: FnMut(Task, &mut S, &mut H) + 'a,
+    {
+        self.schedule(time_system, Clock::GAME, TaskDelay::Frames(frames as u64), repeat, callback)
+    }
+
+    #[track_caller]
+    fn schedule<F>(
+        &mut self,
+        time_system: &TimeSystem,
+        clock: Clock,
+        delay: TaskDelay,
+        repeat: bool,
+        callback: F
+    ) -> Task
+        where
+            F: FnMut(Task, &mut S, &mut H) + 'a,
+            //F: TaskFn<S> + 'a,
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        if let TaskDelay::Time(_) = delay {
+            let clock_index = clock.index();
+            while self.clock_wheels.len() <= clock_index {
+                let index = self.clock_wheels.len();
+                let clock_time = time_system.clocks[index].time;
+                self.clock_wheels.push(TimerWheel::new(time_to_tick(clock_time)));
+            }
         }
+
+        let execution = execution_after(time_system, clock, delay);
+        self.tasks.insert(id, TaskData {
+            execution,
+            interval: if repeat { Some(delay) } else { None },
+            paused: None,
+            version: 0,
+            label: None,
+            origin: Location::caller(),
+            callback: Rc::new(RefCell::new(callback)),
+        });
+        self.insert(id);
+
+        // @TODO logger (with envvar? with argv value?)
+        //println!("scheduled task: {} at {:?}", id, execution);
+
+        Task ( Some(id) )
+    }
+
+    pub(in crate::app) fn set_label(&mut self, task: &Task, label: &str) {
+        let id = task.0.expect("[task_system] trying to label an empty task");
+        if let Some(task_data) = self.tasks.get_mut(&id) {
+            task_data.label = Some(label.to_string());
+        }
+    }
+
+    pub(in crate::app) fn is_scheduled(&self, task: &Task) -> bool {
+        match task.0 {
+            Some(id) => self.tasks.contains_key(&id),
+            None => false,
+        }
+    }
+
+    pub(in crate::app) fn pause(&mut self, task: &Task, time_system: &TimeSystem) {
+        let id = task.0.expect("[task_system] trying to pause an empty task");
+
+        let frame_count = time_system.frame_count as u64;
+
+        if let Some(task_data) = self.tasks.get_mut(&id) {
+            if task_data.paused.is_some() {
+                return;
+            }
+
+            let remaining = match task_data.execution {
+                TaskExecution::Time { clock, time } => {
+                    TaskDelay::Time(time.duration_since(time_system.clock(clock).time))
+                },
+                TaskExecution::Frame(frame) => {
+                    TaskDelay::Frames(frame.saturating_sub(frame_count))
+                },
+            };
+
+            task_data.paused = Some(remaining);
+            task_data.version += 1;
+        }
+    }
+
+    pub(in crate::app) fn resume(&mut self, task: &Task, time_system: &TimeSystem) {
+        let id = task.0.expect("[task_system] trying to resume an empty task");
+
+        let task_data = match self.tasks.get_mut(&id) {
+            Some(task_data) => task_data,
+            None => return,
+        };
+
+        let remaining = match task_data.paused.take() {
+            Some(remaining) => remaining,
+            None => return,
+        };
+
+        let clock = match task_data.execution {
+            TaskExecution::Time { clock, .. } => clock,
+            TaskExecution::Frame(_) => Clock::GAME,
+        };
+
+        task_data.execution = execution_after(time_system, clock, remaining);
+        task_data.version += 1;
+        self.insert(id);
+    }
+
+    pub(in crate::app) fn is_paused(&self, task: &Task) -> bool {
+        match task.0.and_then(|id| self.tasks.get(&id)) {
+            Some(task_data) => task_data.paused.is_some(),
+            None => false,
+        }
+    }
+
+    // Tasks of the clock due at clock_time, in execution order
+    fn due_clock_tasks(&mut self, clock_index: usize, clock_time: GameTime) -> Vec<TaskRef> {
+        let mut task_refs = Vec::new();
+        self.clock_wheels[clock_index].advance(time_to_tick(clock_time), &mut task_refs);
+
+        let mut due = Vec::with_capacity(task_refs.len());
+        for task_ref in task_refs {
+            if !self.is_valid(task_ref) {
+                continue;
+            }
+
+            let time = match self.tasks[&task_ref.id].execution {
+                TaskExecution::Time { time, .. } => time,
+                TaskExecution::Frame(_) => unreachable!(),
+            };
+
+            // Same tick but not due yet. The wheel returns it again on the next advance
+            if time > clock_time {
+                self.clock_wheels[clock_index].insert(time_to_tick(time), task_ref);
+                continue;
+            }
+
+            due.push((time, task_ref));
+        }
+
+        due.sort_by_key(|(time, task_ref)| (*time, task_ref.id));
+        due.into_iter().map(|(_, task_ref)| task_ref).collect()
+    }
+
+    fn due_frame_tasks(&mut self, frame_count: u64) -> Vec<TaskRef> {
+        let mut due = Vec::new();
+        self.frame_wheel.advance(frame_count, &mut due);
+        due.sort_by_key(|task_ref| task_ref.id);
+        due
+    }
+
+    // Callback of a due task. Repeating tasks are rescheduled, the others removed
+    fn start_task(&mut self, task_ref: TaskRef, time_system: &TimeSystem) -> Option<TaskCallback<'a, S, H>> {
+        // A previous task may have cancelled or paused this one
+        if !self.is_valid(task_ref) {
+            return None;
+        }
+
+        let id = task_ref.id;
+
+        let callback = match self.tasks[&id].interval {
+            Some(interval) => {
+                // Reschedule before running, so the callback is able to cancel or pause it
+                let task_data = self.tasks.get_mut(&id).unwrap();
+                task_data.execution = match (task_data.execution, interval) {
+                    (TaskExecution::Time { clock, time }, TaskDelay::Time(interval)) => {
+                        let clock_time = time_system.clock(clock).time;
+                        TaskExecution::Time { clock, time: next_repeat_time(time, interval, clock_time) }
+                    },
+                    (TaskExecution::Frame(frame), TaskDelay::Frames(frames)) => {
+                        TaskExecution::Frame(frame + frames.max(1))
+                    },
+                    _ => unreachable!(),
+                };
+                task_data.version += 1;
+
+                let callback = task_data.callback.clone();
+                self.insert(id);
+                callback
+            },
+            None => self.tasks.remove(&id).unwrap().callback,
+        };
+
+        Some(callback)
     }
 }
 
-// @TODO implement a proper ImDraw
-#[derive(Copy, Clone, Debug, Default, ImDraw)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Task(Option<u64>);
 
+impl ImDraw for Task {
+    fn imdraw(&mut self, label: &str, ui: &imgui::Ui) {
+        match self.0 {
+            Some(id) => ui.text(format!("{}: task {}", label, id)),
+            None => ui.text(format!("{}: (no task)", label)),
+        }
+    }
+}
+
 impl Task {
     // @TODO return Option/Result
-    fn cancel<S>(&mut self, task_system: &mut TaskSystem<S>) {
+    pub(in crate::app) fn cancel<S, H>(&mut self, task_system: &mut TaskSystem<S, H>) {
         let id = self.0.take().expect("Trying to cancel an empty task");
-        task_system.tasks_cancelled.insert(id);
-    }
-}
-
-fn schedule_task<'a, S, F>(
-    task_system: &mut TaskSystem<'a, S>,
-    time_system: &TimeSystem,
-    time_delay: u64,
-    callback: F
-) -> Task
-    where
-        F: FnMut(u64, &mut S, &mut App<S>) + 'a,
-        //F: TaskFn<S> + 'a,
-{
-    let id = task_system.next_id;
-    task_system.next_id += 1;
-
-    let game_time = time_system.game_time;
-    let execution_time = if time_delay == 0 { game_time + 1 } else { game_time + time_delay };
-    task_system.tasks_scheduled.push(TaskData {
-        id,
-        execution_time,
-        callback: Rc::new(RefCell::new(callback)),
-    });
+
+        // Timer wheels entries are ignored when the task is not found
+        task_system.tasks.remove(&id);
+    }
+}
+
+// Delay or interval of a task
+#[derive(Copy, Clone, Debug)]
+enum TaskDelay {
+    Time(Duration),
+    Frames(u64),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum TaskExecution {
+    Time { clock: Clock, time: GameTime },
+    Frame(u64),
+}
+
+fn execution_after(time_system: &TimeSystem, clock: Clock, delay: TaskDelay) -> TaskExecution {
+    match delay {
+        TaskDelay::Time(duration) => {
+            let clock_time = time_system.clock(clock).time;
+            // Zero delay tasks run on the next frame
+            let duration = if duration.is_zero() { Duration::from_micros(1) } else { duration };
+            TaskExecution::Time { clock, time: clock_time + duration }
+        },
+        TaskDelay::Frames(frames) => {
+            let frames = frames.max(1);
+            TaskExecution::Frame(time_system.frame_count as u64 + frames)
+        },
+    }
+}
+
+// Next execution time of a repeating task. The interval is added to the execution time to avoid
+// drifting, skipping the executions missed in a long frame (or with intervals shorter than a frame)
+fn next_repeat_time(time: GameTime, interval: Duration, clock_time: GameTime) -> GameTime {
+    let missed = clock_time.duration_since(time).as_micros() / interval.as_micros();
+    time + Duration::from_micros(interval.as_micros() * (missed + 1))
+}
+
+fn time_to_tick(time: GameTime) -> u64 {
+    time.as_micros() / TASK_WHEEL_TICK.as_micros()
+}
+
+pub(in crate::app) fn run_tasks<'a, S, H: TaskHost<'a, S>>(host: &mut H, state: &mut S) {
+    // Tasks can schedule tasks on new clocks, so we can't iterate over the wheels directly
+    let mut clock_index = 0;
+    loop {
+        let (task_system, time_system) = host.task_systems();
+        if clock_index >= task_system.clock_wheels.len() {
+            break;
+        }
+
+        let clock_time = time_system.clocks[clock_index].time;
+        for task_ref in task_system.due_clock_tasks(clock_index, clock_time) {
+            run_task(host, task_ref, state);
+        }
+        clock_index += 1;
+    }
+
+    let (task_system, time_system) = host.task_systems();
+    for task_ref in task_system.due_frame_tasks(time_system.frame_count as u64) {
+        run_task(host, task_ref, state);
+    }
+}
+
+fn run_task<'a, S, H: TaskHost<'a, S>>(host: &mut H, task_ref: TaskRef, state: &mut S) {
+    let (task_system, time_system) = host.task_systems();
+    let callback = match task_system.start_task(task_ref, time_system) {
+        Some(callback) => callback,
+        None => return,
+    };
 
     // @TODO logger (with envvar? with argv value?)
-    //println!("scheduled task: {} at {}", id, game_time);
+    //println!("executed task: {} at {:?}", task_ref.id, time_system.game_time());
+
+    let mut closure = callback.borrow_mut();
+    closure(Task(Some(task_ref.id)), state, host);
+}
 
-    Task ( Some(id) )
+pub(in crate::app) fn run_task_now<'a, S, H: TaskHost<'a, S>>(host: &mut H, task: &Task, state: &mut S) {
+    let id = task.0.expect("[task_system] trying to run an empty task");
+
+    let (task_system, _) = host.task_systems();
+    let callback = match task_system.tasks.get(&id) {
+        Some(task_data) if task_data.interval.is_some() => task_data.callback.clone(),
+        Some(_) => task_system.tasks.remove(&id).unwrap().callback,
+        None => return,
+    };
+
+    // A repeating task running itself is already borrowed by run_task
+    let mut closure = match callback.try_borrow_mut() {
+        Ok(closure) => closure,
+        Err(_) => {
+            println!("[task_system run_task_now] task {} is already running", id);
+            return;
+        },
+    };
+    closure(Task(Some(id)), state, host);
 }
 
 impl<'a, S> App<'a, S> {
-    // @TODO use a type safe duration type
-    pub fn schedule_task<F>(&mut self, time_delay: u64, callback: F) -> Task
-    where F: FnMut(u64, &mut S, &mut App<S>) + 'a, // @XXX F: TaskFn<S> + 'a,
+    #[track_caller]
+    pub fn schedule_task<F>(&mut self, time_delay: Duration, callback: F) -> Task
+    where F: FnMut(Task, &mut S, &mut App<S>) + 'a, // @XXX F: TaskFn<S> + 'a,
     {
-        schedule_task(&mut self.task_system, &self.time_system, time_delay, callback)
+        self.schedule_task_on(Clock::GAME, time_delay, callback)
+    }
+
+    #[track_caller]
+    pub fn schedule_task_on<F>(&mut self, clock: Clock, time_delay: Duration, callback: F) -> Task
+    where F: FnMut(Task, &mut S, &mut App<S>) + 'a,
+    {
+        self.task_system.schedule_on(&self.time_system, clock, time_delay, false, callback)
+    }
+
+    // Frames are counted from the current frame: 0 or 1 runs the task on the next frame
+    #[track_caller]
+    pub fn schedule_task_in_frames<F>(&mut self, frames: u32, callback: F) -> Task
+    where F: FnMut(Task, &mut S, &mut App<S>) + 'a,
+    {
+        self.task_system.schedule_in_frames(&self.time_system, frames, false, callback)
+    }
+
+    // Runs every interval until cancelled. The first execution is after one interval. Runs at most
+    // once per frame: the executions missed in a frame are skipped
+    #[track_caller]
+    pub fn schedule_repeating_task<F>(&mut self, interval: Duration, callback: F) -> Task
+    where F: FnMut(Task, &mut S, &mut App<S>) + 'a,
+    {
+        self.schedule_repeating_task_on(Clock::GAME, interval, callback)
+    }
+
+    #[track_caller]
+    pub fn schedule_repeating_task_on<F>(&mut self, clock: Clock, interval: Duration, callback: F) -> Task
+    where F: FnMut(Task, &mut S, &mut App<S>) + 'a,
+    {
+        assert!(!interval.is_zero(), "[task_system] repeating task with zero interval");
+
+        self.task_system.schedule_on(&self.time_system, clock, interval, true, callback)
+    }
+
+    #[track_caller]
+    pub fn schedule_repeating_task_in_frames<F>(&mut self, frames: u32, callback: F) -> Task
+    where F: FnMut(Task, &mut S, &mut App<S>) + 'a,
+    {
+        self.task_system.schedule_in_frames(&self.time_system, frames, true, callback)
+    }
+
+    pub fn set_task_label(&mut self, task: &Task, label: &str) {
+        self.task_system.set_label(task, label);
     }
 
     // @TODO return Option/Result
@@ -104,53 +499,306 @@ impl<'a, S> App<'a, S> {
         task.cancel(&mut self.task_system);
     }
 
+    // False if the task was cancelled or already executed (and it's not repeating)
+    pub fn is_task_scheduled(&self, task: &Task) -> bool {
+        self.task_system.is_scheduled(task)
+    }
+
+    // Stops the task countdown, keeping the remaining time (or frames) until its execution
+    pub fn pause_task(&mut self, task: &Task) {
+        self.task_system.pause(task, &self.time_system);
+    }
+
+    pub fn resume_task(&mut self, task: &Task) {
+        self.task_system.resume(task, &self.time_system);
+    }
+
+    pub fn is_task_paused(&self, task: &Task) -> bool {
+        self.task_system.is_paused(task)
+    }
+
     pub fn run_tasks(&mut self, state: &mut S) {
-        let game_time = self.time_system.game_time;
-        while let Some(task) = self.task_system.tasks_scheduled.peek() {
-            if task.execution_time > game_time {
-                break;
-            }
+        run_tasks(self, state);
+    }
 
-            let task = self.task_system.tasks_scheduled.pop().unwrap();
+    // Runs the task immediately. Repeating tasks keep their schedule
+    pub fn run_task_now(&mut self, task: &Task, state: &mut S) {
+        run_task_now(self, task, state);
+    }
 
-            // check if task was cancelled or not
-            if self.task_system.tasks_cancelled.remove(&task.id) {
-                continue;
-            }
+    // Snapshot of all scheduled tasks, sorted by id
+    pub(in crate::app) fn task_debug_infos(&self) -> Vec<TaskDebugInfo> {
+        let frame_count = self.time_system.frame_count as u64;
 
-            // @TODO logger (with envvar? with argv value?)
-            //println!("executed task: {} at {}", task.id, game_time);
+        let mut infos: Vec<TaskDebugInfo> = self.task_system.tasks.iter()
+            .map(|(&id, task_data)| {
+                let remaining = match (task_data.paused, task_data.execution) {
+                    (Some(TaskDelay::Time(remaining)), _) => format!("{:.3}s", remaining.as_secs_f32()),
+                    (Some(TaskDelay::Frames(remaining)), _) => format!("{} frames", remaining),
+                    (None, TaskExecution::Time { clock, time }) => {
+                        let remaining = time.duration_since(self.clock_time(clock));
+                        format!("{:.3}s", remaining.as_secs_f32())
+                    },
+                    (None, TaskExecution::Frame(frame)) => {
+                        format!("{} frames", frame.saturating_sub(frame_count))
+                    },
+                };
 
-            let mut closure = task.callback.borrow_mut();
-            (&mut closure)(task.id, state, self);
-        }
+                let clock = match task_data.execution {
+                    TaskExecution::Time { clock, .. } => self.time_system.clock(clock).name.clone(),
+                    TaskExecution::Frame(_) => "frames".to_string(),
+                };
+
+                TaskDebugInfo {
+                    task: Task(Some(id)),
+                    label: task_data.label.clone(),
+                    origin: task_data.origin,
+                    clock,
+                    remaining,
+                    repeating: task_data.interval.is_some(),
+                    paused: task_data.paused.is_some(),
+                }
+            })
+            .collect();
+
+        infos.sort_by_key(|info| info.task.0);
+        infos
     }
 }
 
-// @Refactor don't store whole structure in heap, only (id, execution_time)
-struct TaskData<'a, S> {
-    id: u64,
-    execution_time: u64,
-    callback: Rc<RefCell<dyn FnMut(u64, &mut S, &mut App<S>) + 'a>>,
+struct TaskData<'a, S, H> {
+    execution: TaskExecution,
+    interval: Option<TaskDelay>,
+
+    // Remaining delay while paused
+    paused: Option<TaskDelay>,
+
+    // Incremented every time the task is rescheduled, to invalidate the old wheel entries
+    version: u32,
+
+    // Debug info
+    label: Option<String>,
+    origin: &'static Location<'static>,
+
+    callback: TaskCallback<'a, S, H>,
     //callback: Rc<RefCell<dyn TaskFn<S> + 'a>>,
 }
 
-impl<'a, S> Ord for TaskData<'a, S> {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.execution_time.cmp(&other.execution_time).then(self.id.cmp(&other.id)).reverse()
-    }
+// Timer wheel entry
+#[derive(Copy, Clone, Debug)]
+struct TaskRef {
+    id: u64,
+    version: u32,
 }
 
-impl<'a, S> PartialOrd for TaskData<'a, S> {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+pub(in crate::app) struct TaskDebugInfo {
+    pub(in crate::app) task: Task,
+    pub(in crate::app) label: Option<String>,
+    pub(in crate::app) origin: &'static Location<'static>,
+    pub(in crate::app) clock: String,
+    pub(in crate::app) remaining: String,
+    pub(in crate::app) repeating: bool,
+    pub(in crate::app) paused: bool,
 }
 
-impl<'a, S> PartialEq for TaskData<'a, S> {
-    fn eq(&self, other: &Self) -> bool {
-        self.id == other.id && self.execution_time == other.execution_time
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::headless::HeadlessApp;
+
+    // Task names with the game time (ms) of their executions
+    type Log = Vec<(&'static str, u64)>;
+
+    fn at(ms: u64) -> GameTime {
+        GameTime::from_micros(ms * 1_000)
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn log_task(name: &'static str) -> impl FnMut(Task, &mut Log, &mut HeadlessApp<Log>) {
+        move |_, log, app| log.push((name, app.time_system.game_time().as_micros() / 1_000))
+    }
+
+    fn schedule(app: &mut HeadlessApp<Log>, clock: Clock, delay: Duration, name: &'static str) -> Task {
+        app.task_system.schedule_on(&app.time_system, clock, delay, false, log_task(name))
+    }
+
+    #[test]
+    fn test_task_order() {
+        let mut app = HeadlessApp::new();
+        let mut log = Log::new();
+
+        let a = schedule(&mut app, Clock::GAME, ms(30), "a");
+        schedule(&mut app, Clock::GAME, ms(10), "b");
+        schedule(&mut app, Clock::GAME, ms(10), "c");
+        schedule(&mut app, Clock::GAME, ms(20), "d");
+        schedule(&mut app, Clock::GAME, Duration::ZERO, "e");
+
+        // 16ms frames: same time tasks in scheduling order
+        app.frame(&mut log);
+        assert_eq!(log, [("e", 16), ("b", 16), ("c", 16)]);
+        assert!(app.task_system.is_scheduled(&a));
+
+        app.frame(&mut log);
+        assert_eq!(log[3..], [("d", 32), ("a", 32)]);
+        assert!(!app.task_system.is_scheduled(&a));
+
+        app.frame(&mut log);
+        assert_eq!(log.len(), 5);
+    }
+
+    #[test]
+    fn test_task_in_frames() {
+        let mut app = HeadlessApp::new();
+        let mut log = Log::new();
+
+        app.task_system.schedule_in_frames(&app.time_system, 3, false, log_task("3"));
+        app.task_system.schedule_in_frames(&app.time_system, 0, false, log_task("0"));
+        app.task_system.schedule_in_frames(&app.time_system, 1, false, log_task("1"));
+        app.task_system.schedule_in_frames(&app.time_system, 2, true, log_task("every 2"));
+
+        for _ in 0..5 {
+            app.frame(&mut log);
+        }
+        assert_eq!(log, [("0", 16), ("1", 16), ("every 2", 32), ("3", 48), ("every 2", 64)]);
+    }
+
+    #[test]
+    fn test_task_pause_resume() {
+        let mut app = HeadlessApp::new();
+        let mut log = Log::new();
+
+        let task = schedule(&mut app, Clock::GAME, ms(50), "time");
+        let frames_task = app.task_system.schedule_in_frames(&app.time_system, 2, false, log_task("frames"));
+
+        // 34ms and 1 frame remaining
+        app.frame(&mut log);
+        app.task_system.pause(&task, &app.time_system);
+        app.task_system.pause(&frames_task, &app.time_system);
+        assert!(app.task_system.is_paused(&task));
+
+        for _ in 0..5 {
+            app.frame(&mut log);
+        }
+        assert!(log.is_empty());
+
+        // 96ms + 34ms
+        app.task_system.resume(&task, &app.time_system);
+        app.task_system.resume(&frames_task, &app.time_system);
+        assert!(!app.task_system.is_paused(&task));
+
+        app.frame(&mut log);
+        app.frame(&mut log);
+        assert_eq!(log, [("frames", 112)]);
+
+        app.frame(&mut log);
+        assert_eq!(log[1..], [("time", 144)]);
     }
-}
 
-impl<'a, S> Eq for TaskData<'a, S> {}
+    #[test]
+    fn test_task_stale_entries() {
+        let mut app = HeadlessApp::new();
+        let mut log = Log::new();
+
+        let rescheduled = schedule(&mut app, Clock::GAME, ms(20), "rescheduled");
+        let mut cancelled = schedule(&mut app, Clock::GAME, ms(20), "cancelled");
+        let repeating = app.task_system.schedule_on(&app.time_system, Clock::GAME, ms(16), true,
+            |mut task, log: &mut Log, app: &mut HeadlessApp<Log>| {
+                log.push(("repeating", app.time_system.game_time().as_micros() / 1_000));
+                if log.len() == 2 {
+                    task.cancel(&mut app.task_system);
+                }
+            });
+
+        // The wheel entries at 20ms are left behind, and skipped by their old version (or missing task)
+        app.frame(&mut log);
+        app.task_system.pause(&rescheduled, &app.time_system);
+        cancelled.cancel(&mut app.task_system);
+
+        app.frame(&mut log);
+        app.task_system.resume(&rescheduled, &app.time_system);
+        app.task_system.pause(&rescheduled, &app.time_system);
+        app.task_system.resume(&rescheduled, &app.time_system);
+
+        for _ in 0..4 {
+            app.frame(&mut log);
+        }
+        assert_eq!(log, [("repeating", 16), ("repeating", 32), ("rescheduled", 48)]);
+        assert!(!app.task_system.is_scheduled(&repeating));
+    }
+
+    #[test]
+    fn test_run_task_now() {
+        let mut app = HeadlessApp::new();
+        let mut log = Log::new();
+
+        let task = schedule(&mut app, Clock::GAME, ms(20), "once");
+        let repeating = app.task_system.schedule_on(&app.time_system, Clock::GAME, ms(16), true,
+            |task, log: &mut Log, app: &mut HeadlessApp<Log>| {
+                log.push(("repeating", app.time_system.game_time().as_micros() / 1_000));
+                // skipped, the task is already running
+                run_task_now(app, &task, log);
+            });
+
+        run_task_now(&mut app, &task, &mut log);
+        run_task_now(&mut app, &repeating, &mut log);
+        assert_eq!(log, [("once", 0), ("repeating", 0)]);
+        assert!(!app.task_system.is_scheduled(&task));
+
+        // The repeating task keeps its schedule
+        app.frame(&mut log);
+        app.frame(&mut log);
+        assert_eq!(log[2..], [("repeating", 16), ("repeating", 32)]);
+    }
+
+    #[test]
+    fn test_task_clocks() {
+        let mut app = HeadlessApp::new();
+        let mut log = Log::new();
+
+        let slow = app.time_system.create_clock("slow");
+        app.time_system.set_clock_scale(slow, 0.5);
+        let paused = app.time_system.create_clock("paused");
+        app.time_system.clock_mut(paused).pause();
+
+        // slow: 8ms per frame
+        schedule(&mut app, slow, ms(20), "slow");
+        schedule(&mut app, paused, ms(10), "paused");
+
+        for _ in 0..3 {
+            app.frame(&mut log);
+        }
+        assert_eq!(log, [("slow", 48)]);
+
+        app.time_system.clock_mut(paused).resume();
+        app.frame(&mut log);
+        assert_eq!(log[1..], [("paused", 64)]);
+    }
+
+    #[test]
+    fn test_repeating_task_catch_up() {
+        let interval = Duration::from_millis(5);
+
+        // 16ms frames: runs once per frame, the next execution is in the next frame and keeps the phase
+        let mut time = at(5);
+        let mut executions = Vec::new();
+        for frame in 1..=4 {
+            let clock_time = at(frame * 16);
+            if time <= clock_time {
+                executions.push(time.as_micros() / 1_000);
+                time = next_repeat_time(time, interval, clock_time);
+            }
+            assert!(time > clock_time);
+        }
+        assert_eq!(executions, [5, 20, 35, 50]);
+        assert_eq!(time, at(65));
+
+        // on time: just the interval
+        assert_eq!(next_repeat_time(at(100), interval, at(100)), at(105));
+
+        // long frame
+        assert_eq!(next_repeat_time(at(100), interval, at(1_000)), at(1_005));
+    }
+}
@@ -1,86 +1,786 @@
 // Time System
 
-// [ ] rename to TimeSystem
+// [x] rename to TimeSystem
 // [ ] maybe use std::time instead of SDL timer_subsystem
-// [ ] create newtype for duration intervals
+// [x] create newtype for duration intervals
 // [ ] clone timer_subsystem instead of referencing it
+// [x] multiple clocks
 
 // @Refactor maybe use std::time?
-// @Refactor create a type to hold the USec, MSec, Sec (different types to be type checked)
 
-use crate::app::App;
+/* Usage
+
+let delay = Duration::from_millis(250);
+let task = app.schedule_task(delay, |_, _, _| {});
+
+let now: GameTime = app.game_time();
+let elapsed: Duration = now - start_time;
+
+if elapsed >= Duration::from_secs(1) { println!("{}s", elapsed.as_secs_f32()); }
+
+// clocks
+
+let gameplay_clock = app.create_clock("gameplay");
+app.schedule_task_on(gameplay_clock, Duration::from_secs(1), |_, _, _| {});
+
+app.pause_clock(gameplay_clock); // menu animations (on Clock::GAME) keep playing
+app.resume_clock(gameplay_clock);
+*/
+
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+
+use crate::app::{
+    App,
+    imgui::ImDraw,
+};
 
 pub(in crate::app) struct TimeSystem {
     pub(in crate::app) frame_count: u32,
-    pub(in crate::app) real_time: u64,
-    pub(in crate::app) real_frame_duration: u64,
-    pub(in crate::app) game_time: u64,
-    pub(in crate::app) game_frame_duration: u64,
-    pub(in crate::app) scale: f64,
+    pub(in crate::app) real_time: RealTime,
+    pub(in crate::app) real_frame_duration: Duration,
+    pub(in crate::app) clocks: Vec<ClockData>,
+    pub(in crate::app) fixed_timestep: Option<FixedTimestepState>,
+    pub(in crate::app) frame_stats: FrameStats,
 
-    last_time: u64,
-    last_scale: f64,
+    last_time: RealTime,
+    // SDL ticks (milliseconds) when the current frame started, to place the event timestamps
+    last_ticks: u32,
 }
 
 impl TimeSystem {
-    pub(in crate::app) fn new(timer_subsystem: sdl2::TimerSubsystem) -> Self {
+    pub(in crate::app) fn new(mut timer_subsystem: sdl2::TimerSubsystem) -> Self {
         Self {
             frame_count: 0,
-            real_time: 0,
-            real_frame_duration: 0,
-            game_time: 0,
-            game_frame_duration: 0,
-            scale: 1.0,
+            real_time: RealTime::default(),
+            real_frame_duration: Duration::default(),
+            clocks: vec![ClockData::new(Clock::GAME, "game".to_string())],
+            fixed_timestep: None,
+            frame_stats: FrameStats::new(FRAME_STATS_HISTORY),
             last_time: system_time(&timer_subsystem),
-            last_scale: 1.0,
+            last_ticks: timer_subsystem.ticks(),
         }
     }
+
+    #[cfg(test)]
+    pub(in crate::app) fn headless() -> Self {
+        Self {
+            frame_count: 0,
+            real_time: RealTime::default(),
+            real_frame_duration: Duration::default(),
+            clocks: vec![ClockData::new(Clock::GAME, "game".to_string())],
+            fixed_timestep: None,
+            frame_stats: FrameStats::new(FRAME_STATS_HISTORY),
+            last_time: RealTime::default(),
+            last_ticks: 0,
+        }
+    }
+
+    // Starts a frame at the system time current_time (current_ticks in SDL ticks), advancing the
+    // clocks by the real time since the previous frame
+    pub(in crate::app) fn new_frame(&mut self, current_time: RealTime, current_ticks: u32) {
+        self.frame_count += 1;
+
+        self.last_ticks = current_ticks;
+        self.real_frame_duration = current_time - self.last_time;
+        self.real_time += self.real_frame_duration;
+        self.last_time = current_time;
+        self.frame_stats.push(self.real_frame_duration);
+
+        let real_frame_duration = self.real_frame_duration;
+        for clock in self.clocks.iter_mut() {
+            clock.advance(real_frame_duration);
+        }
+    }
+
+    pub(in crate::app) fn create_clock(&mut self, name: &str) -> Clock {
+        if self.clocks.iter().any(|clock| clock.name == name) {
+            panic!("[time_system create_clock] trying to create repeated clock: {}", name);
+        }
+
+        let id = self.clocks.len() as u64;
+        let clock = Clock(id);
+
+        self.clocks.push(ClockData::new(clock, name.to_string()));
+        clock
+    }
+
+    pub(in crate::app) fn set_clock_scale(&mut self, clock: Clock, scale: f64) {
+        assert!(scale >= 0.0);
+        self.clock_mut(clock).scale = scale;
+    }
+
+    pub(in crate::app) fn game_time(&self) -> GameTime {
+        self.clocks[Clock::GAME.index()].time
+    }
+
+    pub(in crate::app) fn game_frame_duration(&self) -> Duration {
+        self.clocks[Clock::GAME.index()].frame_duration
+    }
+
+    pub(in crate::app) fn clock(&self, clock: Clock) -> &ClockData {
+        &self.clocks[clock.index()]
+    }
+
+    pub(in crate::app) fn clock_mut(&mut self, clock: Clock) -> &mut ClockData {
+        &mut self.clocks[clock.index()]
+    }
+
+    // System time when the current frame started
+    pub(in crate::app) fn frame_start_time(&self) -> RealTime {
+        self.last_time
+    }
+
+    // Game time of an SDL event timestamp (milliseconds since SDL init). Events are received
+    // between the previous frame start and the current one, so they are placed inside the last
+    // game frame instead of all of them getting the frame time
+    pub(in crate::app) fn event_game_time(&self, event_ticks: u32) -> GameTime {
+        // Events received after the frame started get the frame time
+        let real_age = Duration::from_millis(self.last_ticks.saturating_sub(event_ticks) as u64);
+        self.clock(Clock::GAME).time_before(real_age, self.real_frame_duration)
+    }
+
+    // Real time between a game time and the current frame start (the inverse of event_game_time)
+    pub(in crate::app) fn real_age(&self, game_time: GameTime) -> Duration {
+        self.clock(Clock::GAME).real_age(game_time, self.real_frame_duration)
+    }
 }
 
 impl<S> App<'_, S> {
     pub fn new_frame(&mut self) {
-        let time_system = &mut self.time_system;
-
-        time_system.frame_count += 1;
-
         let current_time = system_time(&self.sdl_context.timer_subsystem);
-        time_system.real_frame_duration = current_time - time_system.last_time;
-        time_system.real_time += time_system.real_frame_duration;
-        time_system.last_time = current_time;
-
-        time_system.game_frame_duration =
-            (time_system.scale * (time_system.real_frame_duration as f64)) as u64;
-        time_system.game_time += time_system.game_frame_duration;
+        let current_ticks = self.sdl_context.timer_subsystem.ticks();
+        self.time_system.new_frame(current_time, current_ticks);
     }
 
+    // Pauses the game clock. Pauses stack: it's only resumed after the same number of resumes
     pub fn pause(&mut self) {
-        let time_system = &mut self.time_system;
-        time_system.last_scale = time_system.scale;
-        time_system.scale = 0.0;
+        self.pause_clock(Clock::GAME);
     }
 
     pub fn resume(&mut self) {
-        let time_system = &mut self.time_system;
-        time_system.scale = time_system.last_scale;
+        self.resume_clock(Clock::GAME);
+    }
+
+    pub fn last_frame_duration(&self) -> Duration {
+        self.time_system.game_frame_duration()
+    }
+
+    pub fn last_real_frame_duration(&self) -> Duration {
+        self.time_system.real_frame_duration
+    }
+
+    pub fn game_time(&self) -> GameTime {
+        self.time_system.game_time()
     }
 
-    pub fn last_frame_duration(&self) -> f32 {
-        to_seconds(self.time_system.game_frame_duration)
+    pub fn real_time(&self) -> RealTime {
+        self.time_system.real_time
     }
 
-    pub fn game_time(&self) -> f32 {
-        to_seconds(self.time_system.game_time)
+    // Clocks
+
+    pub fn create_clock(&mut self, name: &str) -> Clock {
+        self.time_system.create_clock(name)
+    }
+
+    pub fn find_clock(&self, name: &str) -> Option<Clock> {
+        self.time_system.clocks.iter()
+            .find(|clock| clock.name == name)
+            .map(|clock| clock.id)
+    }
+
+    pub fn clock_time(&self, clock: Clock) -> GameTime {
+        self.time_system.clock(clock).time
+    }
+
+    pub fn clock_frame_duration(&self, clock: Clock) -> Duration {
+        self.time_system.clock(clock).frame_duration
+    }
+
+    pub fn clock_scale(&self, clock: Clock) -> f64 {
+        self.time_system.clock(clock).scale
+    }
+
+    pub fn set_clock_scale(&mut self, clock: Clock, scale: f64) {
+        self.time_system.set_clock_scale(clock, scale);
+    }
+
+    pub fn pause_clock(&mut self, clock: Clock) {
+        self.time_system.clock_mut(clock).pause();
+    }
+
+    pub fn resume_clock(&mut self, clock: Clock) {
+        self.time_system.clock_mut(clock).resume();
+    }
+
+    pub fn is_clock_paused(&self, clock: Clock) -> bool {
+        self.time_system.clock(clock).is_paused()
+    }
+
+    // Statistics of the last frames real duration (including the frame cap wait)
+    pub fn frame_time_stats(&self) -> FrameTimeStats {
+        self.time_system.frame_stats.stats()
+    }
+
+    // Fixed timestep
+
+    pub fn set_fixed_timestep(&mut self, fixed_timestep: Option<FixedTimestep>) {
+        let game_time = self.time_system.game_time();
+        self.time_system.fixed_timestep =
+            fixed_timestep.map(|settings| FixedTimestepState::new(settings, game_time));
+    }
+
+    pub fn fixed_timestep(&self) -> Option<FixedTimestep> {
+        self.time_system.fixed_timestep.as_ref().map(|state| state.settings)
+    }
+
+    // Duration of each fixed update. Zero if the fixed timestep mode is disabled
+    pub fn fixed_delta(&self) -> Duration {
+        match &self.time_system.fixed_timestep {
+            Some(state) => state.settings.tick_duration,
+            None => Duration::ZERO,
+        }
+    }
+
+    // Game time of the current fixed update. Equals game_time if the fixed timestep mode is
+    // disabled
+    pub fn fixed_time(&self) -> GameTime {
+        match &self.time_system.fixed_timestep {
+            Some(state) => state.time,
+            None => self.time_system.game_time(),
+        }
+    }
+
+    // How far, in [0, 1), the current frame is between the last fixed update and the next one.
+    // Use it in render to interpolate between the previous and current fixed update states
+    pub fn interpolation_alpha(&self) -> f32 {
+        match &self.time_system.fixed_timestep {
+            Some(state) => state.alpha,
+            None => 1.0,
+        }
+    }
+
+    // Returns how many fixed updates should run this frame
+    pub(in crate::app) fn advance_fixed_timestep(&mut self) -> u32 {
+        let game_frame_duration = self.time_system.game_frame_duration();
+        match &mut self.time_system.fixed_timestep {
+            Some(state) => state.advance(game_frame_duration),
+            None => 0,
+        }
+    }
+
+    pub(in crate::app) fn step_fixed_timestep(&mut self) {
+        if let Some(state) = &mut self.time_system.fixed_timestep {
+            state.step();
+        }
     }
 }
 
-fn system_time(timer_subsystem: &sdl2::TimerSubsystem) -> u64 {
+pub(in crate::app) fn system_time(timer_subsystem: &sdl2::TimerSubsystem) -> RealTime {
     let counter = timer_subsystem.performance_counter() as u128;
     let frequency = timer_subsystem.performance_frequency() as u128;
 
-    (counter * 1_000_000 / frequency) as u64
+    RealTime((counter * 1_000_000 / frequency) as u64)
+}
+
+// Same as system_time, for threads without the timer subsystem (the audio callback)
+pub(in crate::app) fn thread_system_time() -> RealTime {
+    let counter = unsafe { sdl2::sys::SDL_GetPerformanceCounter() } as u128;
+    let frequency = unsafe { sdl2::sys::SDL_GetPerformanceFrequency() } as u128;
+
+    RealTime((counter * 1_000_000 / frequency) as u64)
+}
+
+// ------
+// Clocks
+// ------
+
+// Each clock has its own time, scale and pause state. All clocks advance with the real time.
+// Clock::GAME is the default clock used by game_time, input timestamps and tasks/animators not
+// bound to a specific clock
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, ImDraw)]
+pub struct Clock(u64);
+
+impl Clock {
+    pub const GAME: Clock = Clock(0);
+
+    pub(in crate::app) fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+pub(in crate::app) struct ClockData {
+    pub(in crate::app) id: Clock,
+    pub(in crate::app) name: String,
+    pub(in crate::app) time: GameTime,
+    pub(in crate::app) frame_duration: Duration,
+
+    scale: f64,
+    pause_count: u32,
+}
+
+impl ClockData {
+    fn new(id: Clock, name: String) -> Self {
+        Self {
+            id,
+            name,
+            time: GameTime::default(),
+            frame_duration: Duration::ZERO,
+            scale: 1.0,
+            pause_count: 0,
+        }
+    }
+
+    fn advance(&mut self, real_frame_duration: Duration) {
+        self.frame_duration = if self.is_paused() {
+            Duration::ZERO
+        } else {
+            real_frame_duration.mul_f64(self.scale)
+        };
+        self.time += self.frame_duration;
+    }
+
+    // Clock time real_age before the current frame time, clamped to the last frame
+    fn time_before(&self, real_age: Duration, real_frame_duration: Duration) -> GameTime {
+        if real_frame_duration.is_zero() {
+            return self.time;
+        }
+
+        let real_age = real_age.min(real_frame_duration);
+        let age = real_age.as_micros() as u128 * self.frame_duration.as_micros() as u128
+            / real_frame_duration.as_micros() as u128;
+
+        self.time - Duration::from_micros(age as u64)
+    }
+
+    // Real time since a clock time, with the scale of the last frame. Times in a paused frame are
+    // all the frame time
+    fn real_age(&self, time: GameTime, real_frame_duration: Duration) -> Duration {
+        if self.frame_duration.is_zero() {
+            return Duration::ZERO;
+        }
+
+        let age = self.time.duration_since(time);
+        let real_age = age.as_micros() as u128 * real_frame_duration.as_micros() as u128
+            / self.frame_duration.as_micros() as u128;
+
+        Duration::from_micros(real_age as u64)
+    }
+
+    pub(in crate::app) fn pause(&mut self) {
+        self.pause_count += 1;
+    }
+
+    pub(in crate::app) fn resume(&mut self) {
+        assert!(self.pause_count > 0, "[time_system clock] trying to resume a clock not paused");
+        self.pause_count -= 1;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.pause_count > 0
+    }
+}
+
+// --------------
+// Fixed timestep
+// --------------
+
+#[derive(Copy, Clone, Debug, ImDraw)]
+pub struct FixedTimestep {
+    pub tick_duration: Duration,
+
+    // Max fixed updates per frame. If the game can't keep up, the remaining time is dropped to
+    // avoid spiraling (each frame taking longer to catch up than the last)
+    pub max_steps: u32,
 }
 
-// @Refactor use types for time/duration
-fn to_seconds(usecs: u64) -> f32 {
-    usecs as f32 / 1_000_000.
+impl FixedTimestep {
+    pub fn from_tick_rate(ticks_per_second: u32, max_steps: u32) -> Self {
+        assert!(ticks_per_second > 0);
+        assert!(max_steps > 0);
+
+        Self {
+            tick_duration: Duration::from_micros(1_000_000 / ticks_per_second as u64),
+            max_steps,
+        }
+    }
+}
+
+pub(in crate::app) struct FixedTimestepState {
+    settings: FixedTimestep,
+    accumulator: Duration,
+    time: GameTime,
+    alpha: f32,
+}
+
+impl FixedTimestepState {
+    fn new(settings: FixedTimestep, time: GameTime) -> Self {
+        assert!(!settings.tick_duration.is_zero());
+
+        Self {
+            settings,
+            accumulator: Duration::ZERO,
+            time,
+            alpha: 0.0,
+        }
+    }
+
+    fn advance(&mut self, frame_duration: Duration) -> u32 {
+        let tick = self.settings.tick_duration;
+
+        self.accumulator += frame_duration;
+
+        let available = (self.accumulator.as_micros() / tick.as_micros()) as u32;
+        let steps = available.min(self.settings.max_steps);
+
+        if available > steps {
+            // Drop the time we can't catch up with, keeping the partial tick
+            self.accumulator = Duration::from_micros(self.accumulator.as_micros() % tick.as_micros());
+        } else {
+            self.accumulator -= Duration::from_micros(steps as u64 * tick.as_micros());
+        }
+
+        self.alpha = self.accumulator.as_micros() as f32 / tick.as_micros() as f32;
+
+        steps
+    }
+
+    fn step(&mut self) {
+        self.time += self.settings.tick_duration;
+    }
+}
+
+// -----------
+// Frame stats
+// -----------
+
+const FRAME_STATS_HISTORY: usize = 240;
+
+#[derive(Copy, Clone, Debug, Default, ImDraw)]
+pub struct FrameTimeStats {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+}
+
+// Ring buffer with the last frame durations
+pub(in crate::app) struct FrameStats {
+    frame_durations: Vec<Duration>,
+    next: usize,
+    capacity: usize,
+}
+
+impl FrameStats {
+    fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+
+        Self {
+            frame_durations: Vec::with_capacity(capacity),
+            next: 0,
+            capacity,
+        }
+    }
+
+    fn push(&mut self, duration: Duration) {
+        if self.frame_durations.len() < self.capacity {
+            self.frame_durations.push(duration);
+        } else {
+            self.frame_durations[self.next] = duration;
+        }
+        self.next = (self.next + 1) % self.capacity;
+    }
+
+    fn stats(&self) -> FrameTimeStats {
+        if self.frame_durations.is_empty() {
+            return FrameTimeStats::default();
+        }
+
+        let mut sorted = self.frame_durations.clone();
+        sorted.sort();
+
+        // Nearest-rank percentile
+        let percentile = |p: u64| {
+            let rank = (p as usize * sorted.len()).div_ceil(100);
+            sorted[rank.max(1) - 1]
+        };
+
+        let total: u64 = sorted.iter().map(|d| d.as_micros()).sum();
+
+        FrameTimeStats {
+            min: sorted[0],
+            avg: Duration::from_micros(total / sorted.len() as u64),
+            max: sorted[sorted.len() - 1],
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+        }
+    }
+}
+
+// -----
+// Types
+// -----
+
+// All time values are stored in microseconds. Game time and real time are different types so we
+// can't mix them by accident: game time is affected by the time scale (pause/resume), real time
+// isn't.
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, ImDraw)]
+pub struct Duration(u64);
+
+impl Duration {
+    pub const ZERO: Duration = Duration(0);
+
+    pub const fn from_micros(usecs: u64) -> Self { Self(usecs) }
+    pub const fn from_millis(msecs: u64) -> Self { Self(msecs * 1_000) }
+    pub const fn from_secs(secs: u64)    -> Self { Self(secs * 1_000_000) }
+
+    pub fn from_secs_f32(secs: f32) -> Self {
+        assert!(secs >= 0.0);
+        Self((secs as f64 * 1_000_000.) as u64)
+    }
+
+    pub fn from_secs_f64(secs: f64) -> Self {
+        assert!(secs >= 0.0);
+        Self((secs * 1_000_000.) as u64)
+    }
+
+    pub const fn as_micros(self) -> u64 { self.0 }
+    pub const fn as_millis(self) -> u64 { self.0 / 1_000 }
+
+    pub fn as_secs_f32(self) -> f32 { self.0 as f32 / 1_000_000. }
+    pub fn as_secs_f64(self) -> f64 { self.0 as f64 / 1_000_000. }
+
+    pub fn is_zero(self) -> bool { self.0 == 0 }
+
+    pub fn mul_f64(self, rhs: f64) -> Self {
+        assert!(rhs >= 0.0);
+        Self((self.0 as f64 * rhs) as u64)
+    }
+
+    pub fn saturating_sub(self, rhs: Duration) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl Add for Duration {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output { Self(self.0 + rhs.0) }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, rhs: Self) { self.0 += rhs.0; }
+}
+
+impl Sub for Duration {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output { Self(self.0 - rhs.0) }
+}
+
+impl SubAssign for Duration {
+    fn sub_assign(&mut self, rhs: Self) { self.0 -= rhs.0; }
+}
+
+// Instants: a point in time relative to the start of the app. Subtracting two instants of the
+// same type gives a Duration.
+macro_rules! impl_instant {
+    ($type:ident) => {
+        #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, ImDraw)]
+        pub struct $type(u64);
+
+        impl $type {
+            pub const fn from_micros(usecs: u64) -> Self { Self(usecs) }
+            pub const fn as_micros(self) -> u64 { self.0 }
+
+            pub fn as_secs_f32(self) -> f32 { self.0 as f32 / 1_000_000. }
+            pub fn as_secs_f64(self) -> f64 { self.0 as f64 / 1_000_000. }
+
+            // Time elapsed since the start
+            pub const fn elapsed(self) -> Duration { Duration(self.0) }
+
+            pub fn duration_since(self, earlier: Self) -> Duration {
+                Duration(self.0.saturating_sub(earlier.0))
+            }
+        }
+
+        impl Add<Duration> for $type {
+            type Output = Self;
+            fn add(self, rhs: Duration) -> Self::Output { Self(self.0 + rhs.0) }
+        }
+
+        impl AddAssign<Duration> for $type {
+            fn add_assign(&mut self, rhs: Duration) { self.0 += rhs.0; }
+        }
+
+        impl Sub<Duration> for $type {
+            type Output = Self;
+            fn sub(self, rhs: Duration) -> Self::Output { Self(self.0 - rhs.0) }
+        }
+
+        impl SubAssign<Duration> for $type {
+            fn sub_assign(&mut self, rhs: Duration) { self.0 -= rhs.0; }
+        }
+
+        impl Sub for $type {
+            type Output = Duration;
+            fn sub(self, rhs: Self) -> Self::Output { Duration(self.0 - rhs.0) }
+        }
+    };
+}
+
+impl_instant!(GameTime);
+impl_instant!(RealTime);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duration_conversions() {
+        assert_eq!(Duration::from_secs(2), Duration::from_micros(2_000_000));
+        assert_eq!(Duration::from_millis(3), Duration::from_micros(3_000));
+        assert_eq!(Duration::from_secs_f32(0.5), Duration::from_millis(500));
+        assert_eq!(Duration::from_millis(1_500).as_millis(), 1_500);
+        assert_eq!(Duration::from_millis(1_500).as_secs_f32(), 1.5);
+    }
+
+    #[test]
+    fn test_duration_ops() {
+        let d0 = Duration::from_millis(100);
+        let d1 = Duration::from_millis(300);
+
+        assert_eq!(d0 + d1, Duration::from_millis(400));
+        assert_eq!(d1 - d0, Duration::from_millis(200));
+        assert_eq!(d0.saturating_sub(d1), Duration::ZERO);
+        assert_eq!(d1.mul_f64(0.5), Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_clock_scale() {
+        let mut clock = ClockData::new(Clock::GAME, "game".to_string());
+
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(clock.time, GameTime::from_micros(10_000));
+
+        clock.scale = 0.5;
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(clock.frame_duration, Duration::from_millis(5));
+        assert_eq!(clock.time, GameTime::from_micros(15_000));
+    }
+
+    #[test]
+    fn test_clock_pause_stack() {
+        let mut clock = ClockData::new(Clock::GAME, "game".to_string());
+        clock.scale = 2.0;
+
+        clock.pause();
+        clock.pause();
+        clock.resume();
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(clock.frame_duration, Duration::ZERO);
+
+        clock.resume();
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(clock.frame_duration, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_clock_time_before() {
+        let mut clock = ClockData::new(Clock::GAME, "game".to_string());
+        clock.scale = 0.5;
+        clock.advance(Duration::from_millis(20));
+
+        let real_frame_duration = Duration::from_millis(20);
+        assert_eq!(clock.time_before(Duration::ZERO, real_frame_duration), GameTime::from_micros(10_000));
+        assert_eq!(clock.time_before(Duration::from_millis(8), real_frame_duration), GameTime::from_micros(6_000));
+        // older than the last frame
+        assert_eq!(clock.time_before(Duration::from_millis(50), real_frame_duration), GameTime::default());
+
+        clock.pause();
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(clock.time_before(Duration::from_millis(8), real_frame_duration), GameTime::from_micros(10_000));
+    }
+
+    #[test]
+    fn test_clock_real_age() {
+        let mut clock = ClockData::new(Clock::GAME, "game".to_string());
+        clock.scale = 0.5;
+        clock.advance(Duration::from_millis(20));
+
+        let real_frame_duration = Duration::from_millis(20);
+        let time = clock.time_before(Duration::from_millis(8), real_frame_duration);
+        assert_eq!(clock.real_age(time, real_frame_duration), Duration::from_millis(8));
+        assert_eq!(clock.real_age(clock.time, real_frame_duration), Duration::ZERO);
+
+        clock.pause();
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(clock.real_age(time, real_frame_duration), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_fixed_timestep_steps() {
+        let settings = FixedTimestep {
+            tick_duration: Duration::from_millis(10),
+            max_steps: 4,
+        };
+        let mut state = FixedTimestepState::new(settings, GameTime::default());
+
+        assert_eq!(state.advance(Duration::from_millis(5)), 0);
+        assert_eq!(state.alpha, 0.5);
+
+        assert_eq!(state.advance(Duration::from_millis(20)), 2);
+        assert_eq!(state.alpha, 0.5);
+
+        state.step();
+        state.step();
+        assert_eq!(state.time, GameTime::from_micros(20_000));
+    }
+
+    #[test]
+    fn test_fixed_timestep_max_steps() {
+        let settings = FixedTimestep {
+            tick_duration: Duration::from_millis(10),
+            max_steps: 4,
+        };
+        let mut state = FixedTimestepState::new(settings, GameTime::default());
+
+        assert_eq!(state.advance(Duration::from_millis(105)), 4);
+        assert_eq!(state.accumulator, Duration::from_millis(5));
+
+        assert_eq!(state.advance(Duration::from_millis(5)), 1);
+        assert_eq!(state.accumulator, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_frame_stats() {
+        let mut frame_stats = FrameStats::new(4);
+
+        assert_eq!(frame_stats.stats().max, Duration::ZERO);
+
+        // first value is pushed out of the ring buffer
+        for ms in [100, 10, 20, 30, 40].iter() {
+            frame_stats.push(Duration::from_millis(*ms));
+        }
+
+        let stats = frame_stats.stats();
+        assert_eq!(stats.min, Duration::from_millis(10));
+        assert_eq!(stats.avg, Duration::from_millis(25));
+        assert_eq!(stats.max, Duration::from_millis(40));
+        assert_eq!(stats.p50, Duration::from_millis(20));
+        assert_eq!(stats.p99, Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_instant_ops() {
+        let t0 = GameTime::from_micros(1_000);
+        let t1 = t0 + Duration::from_millis(2);
+
+        assert_eq!(t1, GameTime::from_micros(3_000));
+        assert_eq!(t1 - t0, Duration::from_millis(2));
+        assert_eq!(t0.duration_since(t1), Duration::ZERO);
+        assert_eq!(t1 - Duration::from_millis(3), GameTime::default());
+    }
 }
@@ -1,2 +1,3 @@
 pub mod fnv_hasher;
 pub mod string_ref;
+pub mod timer_wheel;
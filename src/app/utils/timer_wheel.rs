@@ -0,0 +1,256 @@
+// Hierarchical timer wheel
+//
+// Stores values to be returned when a tick is reached. Each level has SLOTS slots and each slot of
+// level L covers SLOTS^L ticks. Values are inserted in the highest level that still separates them
+// from the current tick and cascade down to lower levels as the wheel advances, so insertion is
+// O(1) and advancing costs O(1) per tick (plus the cascaded values).
+//
+// The wheel doesn't support removal: the owner should store a key as value and ignore values that
+// are no longer valid (cancelled, rescheduled) when they are returned.
+
+/* Usage
+
+let mut wheel = TimerWheel::new(0);
+wheel.insert(10, "a");
+wheel.insert(100, "b");
+
+let mut due = Vec::new();
+wheel.advance(50, &mut due); // due = ["a"]
+*/
+
+use std::mem;
+
+const SLOT_BITS : u32   = 6;
+const SLOTS     : usize = 1 << SLOT_BITS;
+const SLOT_MASK : u64   = (SLOTS - 1) as u64;
+const LEVELS    : usize = 4;
+
+pub struct TimerWheel<T> {
+    current_tick: u64,
+    levels: Vec<Vec<Vec<(u64, T)>>>,
+    level_lens: [usize; LEVELS],
+
+    // Values already expired when inserted. Returned on the next advance
+    expired: Vec<T>,
+
+    // Values too far in the future to fit in the wheel
+    overflow: Vec<(u64, T)>,
+
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    pub fn new(current_tick: u64) -> Self {
+        let levels = (0..LEVELS)
+            .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+            .collect();
+
+        Self {
+            current_tick,
+            levels,
+            level_lens: [0; LEVELS],
+            expired: Vec::new(),
+            overflow: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, tick: u64, value: T) {
+        self.len += 1;
+        self.insert_entry(tick, value);
+    }
+
+    // Advances the wheel to the tick, pushing all values with tick <= the new current tick
+    pub fn advance(&mut self, tick: u64, due: &mut Vec<T>) {
+        let due_start = due.len();
+
+        due.append(&mut self.expired);
+
+        while self.current_tick < tick {
+            // Nothing to cascade or fire: skip directly to the end
+            if self.len == due.len() - due_start {
+                self.current_tick = tick;
+                break;
+            }
+
+            // Lower levels empty: skip to the tick before the next cascade of the first non-empty
+            // level
+            let empty_levels = self.level_lens.iter().take_while(|&&len| len == 0).count() as u32;
+            if empty_levels > 0 {
+                let boundary = 1u64 << (empty_levels * SLOT_BITS);
+                let next_cascade = (self.current_tick / boundary + 1) * boundary;
+                if next_cascade - 1 > self.current_tick {
+                    self.current_tick = tick.min(next_cascade - 1);
+                    continue;
+                }
+            }
+
+            self.current_tick += 1;
+            self.cascade();
+
+            // Cascaded values due at the current tick
+            due.append(&mut self.expired);
+
+            let slot = (self.current_tick & SLOT_MASK) as usize;
+            self.level_lens[0] -= self.levels[0][slot].len();
+            due.extend(self.levels[0][slot].drain(..).map(|(_, value)| value));
+        }
+
+        self.len -= due.len() - due_start;
+    }
+
+    fn insert_entry(&mut self, tick: u64, value: T) {
+        if tick <= self.current_tick {
+            self.expired.push(value);
+            return;
+        }
+
+        // The level is given by the highest slot group where the tick differs from the current one
+        let diff = tick ^ self.current_tick;
+        let level = ((63 - diff.leading_zeros()) / SLOT_BITS) as usize;
+
+        if level >= LEVELS {
+            self.overflow.push((tick, value));
+            return;
+        }
+
+        let slot = ((tick >> (level as u32 * SLOT_BITS)) & SLOT_MASK) as usize;
+        self.levels[level][slot].push((tick, value));
+        self.level_lens[level] += 1;
+    }
+
+    // Moves the values of the higher levels slots that start at the current tick to lower levels
+    fn cascade(&mut self) {
+        let wheel_bits = LEVELS as u32 * SLOT_BITS;
+        if self.current_tick & ((1 << wheel_bits) - 1) == 0 {
+            let overflow = mem::take(&mut self.overflow);
+            for (tick, value) in overflow {
+                self.insert_entry(tick, value);
+            }
+        }
+
+        for level in (1..LEVELS).rev() {
+            let level_bits = level as u32 * SLOT_BITS;
+            if self.current_tick & ((1 << level_bits) - 1) != 0 {
+                continue;
+            }
+
+            let slot = ((self.current_tick >> level_bits) & SLOT_MASK) as usize;
+            let entries = mem::take(&mut self.levels[level][slot]);
+            self.level_lens[level] -= entries.len();
+            for (tick, value) in entries {
+                self.insert_entry(tick, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advance(wheel: &mut TimerWheel<u64>, tick: u64) -> Vec<u64> {
+        let mut due = Vec::new();
+        wheel.advance(tick, &mut due);
+        due.sort();
+        due
+    }
+
+    #[test]
+    fn test_timer_wheel_fires_in_order() {
+        let mut wheel = TimerWheel::new(0);
+        wheel.insert(3, 3);
+        wheel.insert(1, 1);
+        wheel.insert(2, 2);
+
+        assert_eq!(advance(&mut wheel, 1), vec![1]);
+        assert_eq!(advance(&mut wheel, 5), vec![2, 3]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_cascade() {
+        let mut wheel = TimerWheel::new(10);
+
+        let ticks = [11, 63, 64, 65, 4_095, 4_096, 300_000, 20_000_000];
+        for tick in ticks.iter() {
+            wheel.insert(*tick, *tick);
+        }
+
+        assert_eq!(wheel.len(), ticks.len());
+
+        // advance one tick at a time around the boundaries
+        for tick in ticks.iter() {
+            assert_eq!(advance(&mut wheel, *tick - 1), Vec::<u64>::new());
+            assert_eq!(advance(&mut wheel, *tick), vec![*tick]);
+        }
+
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_expired() {
+        let mut wheel = TimerWheel::new(100);
+        wheel.insert(50, 50);
+        wheel.insert(100, 100);
+
+        assert_eq!(advance(&mut wheel, 100), vec![50, 100]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn test_timer_wheel_skips_when_empty() {
+        let mut wheel = TimerWheel::new(0);
+        assert_eq!(advance(&mut wheel, 1_000_000_000), Vec::<u64>::new());
+        assert_eq!(wheel.current_tick(), 1_000_000_000);
+
+        wheel.insert(1_000_000_500, 1);
+        assert_eq!(advance(&mut wheel, 1_000_000_500), vec![1]);
+    }
+
+    #[test]
+    fn test_timer_wheel_matches_sorted_list() {
+        // Deterministic pseudo random ticks and advance steps
+        let mut seed = 12345u64;
+        let mut random = |max: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % max
+        };
+
+        let mut wheel = TimerWheel::new(0);
+        let mut pending = Vec::new();
+        let mut current = 0;
+
+        for value in 0..2_000 {
+            let tick = current + random(100_000);
+            wheel.insert(tick, value);
+            pending.push((tick, value));
+
+            if value % 10 == 0 {
+                current += random(5_000);
+
+                let mut expected: Vec<u64> = pending.iter()
+                    .filter(|(tick, _)| *tick <= current)
+                    .map(|(_, value)| *value)
+                    .collect();
+                expected.sort();
+                pending.retain(|(tick, _)| *tick > current);
+
+                assert_eq!(advance(&mut wheel, current), expected);
+                assert_eq!(wheel.len(), pending.len());
+            }
+        }
+    }
+}
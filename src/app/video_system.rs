@@ -1,7 +1,34 @@
+use std::time;
+use sdl2::video::SwapInterval;
+
+use crate::app::{
+    App,
+    imgui::ImDraw,
+    time_system::{Duration, system_time},
+};
+
+// Time before the frame deadline where we stop sleeping and start spinning. Sleeping isn't
+// precise enough (the OS scheduler may wake us up a few ms late)
+const FRAME_PACING_SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VSync {
+    Off,
+    On,
+    // Tears when the frame is late instead of waiting for the next vblank (not supported by all
+    // drivers)
+    Adaptive,
+}
+
+impl_imdraw_todo!(VSync);
+
 // @Maybe refactor? Giving public access to be able to mess with window freely
 pub struct VideoSystem {
     pub window: sdl2::video::Window,
     pub(in crate::app) gl_context: sdl2::video::GLContext,
+
+    vsync: VSync,
+    frame_cap: Option<u32>,
 }
 
 impl VideoSystem {
@@ -54,13 +81,87 @@ impl VideoSystem {
             );
         }
 
+        let vsync = match video_subsystem.gl_get_swap_interval() {
+            SwapInterval::Immediate => VSync::Off,
+            SwapInterval::VSync => VSync::On,
+            SwapInterval::LateSwapTearing => VSync::Adaptive,
+        };
+
         Self {
             window,
             gl_context,
+            vsync,
+            frame_cap: None,
         }
     }
 
     pub(in crate::app) fn swap_buffers(&self) {
         self.window.gl_swap_window();
     }
+
+    // If adaptive vsync is not supported, falls back to vsync on and returns the error
+    pub fn set_vsync(&mut self, vsync: VSync) -> Result<(), String> {
+        let video_subsystem = self.window.subsystem();
+
+        let interval = match vsync {
+            VSync::Off => SwapInterval::Immediate,
+            VSync::On => SwapInterval::VSync,
+            VSync::Adaptive => SwapInterval::LateSwapTearing,
+        };
+
+        match video_subsystem.gl_set_swap_interval(interval) {
+            Ok(_) => {
+                self.vsync = vsync;
+                Ok(())
+            },
+            Err(e) if vsync == VSync::Adaptive => {
+                video_subsystem.gl_set_swap_interval(SwapInterval::VSync)?;
+                self.vsync = VSync::On;
+                Err(e)
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn vsync(&self) -> VSync {
+        self.vsync
+    }
+
+    // Max frames per second. None means unlimited (or limited only by vsync)
+    pub fn set_frame_cap(&mut self, frame_cap: Option<u32>) {
+        if let Some(fps) = frame_cap {
+            assert!(fps > 0);
+        }
+        self.frame_cap = frame_cap;
+    }
+
+    pub fn frame_cap(&self) -> Option<u32> {
+        self.frame_cap
+    }
+}
+
+impl<S> App<'_, S> {
+    // Waits until the frame cap deadline. Sleeps most of the time and spins the last few ms to
+    // have a stable frame pacing
+    pub(in crate::app) fn wait_frame_cap(&self) {
+        let fps = match self.video_system.frame_cap {
+            Some(fps) => fps,
+            None => return,
+        };
+
+        let timer_subsystem = &self.sdl_context.timer_subsystem;
+
+        let frame_duration = Duration::from_micros(1_000_000 / fps as u64);
+        let deadline = self.time_system.frame_start_time() + frame_duration;
+
+        let remaining = deadline.duration_since(system_time(timer_subsystem));
+        if remaining > FRAME_PACING_SPIN_MARGIN {
+            let sleep_duration = remaining - FRAME_PACING_SPIN_MARGIN;
+            std::thread::sleep(time::Duration::from_micros(sleep_duration.as_micros()));
+        }
+
+        while system_time(timer_subsystem) < deadline {
+            std::hint::spin_loop();
+        }
+    }
 }
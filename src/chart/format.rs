@@ -0,0 +1,401 @@
+// Chart format
+//
+// Line based text format. Beats are written as <beats>[+<numerator>/<denominator>] (4+1/3 is a
+// third of a beat after beat 4), times in milliseconds (with up to 3 decimals). Comments are whole
+// lines starting with '#', since titles can have one.
+//
+// The metadata entries are optional except lanes, and a BPM on beat 0 is required. Saving a chart
+// writes a canonical form in this order: comments are dropped and the default signature 0 4/4 is
+// always written.
+
+/* Format
+
+# comment
+[metadata]
+title = Song Title
+artist = Artist
+charter = Charter
+audio = song.wav
+difficulty = Hard
+level = 7
+lanes = 4
+# song time of beat 0
+offset = 120.5
+
+[tempo]
+# <beat> <bpm>
+bpm 0 150
+bpm 32 175.5
+# <beat> <numerator>/<denominator>
+signature 0 4/4
+signature 64 7/8
+# <beat> <duration>
+stop 48 500
+
+[notes]
+# <beat> <lane>
+tap 4 0
+tap 4+1/2 2
+# <beat> <lane> <end beat>
+hold 5 1 7+1/4
+*/
+
+use std::{
+    convert::TryInto,
+    fs,
+};
+
+use crate::app::time_system::Duration;
+
+use super::{Beat, Chart, ChartMetadata, Note, NoteKind, TempoMap, TimeSignature};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Section {
+    None,
+    Metadata,
+    Tempo,
+    Notes,
+}
+
+fn format_ms(duration: Duration) -> String {
+    let micros = duration.as_micros();
+    if micros.is_multiple_of(1_000) {
+        return (micros / 1_000).to_string();
+    }
+
+    let decimals = format!("{:03}", micros % 1_000);
+    format!("{}.{}", micros / 1_000, decimals.trim_end_matches('0'))
+}
+
+fn parse_ms(value: &str) -> Result<Duration, String> {
+    match value.parse::<f64>() {
+        Ok(ms) if ms.is_finite() && ms >= 0.0 => Ok(Duration::from_micros((ms * 1_000.0).round() as u64)),
+        _ => Err(format!("invalid milliseconds '{}'", value)),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid number '{}'", value))
+}
+
+// Splits the value in exactly N tokens
+fn tokens<'a, const N: usize>(kind: &str, value: &'a str) -> Result<[&'a str; N], String> {
+    let values: Vec<&str> = value.split_whitespace().collect();
+    values.as_slice().try_into()
+        .map_err(|_| format!("'{}' expects {} values, found {}", kind, N, values.len()))
+}
+
+impl Chart {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("[chart load] could not read {}: {}", path, e))?;
+
+        Self::from_chart_str(&text)
+            .map_err(|e| format!("[chart load] {}: {}", path, e))
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_chart_string())
+            .map_err(|e| format!("[chart save] could not write {}: {}", path, e))
+    }
+
+    pub fn to_chart_string(&self) -> String {
+        let mut out = String::new();
+
+        let metadata = &self.metadata;
+        out.push_str("[metadata]\n");
+        out.push_str(&format!("title = {}\n", metadata.title));
+        out.push_str(&format!("artist = {}\n", metadata.artist));
+        out.push_str(&format!("charter = {}\n", metadata.charter));
+        out.push_str(&format!("audio = {}\n", metadata.audio));
+        out.push_str(&format!("difficulty = {}\n", metadata.difficulty));
+        out.push_str(&format!("level = {}\n", metadata.level));
+        out.push_str(&format!("lanes = {}\n", self.lanes));
+        out.push_str(&format!("offset = {}\n", format_ms(self.tempo.offset)));
+
+        out.push_str("\n[tempo]\n");
+        for change in self.tempo.bpm_changes() {
+            out.push_str(&format!("bpm {} {}\n", change.beat, change.bpm));
+        }
+        for signature in self.tempo.signatures() {
+            out.push_str(&format!("signature {} {}/{}\n", signature.beat, signature.numerator, signature.denominator));
+        }
+        for stop in self.tempo.stops() {
+            out.push_str(&format!("stop {} {}\n", stop.beat, format_ms(stop.duration)));
+        }
+
+        out.push_str("\n[notes]\n");
+        for note in self.notes() {
+            match note.kind {
+                NoteKind::Tap => out.push_str(&format!("tap {} {}\n", note.beat, note.lane)),
+                NoteKind::Hold { end } => out.push_str(&format!("hold {} {} {}\n", note.beat, note.lane, end)),
+            }
+        }
+
+        out
+    }
+
+    // Errors name the line
+    pub fn from_chart_str(text: &str) -> Result<Self, String> {
+        let mut metadata = ChartMetadata::default();
+        let mut lanes = None;
+        let mut offset = Duration::ZERO;
+
+        // Set once the first BPM is known
+        let mut tempo: Option<TempoMap> = None;
+        let mut bpm_changes = Vec::new();
+        let mut signatures = Vec::new();
+        let mut stops = Vec::new();
+
+        // Added when the lanes are known
+        let mut notes = Vec::new();
+
+        let mut section = Section::None;
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.trim();
+            let error = |message: String| format!("line {}: {}", line_number, message);
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // Section header
+            if line.starts_with('[') {
+                section = match line {
+                    "[metadata]" => Section::Metadata,
+                    "[tempo]" => Section::Tempo,
+                    "[notes]" => Section::Notes,
+                    _ => return Err(error(format!("unknown section '{}'", line))),
+                };
+                continue;
+            }
+
+            match section {
+                Section::None => return Err(error(format!("entry outside of a section '{}'", line))),
+
+                Section::Metadata => {
+                    let (key, value) = match line.find('=') {
+                        Some(split) => (line[..split].trim(), line[split + 1..].trim()),
+                        None => return Err(error(format!("expected 'key = value', found '{}'", line))),
+                    };
+
+                    match key {
+                        "title" => metadata.title = value.to_string(),
+                        "artist" => metadata.artist = value.to_string(),
+                        "charter" => metadata.charter = value.to_string(),
+                        "audio" => metadata.audio = value.to_string(),
+                        "difficulty" => metadata.difficulty = value.to_string(),
+                        "level" => metadata.level = parse_number(value).map_err(error)?,
+                        "lanes" => match parse_number(value).map_err(error)? {
+                            0 => return Err(error("a chart needs at least one lane".to_string())),
+                            count => lanes = Some(count),
+                        },
+                        "offset" => offset = parse_ms(value).map_err(error)?,
+                        _ => return Err(error(format!("unknown metadata '{}'", key))),
+                    }
+                },
+
+                Section::Tempo | Section::Notes => {
+                    let (kind, value) = match line.find(char::is_whitespace) {
+                        Some(split) => (&line[..split], line[split..].trim()),
+                        None => (line, ""),
+                    };
+
+                    match (section, kind) {
+                        (Section::Tempo, "bpm") => {
+                            let [beat, bpm] = tokens(kind, value).map_err(error)?;
+                            let beat: Beat = beat.parse().map_err(error)?;
+                            let bpm: f64 = parse_number(bpm).map_err(error)?;
+
+                            if !bpm.is_finite() || bpm <= 0.0 {
+                                return Err(error(format!("invalid bpm {}", bpm)));
+                            }
+                            bpm_changes.push((line_number, beat, bpm));
+                        },
+                        (Section::Tempo, "signature") => {
+                            let [beat, signature] = tokens(kind, value).map_err(error)?;
+                            let beat: Beat = beat.parse().map_err(error)?;
+                            let invalid = || error(format!("invalid signature '{}'", signature));
+
+                            let split = signature.find('/').ok_or_else(invalid)?;
+                            let numerator: u32 = signature[..split].parse().map_err(|_| invalid())?;
+                            let denominator: u32 = signature[split + 1..].parse().map_err(|_| invalid())?;
+
+                            if !TimeSignature::is_valid(numerator, denominator) {
+                                return Err(invalid());
+                            }
+                            signatures.push((line_number, beat, numerator, denominator));
+                        },
+                        (Section::Tempo, "stop") => {
+                            let [beat, duration] = tokens(kind, value).map_err(error)?;
+                            let beat: Beat = beat.parse().map_err(error)?;
+                            stops.push((line_number, beat, parse_ms(duration).map_err(error)?));
+                        },
+                        (Section::Notes, "tap") => {
+                            let [beat, lane] = tokens(kind, value).map_err(error)?;
+                            let note = Note::tap(beat.parse().map_err(error)?, parse_number(lane).map_err(error)?);
+                            notes.push((line_number, note));
+                        },
+                        (Section::Notes, "hold") => {
+                            let [beat, lane, end] = tokens(kind, value).map_err(error)?;
+                            let note = Note::hold(
+                                beat.parse().map_err(error)?,
+                                parse_number(lane).map_err(error)?,
+                                end.parse().map_err(error)?,
+                            );
+                            notes.push((line_number, note));
+                        },
+                        _ => return Err(error(format!("unknown entry '{}'", kind))),
+                    }
+                },
+            }
+        }
+
+        // Tempo map
+        for (line_number, beat, bpm) in bpm_changes {
+            let error = |message: String| format!("line {}: {}", line_number, message);
+            match tempo.as_mut() {
+                Some(tempo) => {
+                    if tempo.bpm_changes().iter().any(|change| change.beat == beat) {
+                        return Err(error(format!("repeated bpm on beat {}", beat)));
+                    }
+                    tempo.set_bpm(beat, bpm);
+                },
+                None if beat == Beat::ZERO => tempo = Some(TempoMap::new(bpm)),
+                None => return Err(error(format!("the first bpm is on beat {} instead of 0", beat))),
+            }
+        }
+
+        let mut tempo = tempo.ok_or_else(|| "missing bpm".to_string())?;
+        tempo.offset = offset;
+
+        let mut signature_beats = Vec::new();
+        for (line_number, beat, numerator, denominator) in signatures {
+            if signature_beats.contains(&beat) {
+                return Err(format!("line {}: repeated signature on beat {}", line_number, beat));
+            }
+            signature_beats.push(beat);
+            tempo.set_signature(beat, numerator, denominator);
+        }
+
+        for (line_number, beat, duration) in stops {
+            if tempo.stops().iter().any(|stop| stop.beat == beat) {
+                return Err(format!("line {}: repeated stop on beat {}", line_number, beat));
+            }
+            tempo.add_stop(beat, duration);
+        }
+
+        // Notes
+        let lanes = lanes.ok_or_else(|| "missing lanes".to_string())?;
+        let mut chart = Chart::new(lanes, 1.0);
+        chart.metadata = metadata;
+        chart.tempo = tempo;
+
+        for (line_number, note) in notes {
+            chart.add_note(note).map_err(|e| format!("line {}: {}", line_number, e))?;
+        }
+
+        Ok(chart)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART: &str = "\
+[metadata]
+title = Song #1
+artist = Artist
+charter = Charter
+audio = song.wav
+difficulty = Hard
+level = 7
+lanes = 4
+offset = 120.5
+
+[tempo]
+bpm 0 150
+bpm 32 175.5
+signature 0 4/4
+signature 64 7/8
+stop 48 500
+
+[notes]
+tap 4 0
+tap 4+1/2 2
+hold 5 1 7+1/4
+tap 5+1/3 3
+";
+
+    #[test]
+    fn test_chart_format_roundtrip() {
+        let chart = Chart::from_chart_str(CHART).unwrap();
+
+        assert_eq!(chart.metadata.title, "Song #1");
+        assert_eq!(chart.metadata.level, 7);
+        assert_eq!(chart.lanes(), 4);
+        assert_eq!(chart.tempo.offset, Duration::from_micros(120_500));
+        assert_eq!(chart.tempo.bpm_at("40".parse().unwrap()), 175.5);
+        assert_eq!(chart.tempo.stops()[0].duration, Duration::from_millis(500));
+        assert_eq!(chart.notes().len(), 4);
+        assert_eq!(chart.notes()[2], Note::hold("5".parse().unwrap(), 1, "7+1/4".parse().unwrap()));
+
+        // stable output
+        assert_eq!(chart.to_chart_string(), CHART);
+
+        // comments, sections in any order, defaults
+        let text = "# generated\n[notes]\ntap 1 1\n[tempo]\nbpm 0 120\n[metadata]\nlanes = 2\n";
+        let chart = Chart::from_chart_str(text).unwrap();
+        assert_eq!(chart.metadata, ChartMetadata::default());
+        assert_eq!(chart.note_time(&chart.notes()[0]), Duration::from_millis(500));
+        assert_eq!(Chart::from_chart_str(&chart.to_chart_string()), Ok(chart));
+    }
+
+    #[test]
+    fn test_chart_format_example() {
+        // the format block at the top of this file
+        let source = include_str!("format.rs");
+        let start = source.find("/* Format").unwrap() + "/* Format".len();
+        let end = start + source[start..].find("*/").unwrap();
+
+        let chart = Chart::from_chart_str(&source[start..end]).unwrap();
+        assert_eq!(chart.notes().len(), 3);
+        assert_eq!(chart.tempo.stops().len(), 1);
+    }
+
+    #[test]
+    fn test_chart_format_errors() {
+        let error = |text: &str| Chart::from_chart_str(text).err().unwrap();
+        let header = "[metadata]\nlanes = 4\n[tempo]\nbpm 0 120\n";
+
+        assert_eq!(error("[metadata]\nlanes = 4\n"), "missing bpm");
+        assert_eq!(error("[tempo]\nbpm 0 120\n"), "missing lanes");
+        assert_eq!(error("[tempo]\nbpm 4 120\n"), "line 2: the first bpm is on beat 4 instead of 0");
+        assert_eq!(error("[tempo]\nbpm 0 120\nbpm 0 130\n"), "line 3: repeated bpm on beat 0");
+        assert_eq!(error("[tempo]\nbpm 0 -1\n"), "line 2: invalid bpm -1");
+        assert_eq!(error("[tempo]\nsignature 0 4/3\n"), "line 2: invalid signature '4/3'");
+        assert_eq!(error("[tempo]\nsignature 0 1/8192\n"), "line 2: invalid signature '1/8192'");
+        assert_eq!(error("[tempo]\nsignature 0 7/1024\n"), "line 2: invalid signature '7/1024'");
+        assert_eq!(error("[tempo]\nstop 1\n"), "line 2: 'stop' expects 2 values, found 1");
+        assert_eq!(error("title = Song\n"), "line 1: entry outside of a section 'title = Song'");
+        assert_eq!(error("[sounds]\n"), "line 1: unknown section '[sounds]'");
+        assert_eq!(error("[metadata]\nbpm = 120\n"), "line 2: unknown metadata 'bpm'");
+
+        assert_eq!(
+            error(&format!("{}[notes]\ntap 1+1/7 0\n", header)),
+            "line 6: beat '1+1/7' is not a multiple of 1/960 beat"
+        );
+        assert_eq!(
+            error(&format!("{}[notes]\nhold 1 0 3\ntap 2 0\n", header)),
+            "line 7: note at beat 2 overlaps the note at beat 1 on lane 0"
+        );
+        assert_eq!(
+            error(&format!("{}[notes]\ntap 1 4\n", header)),
+            "line 6: lane 4 out of range (the chart has 4 lanes)"
+        );
+        assert_eq!(error(&format!("{}[notes]\nslide 1 0\n", header)), "line 6: unknown entry 'slide'");
+    }
+}
@@ -0,0 +1,173 @@
+// Chart
+//
+// A chart (beatmap) is the playable data of a song: metadata, the tempo map and the notes. Notes
+// are placed on beats, so the charts keep working when the tempo map is adjusted, and converted to
+// song time (the time of the song clock) to judge and draw them. Charts are saved in a text format
+// (see format.rs).
+
+/* Usage
+
+let chart = Chart::load("assets/songs/song/hard.chart")?;
+let music = app.play_music(&chart.metadata.audio, VoiceParams::new(AudioBus::Music))?;
+let song_clock = app.create_song_clock("song", music);
+
+// update
+
+let song_time = app.song_time(song_clock);
+for note in chart.notes_between(self.last_song_time, song_time) {
+    // ...
+}
+self.last_song_time = song_time;
+
+// render: scroll by the beat so stops and BPM changes show
+
+let beat = chart.tempo.beat_at(app.visual_song_time(song_clock));
+for note in chart.notes() {
+    let y = TARGET_Y - (note.beat.as_f64() - beat) as f32 * BEAT_HEIGHT;
+}
+*/
+
+pub mod format;
+pub mod note;
+pub mod tempo;
+
+pub use note::*;
+pub use tempo::*;
+
+use crate::app::{
+    imdraw::ImDraw,
+    time_system::Duration,
+};
+
+#[derive(Clone, Debug, Default, PartialEq, ImDraw)]
+pub struct ChartMetadata {
+    pub title: String,
+    pub artist: String,
+    pub charter: String,
+    // Path of the music, relative to the chart
+    pub audio: String,
+    pub difficulty: String,
+    pub level: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, ImDraw)]
+pub struct Chart {
+    pub metadata: ChartMetadata,
+    pub tempo: TempoMap,
+
+    lanes: u8,
+    // Sorted by beat and lane
+    notes: Vec<Note>,
+}
+
+impl Chart {
+    pub fn new(lanes: u8, bpm: f64) -> Self {
+        assert!(lanes > 0, "[chart new] no lanes");
+
+        Self {
+            metadata: ChartMetadata::default(),
+            tempo: TempoMap::new(bpm),
+            lanes,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn lanes(&self) -> u8 {
+        self.lanes
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        &self.notes
+    }
+
+    // Keeps the notes sorted. Fails if the lane doesn't exist, the hold ends before it starts or
+    // the note overlaps another one
+    pub fn add_note(&mut self, note: Note) -> Result<(), String> {
+        if note.lane >= self.lanes {
+            return Err(format!("lane {} out of range (the chart has {} lanes)", note.lane, self.lanes));
+        }
+
+        if note.end_beat() < note.beat || (note.end_beat() == note.beat && note.kind != NoteKind::Tap) {
+            return Err(format!("hold at beat {} ends at beat {}", note.beat, note.end_beat()));
+        }
+
+        if let Some(other) = self.notes.iter().find(|other| other.overlaps(&note)) {
+            return Err(format!(
+                "note at beat {} overlaps the note at beat {} on lane {}", note.beat, other.beat, note.lane
+            ));
+        }
+
+        let index = self.notes.partition_point(|other| (other.beat, other.lane) < (note.beat, note.lane));
+        self.notes.insert(index, note);
+        Ok(())
+    }
+
+    pub fn remove_note(&mut self, beat: Beat, lane: u8) -> Option<Note> {
+        let index = self.notes.iter().position(|note| note.beat == beat && note.lane == lane)?;
+        Some(self.notes.remove(index))
+    }
+
+    pub fn note_time(&self, note: &Note) -> Duration {
+        self.tempo.time_at(note.beat)
+    }
+
+    pub fn note_end_time(&self, note: &Note) -> Duration {
+        self.tempo.time_at(note.end_beat())
+    }
+
+    // Notes starting in [from, to)
+    pub fn notes_between(&self, from: Duration, to: Duration) -> &[Note] {
+        // The time only grows with the beat
+        let start = self.notes.partition_point(|note| self.note_time(note) < from);
+        let end = self.notes.partition_point(|note| self.note_time(note) < to);
+        &self.notes[start..end.max(start)]
+    }
+
+    // When the last note ends
+    pub fn duration(&self) -> Duration {
+        self.notes.iter()
+            .map(|note| self.note_end_time(note))
+            .max()
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beat(text: &str) -> Beat {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_chart_notes() {
+        let mut chart = Chart::new(4, 120.0);
+
+        chart.add_note(Note::tap(beat("2"), 1)).unwrap();
+        chart.add_note(Note::hold(beat("1"), 1, beat("1+1/2"))).unwrap();
+        chart.add_note(Note::tap(beat("1"), 0)).unwrap();
+        chart.add_note(Note::tap(beat("3"), 3)).unwrap();
+
+        let order: Vec<(String, u8)> = chart.notes().iter().map(|note| (note.beat.to_string(), note.lane)).collect();
+        assert_eq!(order, [("1".to_string(), 0), ("1".to_string(), 1), ("2".to_string(), 1), ("3".to_string(), 3)]);
+
+        assert_eq!(
+            chart.add_note(Note::tap(beat("1+1/2"), 1)),
+            Err("note at beat 1+1/2 overlaps the note at beat 1 on lane 1".to_string())
+        );
+        assert!(chart.add_note(Note::tap(beat("1"), 4)).is_err());
+        assert!(chart.add_note(Note::hold(beat("5"), 0, beat("5"))).is_err());
+
+        // 500ms per beat
+        let times: Vec<u64> = chart.notes_between(Duration::from_millis(500), Duration::from_millis(1_500))
+            .iter()
+            .map(|note| chart.note_time(note).as_millis())
+            .collect();
+        assert_eq!(times, [500, 500, 1_000]);
+        assert_eq!(chart.duration(), Duration::from_millis(1_500));
+
+        assert_eq!(chart.remove_note(beat("3"), 3), Some(Note::tap(beat("3"), 3)));
+        assert_eq!(chart.duration(), Duration::from_millis(1_000));
+    }
+}
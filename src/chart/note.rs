@@ -0,0 +1,44 @@
+// Notes
+
+use crate::app::imdraw::ImDraw;
+
+use super::tempo::Beat;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NoteKind {
+    Tap,
+    // Held until the end beat
+    Hold { end: Beat },
+}
+
+impl_imdraw_todo!(NoteKind);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ImDraw)]
+pub struct Note {
+    pub beat: Beat,
+    pub lane: u8,
+    pub kind: NoteKind,
+}
+
+impl Note {
+    pub fn tap(beat: Beat, lane: u8) -> Self {
+        Self { beat, lane, kind: NoteKind::Tap }
+    }
+
+    pub fn hold(beat: Beat, lane: u8, end: Beat) -> Self {
+        Self { beat, lane, kind: NoteKind::Hold { end } }
+    }
+
+    // Same as the beat for taps
+    pub fn end_beat(&self) -> Beat {
+        match self.kind {
+            NoteKind::Tap => self.beat,
+            NoteKind::Hold { end } => end,
+        }
+    }
+
+    // Notes on the same lane can't overlap, including the ends of holds
+    pub fn overlaps(&self, other: &Note) -> bool {
+        self.lane == other.lane && self.beat <= other.end_beat() && other.beat <= self.end_beat()
+    }
+}
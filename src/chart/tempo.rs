@@ -0,0 +1,390 @@
+// Tempo map
+//
+// Positions in a chart are beats (quarter notes), stored as ticks so they can be compared and
+// written exactly. The tempo map converts them to song time: BPM changes, stops (the time goes on
+// but the beat doesn't) and the offset of the first beat. Time signatures only place the measure
+// lines.
+
+use std::fmt;
+use std::ops::{Add, Sub};
+use std::str::FromStr;
+
+use crate::app::{
+    imdraw::ImDraw,
+    time_system::Duration,
+};
+
+// Ticks per beat. Divisible by 2, 3, 4, 5, 6, 8, 10, 12, 15, 16, 20, 24, 32, 48, 64 (like MIDI PPQ)
+pub const BEAT_TICKS: u64 = 960;
+
+const MICROS_PER_MINUTE: f64 = 60_000_000.0;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, ImDraw)]
+pub struct Beat(u64);
+
+impl Beat {
+    pub const ZERO: Beat = Beat(0);
+
+    pub const fn from_ticks(ticks: u64) -> Self { Self(ticks) }
+    pub const fn from_beats(beats: u64) -> Self { Self(beats * BEAT_TICKS) }
+
+    // beats + numerator/denominator of a beat. None if it's not a whole number of ticks or overflows
+    pub fn from_fraction(beats: u64, numerator: u64, denominator: u64) -> Option<Self> {
+        let fraction_ticks = numerator.checked_mul(BEAT_TICKS)?;
+        if denominator == 0 || !fraction_ticks.is_multiple_of(denominator) {
+            return None;
+        }
+
+        beats.checked_mul(BEAT_TICKS)?.checked_add(fraction_ticks / denominator).map(Self)
+    }
+
+    pub const fn as_ticks(self) -> u64 { self.0 }
+    pub fn as_f64(self) -> f64 { self.0 as f64 / BEAT_TICKS as f64 }
+}
+
+impl Add for Beat {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output { Self(self.0 + rhs.0) }
+}
+
+impl Sub for Beat {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output { Self(self.0 - rhs.0) }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// <beats>[+<numerator>/<denominator>], with the fraction reduced
+impl fmt::Display for Beat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let beats = self.0 / BEAT_TICKS;
+        let ticks = self.0 % BEAT_TICKS;
+
+        if ticks == 0 {
+            write!(f, "{}", beats)
+        } else {
+            let divisor = gcd(ticks, BEAT_TICKS);
+            write!(f, "{}+{}/{}", beats, ticks / divisor, BEAT_TICKS / divisor)
+        }
+    }
+}
+
+impl FromStr for Beat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid beat '{}'", text);
+        let parse = |value: &str| value.parse::<u64>().map_err(|_| invalid());
+
+        let (beats, fraction) = match text.find('+') {
+            Some(split) => (parse(&text[..split])?, Some(&text[split + 1..])),
+            None => (parse(text)?, None),
+        };
+
+        match fraction {
+            Some(fraction) => {
+                let split = fraction.find('/').ok_or_else(invalid)?;
+                let numerator = parse(&fraction[..split])?;
+                let denominator = parse(&fraction[split + 1..])?;
+
+                let fraction_ticks = numerator.checked_mul(BEAT_TICKS).ok_or_else(invalid)?;
+                if denominator != 0 && !fraction_ticks.is_multiple_of(denominator) {
+                    return Err(format!("beat '{}' is not a multiple of 1/{} beat", text, BEAT_TICKS));
+                }
+
+                // Zero denominator or overflow
+                Beat::from_fraction(beats, numerator, denominator).ok_or_else(invalid)
+            },
+            None => beats.checked_mul(BEAT_TICKS).map(Beat::from_ticks).ok_or_else(invalid),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, ImDraw)]
+pub struct BpmChange {
+    pub beat: Beat,
+    pub bpm: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ImDraw)]
+pub struct TimeSignature {
+    // Measures start again here
+    pub beat: Beat,
+    pub numerator: u32,
+    pub denominator: u32,
+}
+
+impl TimeSignature {
+    // Measures must be a whole number of ticks
+    pub fn is_valid(numerator: u32, denominator: u32) -> bool {
+        numerator > 0
+            && denominator.is_power_of_two()
+            && (numerator as u64 * BEAT_TICKS * 4).is_multiple_of(denominator as u64)
+    }
+
+    pub fn measure_ticks(&self) -> u64 {
+        self.numerator as u64 * BEAT_TICKS * 4 / self.denominator as u64
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ImDraw)]
+pub struct Stop {
+    // Notes on this beat are hit before the stop
+    pub beat: Beat,
+    pub duration: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, ImDraw)]
+pub struct TempoMap {
+    // Song time of beat 0
+    pub offset: Duration,
+
+    // Sorted by beat, at most one per beat. The first BPM change and time signature are on beat 0
+    bpm_changes: Vec<BpmChange>,
+    signatures: Vec<TimeSignature>,
+    stops: Vec<Stop>,
+}
+
+// Replaces the item on the same beat
+fn insert_by_beat<T>(items: &mut Vec<T>, item: T, beat: fn(&T) -> Beat) {
+    match items.binary_search_by_key(&beat(&item), beat) {
+        Ok(index) => items[index] = item,
+        Err(index) => items.insert(index, item),
+    }
+}
+
+impl TempoMap {
+    // 4/4
+    pub fn new(bpm: f64) -> Self {
+        let mut tempo_map = Self {
+            offset: Duration::ZERO,
+            bpm_changes: Vec::new(),
+            signatures: Vec::new(),
+            stops: Vec::new(),
+        };
+
+        tempo_map.set_bpm(Beat::ZERO, bpm);
+        tempo_map.set_signature(Beat::ZERO, 4, 4);
+        tempo_map
+    }
+
+    pub fn set_bpm(&mut self, beat: Beat, bpm: f64) {
+        assert!(bpm.is_finite() && bpm > 0.0, "[tempo_map set_bpm] invalid bpm {}", bpm);
+        insert_by_beat(&mut self.bpm_changes, BpmChange { beat, bpm }, |change| change.beat);
+    }
+
+    pub fn set_signature(&mut self, beat: Beat, numerator: u32, denominator: u32) {
+        assert!(
+            TimeSignature::is_valid(numerator, denominator),
+            "[tempo_map set_signature] invalid signature {}/{}", numerator, denominator
+        );
+        let signature = TimeSignature { beat, numerator, denominator };
+        insert_by_beat(&mut self.signatures, signature, |signature| signature.beat);
+    }
+
+    pub fn add_stop(&mut self, beat: Beat, duration: Duration) {
+        insert_by_beat(&mut self.stops, Stop { beat, duration }, |stop| stop.beat);
+    }
+
+    // The ones on beat 0 can be replaced but not removed
+    pub fn remove_bpm_change(&mut self, beat: Beat) {
+        if beat != Beat::ZERO {
+            self.bpm_changes.retain(|change| change.beat != beat);
+        }
+    }
+
+    pub fn remove_signature(&mut self, beat: Beat) {
+        if beat != Beat::ZERO {
+            self.signatures.retain(|signature| signature.beat != beat);
+        }
+    }
+
+    pub fn remove_stop(&mut self, beat: Beat) {
+        self.stops.retain(|stop| stop.beat != beat);
+    }
+
+    pub fn bpm_changes(&self) -> &[BpmChange] {
+        &self.bpm_changes
+    }
+
+    pub fn signatures(&self) -> &[TimeSignature] {
+        &self.signatures
+    }
+
+    pub fn stops(&self) -> &[Stop] {
+        &self.stops
+    }
+
+    pub fn bpm_at(&self, beat: Beat) -> f64 {
+        self.bpm_changes.iter().rev().find(|change| change.beat <= beat).unwrap().bpm
+    }
+
+    pub fn signature_at(&self, beat: Beat) -> TimeSignature {
+        *self.signatures.iter().rev().find(|signature| signature.beat <= beat).unwrap()
+    }
+
+    pub fn time_at(&self, beat: Beat) -> Duration {
+        let mut micros = self.offset.as_micros() as f64;
+
+        for (index, change) in self.bpm_changes.iter().enumerate() {
+            if change.beat >= beat {
+                break;
+            }
+
+            let segment_end = match self.bpm_changes.get(index + 1) {
+                Some(next) => next.beat.min(beat),
+                None => beat,
+            };
+            micros += ticks_to_micros((segment_end - change.beat).as_ticks() as f64, change.bpm);
+        }
+
+        for stop in self.stops.iter().take_while(|stop| stop.beat < beat) {
+            micros += stop.duration.as_micros() as f64;
+        }
+
+        Duration::from_micros(micros.round() as u64)
+    }
+
+    // Fractional beat at the song time, for scrolling. Negative before the offset
+    pub fn beat_at(&self, time: Duration) -> f64 {
+        let mut micros_left = time.as_micros() as f64 - self.offset.as_micros() as f64;
+        let mut ticks = 0.0;
+
+        let mut bpm_changes = self.bpm_changes.iter().skip(1).peekable();
+        let mut stops = self.stops.iter().peekable();
+        let mut bpm = self.bpm_changes[0].bpm;
+
+        loop {
+            let next_bpm_ticks = bpm_changes.peek().map(|change| change.beat.as_ticks() as f64);
+            let next_stop_ticks = stops.peek().map(|stop| stop.beat.as_ticks() as f64);
+            let next_ticks = match (next_bpm_ticks, next_stop_ticks) {
+                (Some(a), Some(b)) => a.min(b),
+                (a, b) => match a.or(b) {
+                    Some(next_ticks) => next_ticks,
+                    None => break,
+                },
+            };
+
+            let segment_micros = ticks_to_micros(next_ticks - ticks, bpm);
+            if micros_left <= segment_micros {
+                break;
+            }
+
+            micros_left -= segment_micros;
+            ticks = next_ticks;
+
+            if next_stop_ticks == Some(next_ticks) {
+                let stop = stops.next().unwrap();
+                if micros_left <= stop.duration.as_micros() as f64 {
+                    return ticks / BEAT_TICKS as f64;
+                }
+                micros_left -= stop.duration.as_micros() as f64;
+            }
+
+            if next_bpm_ticks == Some(next_ticks) {
+                bpm = bpm_changes.next().unwrap().bpm;
+            }
+        }
+
+        (ticks + micros_left * bpm * BEAT_TICKS as f64 / MICROS_PER_MINUTE) / BEAT_TICKS as f64
+    }
+
+    // Measure starts before the beat, for the measure lines
+    pub fn measure_starts(&self, until: Beat) -> Vec<Beat> {
+        let mut starts = Vec::new();
+
+        for (index, signature) in self.signatures.iter().enumerate() {
+            let end = match self.signatures.get(index + 1) {
+                Some(next) => next.beat.min(until),
+                None => until,
+            };
+
+            let mut beat = signature.beat;
+            while beat < end {
+                starts.push(beat);
+                beat = beat + Beat::from_ticks(signature.measure_ticks());
+            }
+        }
+
+        starts
+    }
+}
+
+fn ticks_to_micros(ticks: f64, bpm: f64) -> f64 {
+    ticks * MICROS_PER_MINUTE / (bpm * BEAT_TICKS as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn beat(text: &str) -> Beat {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn test_beat_text() {
+        assert_eq!(beat("4+1/3"), Beat::from_ticks(4 * BEAT_TICKS + 320));
+        assert_eq!(beat("4+2/6").to_string(), "4+1/3");
+        assert_eq!(beat("12").to_string(), "12");
+        assert_eq!(beat("0+5/4"), beat("1+1/4"));
+
+        assert_eq!("1+1/7".parse::<Beat>(), Err("beat '1+1/7' is not a multiple of 1/960 beat".to_string()));
+        assert_eq!("1.5".parse::<Beat>(), Err("invalid beat '1.5'".to_string()));
+        assert!("1+1".parse::<Beat>().is_err());
+        assert!("1+1/0".parse::<Beat>().is_err());
+
+        // overflow
+        assert_eq!("100000000000000000".parse::<Beat>(), Err("invalid beat '100000000000000000'".to_string()));
+        assert!("1+100000000000000000/2".parse::<Beat>().is_err());
+        assert!("19215358410114116+1/1".parse::<Beat>().is_err());
+    }
+
+    #[test]
+    fn test_tempo_map_time() {
+        let mut tempo_map = TempoMap::new(120.0);
+        tempo_map.offset = Duration::from_millis(100);
+        tempo_map.set_bpm(beat("8"), 60.0);
+        tempo_map.add_stop(beat("4"), Duration::from_millis(250));
+
+        assert_eq!(tempo_map.time_at(Beat::ZERO), Duration::from_millis(100));
+        assert_eq!(tempo_map.time_at(beat("4")), Duration::from_millis(2_100));
+        assert_eq!(tempo_map.time_at(beat("4+1/2")), Duration::from_millis(2_600));
+        assert_eq!(tempo_map.time_at(beat("8")), Duration::from_millis(4_350));
+        assert_eq!(tempo_map.time_at(beat("9")), Duration::from_millis(5_350));
+        assert_eq!(tempo_map.bpm_at(beat("8")), 60.0);
+
+        let assert_beat = |time: u64, expected: f64| {
+            let beat = tempo_map.beat_at(Duration::from_millis(time));
+            assert!((beat - expected).abs() < 1e-9, "beat_at({}ms) = {}, expected {}", time, beat, expected);
+        };
+        assert_beat(0, -0.2);
+        assert_beat(1_100, 2.0);
+        // during the stop
+        assert_beat(2_200, 4.0);
+        assert_beat(2_600, 4.5);
+        assert_beat(5_350, 9.0);
+    }
+
+    #[test]
+    fn test_tempo_map_measures() {
+        let mut tempo_map = TempoMap::new(150.0);
+        tempo_map.set_signature(beat("8"), 3, 4);
+        tempo_map.set_signature(beat("12"), 7, 8);
+
+        let starts: Vec<String> = tempo_map.measure_starts(beat("20")).iter().map(Beat::to_string).collect();
+        assert_eq!(starts, ["0", "4", "8", "11", "12", "15+1/2", "19"]);
+
+        assert_eq!(tempo_map.signature_at(beat("13")).numerator, 7);
+        tempo_map.remove_signature(beat("12"));
+        assert_eq!(tempo_map.signature_at(beat("13")).numerator, 3);
+
+        // measures of whole ticks only
+        assert!(TimeSignature::is_valid(1, 256));
+        assert!(TimeSignature::is_valid(5, 64));
+        assert!(!TimeSignature::is_valid(1, 512));
+        assert!(!TimeSignature::is_valid(0, 4));
+    }
+}
@@ -77,7 +77,7 @@ self.entity_containers.destroy(self.entity_id);
 // update
 if let Some(my_entity) = self.entity_containers.get_mut(self.entity_id) {
     my_entity.entity_mut().transform.pos +=
-        100.0 * app.last_frame_duration() * move_direction;
+        100.0 * app.last_frame_duration().as_secs_f32() * move_direction;
 }
 
 // render
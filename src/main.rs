@@ -9,6 +9,7 @@ extern crate imgui;
 extern crate imgui_opengl_renderer;
 
 #[macro_use] mod app;
+mod chart;
 //mod entities;
 mod linalg;
 
@@ -68,9 +69,8 @@ impl GameState for State {
         {
             let mut button = Button::new();
             button.add_key(sdl2::keyboard::Scancode::W);
-            button.add_controller_button(0, sdl2::controller::Button::DPadUp);
+            button.add_controller_button(sdl2::controller::Button::DPadUp);
             button.add_controller_axis(
-                0,
                 sdl2::controller::Axis::LeftY,
                 ControllerAxisThreshold::lesser_than(-0.5)
             );
@@ -81,9 +81,8 @@ impl GameState for State {
         {
             let mut button = Button::new();
             button.add_key(sdl2::keyboard::Scancode::S);
-            button.add_controller_button(0, sdl2::controller::Button::DPadDown);
+            button.add_controller_button(sdl2::controller::Button::DPadDown);
             button.add_controller_axis(
-                0,
                 sdl2::controller::Axis::LeftY,
                 ControllerAxisThreshold::greater_than(0.5)
             );
@@ -94,9 +93,8 @@ impl GameState for State {
         {
             let mut button = Button::new();
             button.add_key(sdl2::keyboard::Scancode::D);
-            button.add_controller_button(0, sdl2::controller::Button::DPadRight);
+            button.add_controller_button(sdl2::controller::Button::DPadRight);
             button.add_controller_axis(
-                0,
                 sdl2::controller::Axis::LeftX,
                 ControllerAxisThreshold::greater_than(0.5)
             );
@@ -107,9 +105,8 @@ impl GameState for State {
         {
             let mut button = Button::new();
             button.add_key(sdl2::keyboard::Scancode::A);
-            button.add_controller_button(0, sdl2::controller::Button::DPadLeft);
+            button.add_controller_button(sdl2::controller::Button::DPadLeft);
             button.add_controller_axis(
-                0,
                 sdl2::controller::Axis::LeftX,
                 ControllerAxisThreshold::lesser_than(-0.5)
             );
@@ -117,6 +114,21 @@ impl GameState for State {
             input_mapping.add_button_mapping("LEFT".to_string(), button);
         }
 
+        {
+            let mut horizontal = Axis::new();
+            horizontal.add_keys(sdl2::keyboard::Scancode::A, sdl2::keyboard::Scancode::D);
+            horizontal.add_controller_axis(sdl2::controller::Axis::LeftX);
+
+            let mut vertical = Axis::new();
+            vertical.add_keys(sdl2::keyboard::Scancode::W, sdl2::keyboard::Scancode::S);
+            vertical.add_controller_axis(sdl2::controller::Axis::LeftY);
+
+            let mut axis = Axis2D::new(horizontal, vertical);
+            axis.settings.dead_zone = 0.2;
+
+            input_mapping.add_axis_2d_mapping("MOVE".to_string(), axis);
+        }
+
         Self {
             input_mapping,
             font,